@@ -0,0 +1,154 @@
+//! Durations regressions against a recorded baseline.
+//!
+//! The baseline is the run summary of a previous passing run,
+//! saved to the `--duration-baseline` file when it does not exist yet.
+//! Later runs compare their durations with it, and fail when the total duration
+//! regresses by more than `--max-regression` percent.
+//! Regressions of individual tests are only reported since they are noisier.
+
+use anyhow::Context;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::summary::RunSummary;
+
+/// Tests faster than this number of milliseconds in the baseline are not compared,
+/// since their durations are mostly noise.
+const MIN_TEST_DURATION: f64 = 10.0;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Run summary of the baseline.
+    pub file: PathBuf,
+    /// Allowed regression of the total duration, in percent.
+    pub max_regression: f64,
+}
+
+/// Compare the durations of a run with the baseline, or record the baseline if there is none.
+/// Returns false if the total duration regressed more than allowed.
+pub fn check(summary: &RunSummary, options: &Options) -> anyhow::Result<bool> {
+    if !options.file.exists() {
+        if summary.exit_code == 0 {
+            std::fs::write(&options.file, summary.to_json().to_string())
+                .context(format!("Failed to write {}", options.file.display()))?;
+            log::error!(
+                "Recorded the durations baseline in {}",
+                options.file.display()
+            );
+        }
+        return Ok(true);
+    }
+    let baseline = RunSummary::read(&options.file)?;
+
+    let regressions = test_regressions(&baseline, summary, options.max_regression);
+    if !regressions.is_empty() {
+        log::error!("\nTests slower than in the baseline:\n");
+        for (name, before, after) in regressions.iter() {
+            log::error!(
+                "{:>8} ms -> {:>8} ms {:>+7.1} %  {}",
+                before.round(),
+                after.round(),
+                regression(*before, *after),
+                name
+            );
+        }
+    }
+    let (before, after) = (baseline.duration(), summary.duration());
+    let total_regression = regression(before, after);
+    log::error!(
+        "\nTotal duration of tests: {} ms, {:+.1} % compared to the baseline ({} ms)\n",
+        after.round(),
+        total_regression,
+        before.round()
+    );
+    if total_regression > options.max_regression {
+        log::error!(
+            "The total duration regressed by more than the allowed {} %\n",
+            options.max_regression
+        );
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Tests slower than in the baseline by more than the allowed regression,
+/// with their baseline and current durations, the biggest regression first.
+pub fn test_regressions(
+    baseline: &RunSummary,
+    summary: &RunSummary,
+    max_regression: f64,
+) -> Vec<(String, f64, f64)> {
+    let baseline_durations: HashMap<String, f64> = baseline
+        .tests
+        .iter()
+        .map(|test| (test.name(), test.duration))
+        .collect();
+    let mut regressions: Vec<(String, f64, f64)> = summary
+        .tests
+        .iter()
+        .filter_map(|test| {
+            let name = test.name();
+            let before = *baseline_durations.get(&name)?;
+            if before >= MIN_TEST_DURATION && regression(before, test.duration) > max_regression {
+                Some((name, before, test.duration))
+            } else {
+                None
+            }
+        })
+        .collect();
+    regressions.sort_by(|a, b| {
+        regression(b.1, b.2)
+            .partial_cmp(&regression(a.1, a.2))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    regressions
+}
+
+/// Regression in percent of a duration compared to its baseline.
+fn regression(before: f64, after: f64) -> f64 {
+    if before > 0.0 {
+        100.0 * (after - before) / before
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_regressions;
+    use crate::summary::{RunSummary, Status, TestOutcome};
+
+    #[test]
+    fn slower_tests() {
+        let summary = |durations: &[(&str, f64)]| RunSummary {
+            exit_code: 0,
+            seed: 0,
+            fuzz_runs: 100,
+            tests: durations
+                .iter()
+                .map(|(name, duration)| TestOutcome {
+                    labels: vec![name.to_string()],
+                    module: None,
+                    status: Status::Pass,
+                    duration: *duration,
+                    heap_growth: None,
+                })
+                .collect(),
+        };
+        let baseline = summary(&[("a", 100.0), ("b", 100.0), ("c", 2.0), ("d", 50.0)]);
+        let current = summary(&[
+            ("a", 110.0),
+            ("b", 300.0),
+            ("c", 20.0),
+            ("d", 100.0),
+            ("e", 1e3),
+        ]);
+        assert_eq!(
+            test_regressions(&baseline, &current, 20.0),
+            vec![
+                ("b".to_string(), 100.0, 300.0),
+                ("d".to_string(), 50.0, 100.0),
+            ]
+        );
+    }
+}
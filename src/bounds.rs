@@ -0,0 +1,81 @@
+//! Check the dependency bounds of a package, with `--dependencies bounds`.
+//!
+//! The tests dependencies are solved twice, once with the oldest versions
+//! allowed by the package elm.json and once with the newest ones,
+//! and the tests are run with both.
+//! This helps package authors verify that their declared lower bounds actually work.
+
+use pubgrub_dependency_provider_elm::dependency_provider::VersionStrategy;
+use pubgrub_dependency_provider_elm::project_config::ProjectConfig;
+use std::path::Path;
+
+use crate::deps::ConnectivityStrategy;
+use crate::project::Project;
+
+/// Run the tests with the oldest and the newest allowed dependencies, with `--dependencies bounds`.
+/// Returns the highest exit code of both runs.
+pub fn main_helper(
+    elm_home: &Path,
+    project: &Project,
+    make_options: &crate::make::Options,
+    run_options: &crate::run::Options,
+) -> anyhow::Result<i32> {
+    if let ProjectConfig::Application(_) = project.config {
+        anyhow::bail!("Applications have exact dependencies, so checking dependency bounds only makes sense for packages");
+    }
+
+    let mut outcomes = Vec::new();
+    for (name, strategy) in [
+        ("oldest", VersionStrategy::Oldest),
+        ("newest", VersionStrategy::Newest),
+    ] {
+        log::error!(
+            "\nRunning tests with the {} allowed dependencies ...\n",
+            name
+        );
+        let strategy_make_options = crate::make::Options {
+            connectivity: ConnectivityStrategy::Online(strategy),
+            dependency_bounds: false,
+            lock: crate::lock::Mode::Ignore,
+            ..make_options.clone()
+        };
+        let exit_code =
+            crate::run::main_helper(elm_home, project, &strategy_make_options, run_options)?;
+        outcomes.push((
+            name,
+            exit_code,
+            picked_dependencies(&project.root_directory),
+        ));
+    }
+
+    // Summarize both runs.
+    log::error!("\nDependency bounds check:\n");
+    for (name, exit_code, dependencies) in outcomes.iter() {
+        let verdict = if *exit_code == 0 { "passed" } else { "failed" };
+        log::error!("  {} dependencies: {}", name, verdict);
+        log::error!("    {}", dependencies);
+    }
+    Ok(outcomes.iter().map(|(_, code, _)| *code).max().unwrap_or(0))
+}
+
+/// Direct dependencies picked for the last run, as written in the generated elm.json.
+fn picked_dependencies(project_root: &Path) -> String {
+    let tests_elm_json = project_root
+        .join("elm-stuff")
+        .join("tests-0.19.1")
+        .join("elm.json");
+    let config: serde_json::Value = match std::fs::read_to_string(tests_elm_json)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+    {
+        Some(config) => config,
+        None => return "(unknown dependencies)".to_string(),
+    };
+    let direct: Vec<String> = config["dependencies"]["direct"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(pkg, version)| format!("{} {}", pkg, version.as_str().unwrap_or("?")))
+        .collect();
+    direct.join(", ")
+}
@@ -0,0 +1,293 @@
+//! Headless browser runtime.
+//!
+//! The generated files of `elm-stuff/tests-0.19.1/` are served on a local port
+//! by a small HTTP server, and the supervisor page `js/index.html` is opened in a headless
+//! Chromium, where tests run in an iframe with a real DOM.
+//! The page sends its messages with POST requests to `/messages`,
+//! and waits for the next message of elm-test-rs with GET requests to `/next`.
+//! The server relays them to the channel of the supervisor, like the socket of Node and Deno,
+//! except `writeFile` messages, written by the server since the page cannot write files.
+//! Only the report outputs, the run summary and the coverage hits may be written.
+//!
+//! The page talks to the server with plain HTTP requests instead of a WebSocket,
+//! which keeps the server to the standard library, and the browser is only started
+//! with command line flags instead of being driven through the DevTools protocol,
+//! so that any Chromium works without a matching driver.
+
+use anyhow::Context;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Environment variable with the path of the browser to use.
+const BROWSER_ENV: &str = "ELM_TEST_RS_BROWSER";
+
+/// Executables looked for when the browser is not given.
+const BROWSERS: &[&str] = &[
+    "chromium",
+    "chromium-browser",
+    "google-chrome",
+    "google-chrome-stable",
+    "chrome",
+];
+
+/// Duration after which a waiting `/next` request is answered with no message,
+/// for the page to ask again instead of hitting a timeout of the browser.
+const NEXT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Connection to the channel of elm-test-rs, opened with the first message of the page.
+struct Relay {
+    channel_port: u16,
+    writer: Mutex<Option<TcpStream>>,
+    /// The reader, with the start of a line received before a timeout.
+    reader: Mutex<Option<(BufReader<TcpStream>, String)>>,
+    /// Files the page may write, and directories where it may write report files.
+    writable: Vec<PathBuf>,
+    /// Token of the supervisor channel, added to the hello message of the page.
+    token: String,
+}
+
+/// Serve the tests directory and open the supervisor page in a headless browser,
+/// relaying its messages to the channel listening on the given port.
+/// The page may only write the given files, or files directly inside them for directory reports.
+pub fn start(
+    tests_root: &Path,
+    channel_port: u16,
+    token: &str,
+    writable: Vec<PathBuf>,
+) -> anyhow::Result<Child> {
+    let browser = find_browser()?;
+    let listener = TcpListener::bind(("127.0.0.1", 0))
+        .context("Failed to open a local port for the browser")?;
+    let port = listener
+        .local_addr()
+        .context("Failed to retrieve the address of the browser server")?
+        .port();
+    let relay = Arc::new(Relay {
+        channel_port,
+        writer: Mutex::new(None),
+        reader: Mutex::new(None),
+        writable,
+        token: token.to_string(),
+    });
+    let root = tests_root.to_path_buf();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().filter_map(|stream| stream.ok()) {
+            let relay = Arc::clone(&relay);
+            let root = root.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = handle(&root, &relay, stream) {
+                    log::debug!("Browser request failed: {:#}", e);
+                }
+            });
+        }
+    });
+    Command::new(&browser)
+        .args(["--headless", "--disable-gpu", "--no-first-run"])
+        .arg("--no-default-browser-check")
+        .arg(format!(
+            "--user-data-dir={}",
+            tests_root.join("browser-profile").display()
+        ))
+        .arg(format!("http://127.0.0.1:{}/js/index.html", port))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context(format!("Failed to start {}", browser.display()))
+}
+
+/// Path of the browser, given by ELM_TEST_RS_BROWSER or found in the PATH.
+fn find_browser() -> anyhow::Result<PathBuf> {
+    if let Some(browser) = std::env::var_os(BROWSER_ENV) {
+        return Ok(PathBuf::from(browser));
+    }
+    BROWSERS
+        .iter()
+        .find_map(|name| which::which(name).ok())
+        .context(format!(
+            "No Chromium browser found, install one or set {} to its path",
+            BROWSER_ENV
+        ))
+}
+
+/// Answer one HTTP request of the browser.
+fn handle(root: &Path, relay: &Relay, stream: TcpStream) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let path = parts.next().unwrap_or("").to_string();
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    match (method.as_str(), path.as_str()) {
+        ("POST", "/messages") => {
+            for line in String::from_utf8_lossy(&body).lines() {
+                relay_message(root, relay, line)?;
+            }
+            respond(stream, "200 OK", "text/plain", b"")
+        }
+        ("GET", "/next") => match next_message(root, relay)? {
+            Some(msg) => respond(stream, "200 OK", "application/json", msg.as_bytes()),
+            None => respond(stream, "204 No Content", "text/plain", b""),
+        },
+        ("GET", _) => {
+            let path = path.split('?').next().unwrap_or("");
+            let file = root.join(path.trim_start_matches('/'));
+            match std::fs::read(&file) {
+                Ok(contents) if !path.contains("..") => {
+                    respond(stream, "200 OK", content_type(&file), &contents)
+                }
+                _ => respond(stream, "404 Not Found", "text/plain", b""),
+            }
+        }
+        _ => respond(stream, "404 Not Found", "text/plain", b""),
+    }
+}
+
+fn respond(
+    mut stream: TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> anyhow::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    Ok(stream.flush()?)
+}
+
+fn content_type(file: &Path) -> &'static str {
+    match file.extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") | Some("mjs") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Write a file asked by the page, or forward its message to the channel.
+fn relay_message(root: &Path, relay: &Relay, line: &str) -> anyhow::Result<()> {
+    let mut msg: serde_json::Value = serde_json::from_str(line).context("Invalid page message")?;
+    if msg["type"] == "hello" {
+        // The token is known by the server only, not by the page.
+        msg["token"] = relay.token.clone().into();
+    }
+    if msg["type"] == "writeFile" {
+        let path = root.join(msg["path"].as_str().context("Missing file path")?);
+        if !is_writable(&relay.writable, &path) {
+            anyhow::bail!("The page is not allowed to write {}", path.display());
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let contents = msg["contents"].as_str().unwrap_or("");
+        let append = msg["append"].as_bool().unwrap_or(false);
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&path)
+            .context(format!("Failed to write {}", path.display()))?;
+        return Ok(file.write_all(contents.as_bytes())?);
+    }
+    let mut writer = relay.writer.lock().unwrap();
+    if writer.is_none() {
+        let channel = TcpStream::connect(("127.0.0.1", relay.channel_port))
+            .context("Failed to connect to elm-test-rs")?;
+        channel.set_read_timeout(Some(NEXT_TIMEOUT))?;
+        *relay.reader.lock().unwrap() = Some((BufReader::new(channel.try_clone()?), String::new()));
+        *writer = Some(channel);
+    }
+    let channel = writer.as_mut().unwrap(); // unwrap is fine since it was just set
+    channel.write_all(msg.to_string().as_bytes())?;
+    Ok(channel.write_all(b"\n")?)
+}
+
+/// Whether the path is one of the writable files,
+/// or a file directly inside one of them, for reports written to a directory.
+fn is_writable(writable: &[PathBuf], path: &Path) -> bool {
+    let in_directory = |dir: &PathBuf| {
+        path.parent() == Some(dir.as_path())
+            && matches!(path.components().next_back(), Some(Component::Normal(_)))
+    };
+    writable
+        .iter()
+        .any(|file| path == file || in_directory(file))
+}
+
+/// Wait for the next message of elm-test-rs, or `None` after a timeout.
+/// The runner path of `runStart` messages is changed into its url.
+fn next_message(root: &Path, relay: &Relay) -> anyhow::Result<Option<String>> {
+    let mut reader = relay.reader.lock().unwrap();
+    let (channel, line) = match reader.as_mut() {
+        Some(reader) => reader,
+        None => return Ok(None),
+    };
+    match channel.read_line(line) {
+        Ok(0) => anyhow::bail!("elm-test-rs closed the channel"),
+        Ok(_) => {}
+        Err(e)
+            if matches!(
+                e.kind(),
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+            ) =>
+        {
+            return Ok(None)
+        }
+        Err(e) => return Err(e.into()),
+    }
+    let mut msg: serde_json::Value = serde_json::from_str(line)?;
+    line.clear();
+    if let Some(runner) = msg["runner"].as_str() {
+        let relative = pathdiff::diff_paths(runner, root).unwrap_or_else(|| runner.into());
+        let url = crate::utils::elm_json_path(&relative).context("Invalid runner path")?;
+        msg["runner"] = format!("/{}", url).into();
+    }
+    Ok(Some(msg.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_writable;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn writable_paths() {
+        let writable = vec![
+            PathBuf::from("/project/elm-stuff/run-summary.json"),
+            PathBuf::from("/project/reports"),
+        ];
+        let allowed = |path: &str| is_writable(&writable, Path::new(path));
+        assert!(allowed("/project/elm-stuff/run-summary.json"));
+        assert!(allowed("/project/reports"));
+        assert!(allowed("/project/reports/Tests.xml"));
+        assert!(!allowed("/project/reports/../elm.json"));
+        assert!(!allowed("/project/reports/nested/Tests.xml"));
+        assert!(!allowed("/project/elm.json"));
+        assert!(!allowed("/home/user/.bashrc"));
+    }
+}
@@ -0,0 +1,311 @@
+//! Function coverage of the source modules by the tests.
+//!
+//! The compiled `Runner.elm.js` is instrumented with one counter per
+//! top-level function of the source modules.
+//! Branches of `case` and `if` expressions, and local functions, are not instrumented,
+//! so a function is covered as soon as a test calls it.
+//! Since elm compiles a declaration `Some.Module.name` into `var $author$project$Some$Module$name`,
+//! counters can be mapped back to the Elm declarations.
+//! Runners send the counters hit by each test to the supervisor,
+//! which accumulates them into a JSON file read back after the tests.
+
+use anyhow::Context;
+use regex::{Captures, Regex};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use crate::project::Project;
+
+/// A top-level function of a source module, instrumented with a counter.
+#[derive(Debug, PartialEq)]
+pub struct Declaration {
+    pub module: String,
+    pub name: String,
+}
+
+/// Coverage of one module.
+#[derive(Debug, Default, PartialEq)]
+pub struct ModuleCoverage {
+    /// Number of times each declaration was called.
+    pub hits: BTreeMap<String, u64>,
+}
+
+impl ModuleCoverage {
+    /// Number of declarations called at least once.
+    pub fn covered(&self) -> usize {
+        self.hits.values().filter(|count| **count > 0).count()
+    }
+
+    /// Percentage of declarations called at least once.
+    pub fn percent(&self) -> f64 {
+        percent(self.covered(), self.hits.len())
+    }
+}
+
+/// Minimum coverage required, either for all source modules together or for one of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Threshold {
+    /// Module on which the threshold applies, or None for the total coverage.
+    pub module: Option<String>,
+    pub percent: f64,
+}
+
+impl Threshold {
+    /// Parse a threshold argument, either "percent" or "Module.Name=percent".
+    pub fn parse(arg: &str) -> anyhow::Result<Threshold> {
+        let (module, percent_str) = match arg.rfind('=') {
+            None => (None, arg),
+            Some(i) => (Some(arg[..i].to_string()), &arg[i + 1..]),
+        };
+        let percent: f64 = percent_str
+            .trim_end_matches('%')
+            .parse()
+            .context(format!("Invalid coverage percentage {}", percent_str))?;
+        if !(0.0..=100.0).contains(&percent) {
+            anyhow::bail!("Coverage percentage {} is not between 0 and 100", percent);
+        }
+        Ok(Threshold { module, percent })
+    }
+}
+
+/// Names of the source modules of the project, excluding the tests.
+pub fn source_modules(project: &Project) -> HashSet<String> {
+    let source_dirs = project.source_dirs();
+    source_dirs
+        .iter()
+        .flat_map(crate::make::elm_files_within)
+        .filter_map(|file| crate::utils::absolute_path(file).ok())
+        .filter_map(|path| crate::make::get_module_name(&source_dirs, &path).ok())
+        .collect()
+}
+
+/// Add a counter at the start of every top-level function of the given modules.
+/// Counters live in the global `__elmTestCoverage` array, created at the top of the file.
+///
+/// Returns the instrumented code and the declaration associated to each counter.
+pub fn instrument(
+    elm_js: &str,
+    modules: &HashSet<String>,
+) -> anyhow::Result<(String, Vec<Declaration>)> {
+    let function_definition = Regex::new(
+        r#"(?mx)
+    ^var\s+\$author\$project\$((?:[A-Z]\w*\$)+)([a-z]\w*)
+    \s*=\s*(?:F\d+\(\s*)?function\s*\([\w,\s]*\)\s*\{
+"#,
+    )?;
+    let mut declarations = Vec::new();
+    let elm_js = function_definition.replace_all(elm_js, |caps: &Captures| {
+        let module = caps[1].trim_end_matches('$').replace('$', ".");
+        if !modules.contains(&module) {
+            return caps[0].to_string();
+        }
+        let counter = format!("{}\n__elmTestCoverage[{}]++;", &caps[0], declarations.len());
+        declarations.push(Declaration {
+            module,
+            name: caps[2].to_string(),
+        });
+        counter
+    });
+    let prelude = format!(
+        "globalThis.__elmTestCoverage = new Uint32Array({});",
+        declarations.len()
+    );
+    Ok(([prelude.as_str(), &elm_js].join("\n"), declarations))
+}
+
+/// Combine the counters hits written by the supervisor with the instrumented declarations.
+pub fn collect(
+    declarations: &[Declaration],
+    hits_file: &Path,
+) -> anyhow::Result<BTreeMap<String, ModuleCoverage>> {
+    let hits_str = std::fs::read_to_string(hits_file)
+        .context(format!("Failed to read {}", hits_file.display()))?;
+    let hits: serde_json::Value =
+        serde_json::from_str(&hits_str).context("Invalid coverage hits JSON")?;
+    let mut modules: BTreeMap<String, ModuleCoverage> = BTreeMap::new();
+    for (id, decl) in declarations.iter().enumerate() {
+        let count = hits[id.to_string().as_str()].as_u64().unwrap_or(0);
+        *modules
+            .entry(decl.module.clone())
+            .or_default()
+            .hits
+            .entry(decl.name.clone())
+            .or_insert(0) += count;
+    }
+    Ok(modules)
+}
+
+/// Print a per-module coverage summary to stderr
+/// and write the detailed report in JSON next to the hits file.
+pub fn report(
+    modules: &BTreeMap<String, ModuleCoverage>,
+    report_file: &Path,
+) -> anyhow::Result<()> {
+    let width = modules.keys().map(|m| m.len()).max().unwrap_or(0).max(5);
+    let mut summary = vec![String::from("\nCoverage of top-level functions:\n")];
+    for (module, coverage) in modules.iter() {
+        summary.push(format!(
+            "{:width$}  {:>5.1}%  ({}/{})",
+            module,
+            coverage.percent(),
+            coverage.covered(),
+            coverage.hits.len(),
+            width = width,
+        ));
+    }
+    let (covered, total) = total_coverage(modules);
+    summary.push(format!(
+        "{:width$}  {:>5.1}%  ({}/{})\n",
+        "Total",
+        percent(covered, total),
+        covered,
+        total,
+        width = width,
+    ));
+    log::error!("{}", summary.join("\n"));
+
+    let json_modules: serde_json::Map<String, serde_json::Value> = modules
+        .iter()
+        .map(|(module, coverage)| {
+            let hits: serde_json::Map<String, serde_json::Value> = coverage
+                .hits
+                .iter()
+                .map(|(name, count)| (name.clone(), serde_json::Value::from(*count)))
+                .collect();
+            let module_json = serde_json::json!({
+                "covered": coverage.covered(),
+                "total": coverage.hits.len(),
+                "declarations": hits,
+            });
+            (module.clone(), module_json)
+        })
+        .collect();
+    let report_str = serde_json::to_string_pretty(&serde_json::Value::Object(json_modules))
+        .context("Failed to convert the coverage report to JSON")?;
+    std::fs::write(report_file, report_str)
+        .context(format!("Failed to write {}", report_file.display()))?;
+    log::error!(
+        "Detailed coverage report written to {}",
+        report_file.display()
+    );
+    Ok(())
+}
+
+/// Check the coverage against the given thresholds, and print the ones not reached.
+/// Returns true if all thresholds are reached.
+pub fn check(modules: &BTreeMap<String, ModuleCoverage>, thresholds: &[Threshold]) -> bool {
+    let mut reached = true;
+    for threshold in thresholds {
+        let (name, coverage) = match &threshold.module {
+            None => {
+                let (covered, total) = total_coverage(modules);
+                ("Total", percent(covered, total))
+            }
+            Some(module) => match modules.get(module) {
+                Some(coverage) => (module.as_str(), coverage.percent()),
+                None => {
+                    log::error!("No coverage data for module {}", module);
+                    reached = false;
+                    continue;
+                }
+            },
+        };
+        if coverage < threshold.percent {
+            log::error!(
+                "Coverage of {} is {:.1}%, below the minimum of {}%",
+                name,
+                coverage,
+                threshold.percent
+            );
+            reached = false;
+        }
+    }
+    reached
+}
+
+/// Number of covered declarations, and total number of declarations, of all modules.
+fn total_coverage(modules: &BTreeMap<String, ModuleCoverage>) -> (usize, usize) {
+    modules.values().fold((0, 0), |(covered, total), coverage| {
+        (covered + coverage.covered(), total + coverage.hits.len())
+    })
+}
+
+fn percent(covered: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        100.0 * covered as f64 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, instrument, Declaration, ModuleCoverage, Threshold};
+    use std::collections::{BTreeMap, HashSet};
+
+    #[test]
+    fn instrument_source_functions() {
+        let elm_js = r#"var $author$project$Math$Extra$add = F2(
+	function (a, b) {
+		return a + b;
+	});
+var $author$project$Math$Extra$zero = 0;
+var $author$project$Tests$suite = function (x) {
+	return x;
+};"#;
+        let modules: HashSet<String> = vec!["Math.Extra".to_string()].into_iter().collect();
+        let (instrumented, declarations) = instrument(elm_js, &modules).unwrap();
+        assert_eq!(
+            declarations,
+            vec![Declaration {
+                module: "Math.Extra".to_string(),
+                name: "add".to_string(),
+            }]
+        );
+        assert!(instrumented.starts_with("globalThis.__elmTestCoverage = new Uint32Array(1);"));
+        assert!(instrumented.contains("function (a, b) {\n__elmTestCoverage[0]++;"));
+        assert!(!instrumented.contains("__elmTestCoverage[1]"));
+    }
+
+    #[test]
+    fn parse_threshold() {
+        let threshold = |module: Option<&str>, percent| Threshold {
+            module: module.map(|m| m.to_string()),
+            percent,
+        };
+        assert_eq!(Threshold::parse("80").unwrap(), threshold(None, 80.0));
+        assert_eq!(Threshold::parse("92.5%").unwrap(), threshold(None, 92.5));
+        assert_eq!(
+            Threshold::parse("Math.Extra=90").unwrap(),
+            threshold(Some("Math.Extra"), 90.0)
+        );
+        assert!(Threshold::parse("120").is_err());
+        assert!(Threshold::parse("Math.Extra=").is_err());
+    }
+
+    #[test]
+    fn check_thresholds() {
+        let module = |hits: &[u64]| ModuleCoverage {
+            hits: hits
+                .iter()
+                .enumerate()
+                .map(|(i, count)| (format!("f{}", i), *count))
+                .collect(),
+        };
+        let mut modules = BTreeMap::new();
+        modules.insert("A".to_string(), module(&[1, 1, 0, 0]));
+        modules.insert("B".to_string(), module(&[3, 2, 1, 0]));
+        let total = |percent| Threshold {
+            module: None,
+            percent,
+        };
+        let of_module = |module: &str, percent| Threshold {
+            module: Some(module.to_string()),
+            percent,
+        };
+        assert!(check(&modules, &[total(62.5), of_module("B", 75.0)]));
+        assert!(!check(&modules, &[total(70.0)]));
+        assert!(!check(&modules, &[of_module("A", 60.0)]));
+        assert!(!check(&modules, &[of_module("Missing", 0.0)]));
+    }
+}
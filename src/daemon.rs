@@ -0,0 +1,215 @@
+//! Long-lived daemon running tests on demand.
+//!
+//! The daemon stays resident with the compiled runner and a persistent supervisor,
+//! whose workers stay alive between runs, to avoid the cold start of the whole pipeline at every run.
+//! The runner is only compiled again when the request or the files of the project change,
+//! and the supervisor is restarted when the reporter, globs or reports it embeds change.
+//! It listens for run requests on a local TCP socket, whose address is written
+//! in `elm-stuff/tests-0.19.1/daemon-address`.
+//!
+//! A request is a single JSON line such as `{"files": ["tests/A.elm"], "filter": "foo"}`,
+//! where both fields are optional.
+//! The report of the run is streamed back line by line,
+//! followed by a last JSON line such as `{"exitCode": 0}`.
+
+use anyhow::Context;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use walkdir::WalkDir;
+
+use crate::project::Project;
+use crate::run::{Prepared, Supervisor};
+
+/// Start the daemon and handle run requests one after the other.
+pub fn main(
+    elm_home: &Path,
+    elm_project_root: &Path,
+    port: u16,
+    make_options: crate::make::Options,
+    run_options: crate::run::Options,
+) -> anyhow::Result<()> {
+    // Prints to stderr the current version
+    crate::utils::print_title();
+
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .context(format!("Failed to listen on port {}", port))?;
+    let address = listener
+        .local_addr()
+        .context("Failed to retrieve the daemon address")?;
+    let tests_root = elm_project_root.join("elm-stuff").join("tests-0.19.1");
+    std::fs::create_dir_all(&tests_root).context(format!(
+        "Could not create tests dir {}",
+        tests_root.display()
+    ))?;
+    std::fs::write(tests_root.join("daemon-address"), address.to_string())
+        .context("Failed to write the daemon address")?;
+    log::error!("elm-test-rs daemon listening on {}", address);
+
+    let mut warm = None;
+    for stream in listener.incoming() {
+        let result = stream
+            .context("Failed to accept a connection")
+            .and_then(|stream| {
+                handle_request(
+                    elm_home,
+                    elm_project_root,
+                    &make_options,
+                    &run_options,
+                    &mut warm,
+                    stream,
+                )
+            });
+        if let Err(e) = result {
+            log::error!("Run request failed: {:#}", e);
+        }
+    }
+    Ok(())
+}
+
+/// What is kept alive between requests.
+struct Warm {
+    /// Fingerprint of the inputs of the compiled runner, see `inputs_fingerprint`.
+    inputs: u64,
+    prepared: Prepared,
+    /// The persistent supervisor, with the workers of the last run.
+    supervisor: Option<Supervisor>,
+    /// The `supervisor_key` of the prepared files the supervisor was started with.
+    supervisor_key: u64,
+}
+
+/// Run the tests asked in one request, and stream the report back to the client.
+fn handle_request(
+    elm_home: &Path,
+    elm_project_root: &Path,
+    make_options: &crate::make::Options,
+    run_options: &crate::run::Options,
+    warm: &mut Option<Warm>,
+    mut stream: TcpStream,
+) -> anyhow::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(stream.try_clone()?)
+        .read_line(&mut request_line)
+        .context("Failed to read the run request")?;
+    let request: serde_json::Value =
+        serde_json::from_str(request_line.trim()).context("Invalid run request")?;
+    let files: Vec<String> = match request["files"].as_array() {
+        None => make_options.files.clone(),
+        Some(files) => files
+            .iter()
+            .filter_map(|f| f.as_str().map(|s| s.to_string()))
+            .collect(),
+    };
+    let filter = request["filter"]
+        .as_str()
+        .map(|s| s.to_string())
+        .or_else(|| run_options.filter.clone());
+    let make_options = crate::make::Options {
+        files,
+        filter: filter.clone(),
+        ..make_options.clone()
+    };
+    let run_options = crate::run::Options {
+        filter,
+        ..run_options.clone()
+    };
+
+    // Reload the project since its elm.json may have changed,
+    // and only compile the runner again if one of its inputs changed.
+    let project = Project::from_dir(elm_project_root)?;
+    let inputs = inputs_fingerprint(&project, &make_options);
+    let recompile = match warm {
+        Some(w) => w.inputs != inputs,
+        None => true,
+    };
+    if recompile {
+        let prepared =
+            match crate::run::prepare(elm_home, &project, &make_options, &run_options, true)? {
+                Some(prepared) => prepared,
+                None => {
+                    let response =
+                        serde_json::json!({ "exitCode": 1, "error": "Compilation failed" });
+                    writeln!(stream, "{}", response)?;
+                    return Ok(());
+                }
+            };
+        let (supervisor, supervisor_key) = match warm.take() {
+            Some(w) => (w.supervisor, w.supervisor_key),
+            None => (None, prepared.supervisor_key),
+        };
+        *warm = Some(Warm {
+            inputs,
+            prepared,
+            supervisor,
+            supervisor_key,
+        });
+    }
+    let warm_state = warm.as_mut().unwrap(); // unwrap is fine since it was just set
+
+    // Start the persistent supervisor if it is not running,
+    // or with another reporter, globs or reports than the ones of the prepared files.
+    let alive = match &mut warm_state.supervisor {
+        Some(s) => matches!(s.child.try_wait(), Ok(None)),
+        None => false,
+    };
+    if !alive || warm_state.supervisor_key != warm_state.prepared.supervisor_key {
+        if let Some(mut stale) = warm_state.supervisor.take() {
+            let _ = stale.child.kill();
+            let _ = stale.child.wait();
+        }
+        log::info!("Starting the persistent supervisor ...");
+        warm_state.supervisor = Some(crate::run::spawn_supervisor(
+            &warm_state.prepared,
+            &run_options,
+        )?);
+        warm_state.supervisor_key = warm_state.prepared.supervisor_key;
+    }
+    let prepared = &warm_state.prepared;
+    let running = warm_state.supervisor.as_mut().unwrap(); // unwrap is fine since it was just set
+
+    // The workers of the previous run are reused if the runner did not change.
+    crate::run::send_runner(running, prepared, !recompile)?;
+
+    // Stream the report until the end of the run.
+    let exit_code = crate::run::receive_reports(running, prepared, &mut stream)?;
+    let exit_code: i32 = match exit_code {
+        Some(code) => code,
+        None => {
+            warm_state.supervisor = None;
+            anyhow::bail!("The supervisor stopped before the end of the run");
+        }
+    };
+    let exit_code = crate::run::finish(prepared, &run_options, exit_code)?;
+    writeln!(stream, "{}", serde_json::json!({ "exitCode": exit_code }))?;
+    Ok(())
+}
+
+/// Fingerprint of the inputs of the compiled runner: the files and filter of the request,
+/// and the size and modification time of elm.json and of the files of the source directories,
+/// including the fixtures and snapshots of the tests directory.
+fn inputs_fingerprint(project: &Project, make_options: &crate::make::Options) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    make_options.files.hash(&mut hasher);
+    make_options.filter.hash(&mut hasher);
+    let elm_json = project.root_directory.join("elm.json");
+    for root in std::iter::once(&elm_json).chain(&project.src_and_test_dirs) {
+        let entries = WalkDir::new(root)
+            .follow_links(true)
+            .into_iter()
+            .filter_entry(|e| {
+                let name = e.file_name().to_string_lossy();
+                e.depth() == 0 || !(name.starts_with('.') || name == "elm-stuff")
+            })
+            .filter_map(Result::ok);
+        for entry in entries {
+            entry.path().hash(&mut hasher);
+            if let Ok(metadata) = entry.metadata() {
+                metadata.len().hash(&mut hasher);
+                metadata.modified().ok().hash(&mut hasher);
+            }
+        }
+    }
+    hasher.finish()
+}
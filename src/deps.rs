@@ -6,17 +6,39 @@ use pubgrub::solver::resolve;
 use pubgrub::type_aliases::Map;
 use pubgrub::version::SemanticVersion as SemVer;
 use std::collections::BTreeMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use pubgrub_dependency_provider_elm::constraint::Constraint;
 use pubgrub_dependency_provider_elm::dependency_provider::{
     ElmPackageProviderOffline, ElmPackageProviderOnline, ProjectAdapter, VersionStrategy,
 };
 use pubgrub_dependency_provider_elm::project_config::{
-    AppDependencies, ApplicationConfig, PackageConfig, Pkg, ProjectConfig,
+    AppDependencies, ApplicationConfig, ExposedModules, PackageConfig, Pkg, ProjectConfig,
 };
 
-#[derive(Debug)]
+/// Environment variable with the URL of the package registry, read by the `--registry` option.
+pub const REGISTRY_VAR: &str = "ELM_REGISTRY";
+
+/// Package registry and proxy for network calls, given with `--registry` and `--proxy`.
+#[derive(Debug, Clone, Default)]
+pub struct Network {
+    /// URL of the package registry, which can be an internal mirror of the package website.
+    pub registry: Option<String>,
+    /// Proxy used instead of the ones of the HTTPS_PROXY and HTTP_PROXY environment variables.
+    pub proxy: Option<String>,
+}
+
+impl Network {
+    /// URL of the package registry used when solving dependencies online.
+    pub fn registry_url(&self) -> String {
+        match &self.registry {
+            Some(url) if !url.trim().is_empty() => url.trim().trim_end_matches('/').to_string(),
+            _ => "https://package.elm-lang.org".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum ConnectivityStrategy {
     Progressive,
     Offline,
@@ -26,6 +48,7 @@ pub enum ConnectivityStrategy {
 /// Install elm-explorations/test to the tests dependencies.
 pub fn init<P: AsRef<Path>>(
     elm_home: P,
+    network: &Network,
     config: ProjectConfig,
     offline: bool,
 ) -> anyhow::Result<ProjectConfig> {
@@ -36,11 +59,11 @@ pub fn init<P: AsRef<Path>>(
     };
     match config {
         ProjectConfig::Application(app_config) => Ok(ProjectConfig::Application(
-            init_app(elm_home.as_ref(), &strategy, app_config)
+            init_app(elm_home.as_ref(), network, &strategy, app_config)
                 .context("Error while setting up the app test dependencies")?,
         )),
         ProjectConfig::Package(pkg_config) => Ok(ProjectConfig::Package(
-            init_pkg(elm_home.as_ref(), &strategy, pkg_config)
+            init_pkg(elm_home.as_ref(), network, &strategy, pkg_config)
                 .context("Error while setting up the package test dependencies")?,
         )),
     }
@@ -48,6 +71,7 @@ pub fn init<P: AsRef<Path>>(
 
 fn init_app(
     elm_home: &Path,
+    network: &Network,
     strategy: &ConnectivityStrategy,
     mut app_config: ApplicationConfig,
 ) -> anyhow::Result<ApplicationConfig> {
@@ -61,7 +85,7 @@ fn init_app(
         .collect();
 
     // Check that those dependencies are correct
-    solve_check(elm_home, &all_deps, strategy, true)
+    solve_check(elm_home, network, &all_deps, strategy, true)
         .context("The app dependencies are incorrect")?;
 
     // Check if elm-explorations/test is already in the dependencies.
@@ -97,6 +121,7 @@ fn init_app(
     // Solve dependencies
     let solution = solve_deps(
         elm_home,
+        network,
         strategy,
         &all_deps,
         Pkg::new("root", ""),
@@ -122,6 +147,7 @@ fn init_app(
 
 fn init_pkg(
     elm_home: &Path,
+    network: &Network,
     strategy: &ConnectivityStrategy,
     mut pkg_config: PackageConfig,
 ) -> anyhow::Result<PackageConfig> {
@@ -133,7 +159,7 @@ fn init_pkg(
         .collect();
 
     // Check that those dependencies are correct
-    solve_check(elm_home, &all_deps, strategy, false)
+    solve_check(elm_home, network, &all_deps, strategy, false)
         .context("The package dependencies are incorrect")?;
 
     // Check if elm-explorations/test is already in the dependencies.
@@ -150,6 +176,7 @@ fn init_pkg(
     // Solve dependencies to check that elm-explorations/test is compatible
     solve_deps(
         elm_home,
+        network,
         strategy,
         &all_deps,
         pkg_config.name.clone(),
@@ -167,9 +194,12 @@ fn init_pkg(
 /// Solve dependencies needed to run the tests.
 pub fn solve<P: AsRef<Path>>(
     elm_home: &Path,
+    network: &Network,
     connectivity: &ConnectivityStrategy,
     config: &ProjectConfig,
     src_dirs: &[P],
+    extra_deps: &[(Pkg, Range<SemVer>)],
+    pins: &[(Pkg, SemVer)],
 ) -> anyhow::Result<ApplicationConfig> {
     match config {
         ProjectConfig::Application(app_config) => {
@@ -178,15 +208,45 @@ pub fn solve<P: AsRef<Path>>(
                 .chain(app_config.test_dependencies.direct.iter())
                 .map(|(p, v)| (p.clone(), Range::exact(*v)))
                 .collect();
-            // TODO: take somehow into account already picked versions for indirect deps.
+            // Keep the versions of the indirect dependencies picked by the application,
+            // since picking other ones could hide bugs, unless they conflict with the test runner.
+            let indirect_deps: Map<Pkg, Range<SemVer>> = app_config
+                .dependencies
+                .indirect
+                .iter()
+                .chain(app_config.test_dependencies.indirect.iter())
+                .filter(|(p, _)| !direct_deps.contains_key(*p))
+                .map(|(p, v)| (p.clone(), Range::exact(*v)))
+                .collect();
+            let root = Pkg::new("root", "");
             solve_helper(
                 elm_home,
+                network,
                 connectivity,
                 src_dirs,
-                &Pkg::new("root", ""),
+                &root,
                 SemVer::zero(),
-                direct_deps,
+                direct_deps.clone(),
+                &indirect_deps,
+                extra_deps,
+                pins,
             )
+            .or_else(|err| {
+                log::warn!("The indirect dependencies of the application conflict with the ones of the tests, so they are solved again");
+                log::info!("{:#}", err);
+                solve_helper(
+                    elm_home,
+                    network,
+                    connectivity,
+                    src_dirs,
+                    &root,
+                    SemVer::zero(),
+                    direct_deps,
+                    &Map::default(),
+                    extra_deps,
+                    pins,
+                )
+            })
         }
         ProjectConfig::Package(pkg_config) => {
             let normal_deps = pkg_config.dependencies.iter();
@@ -196,27 +256,97 @@ pub fn solve<P: AsRef<Path>>(
                 .collect();
             solve_helper(
                 elm_home,
+                network,
                 connectivity,
                 src_dirs,
                 &pkg_config.name,
                 pkg_config.version,
                 deps,
+                &Map::default(),
+                extra_deps,
+                pins,
             )
         }
     }
 }
 
-#[allow(clippy::ptr_arg)]
+/// Solve dependencies of an example application using the local source of a package.
+/// The package itself is removed from the dependencies,
+/// and replaced by its own dependencies since its source is compiled with the example.
+pub fn solve_example<P: AsRef<Path>>(
+    elm_home: &Path,
+    network: &Network,
+    connectivity: &ConnectivityStrategy,
+    pkg_config: &PackageConfig,
+    example_config: &ApplicationConfig,
+    src_dirs: &[P],
+) -> anyhow::Result<ApplicationConfig> {
+    let mut deps: Map<Pkg, Range<SemVer>> = example_config
+        .dependencies
+        .direct
+        .iter()
+        .filter(|(p, _)| **p != pkg_config.name)
+        .map(|(p, v)| (p.clone(), Range::exact(*v)))
+        .collect();
+    for (p, c) in pkg_config.dependencies.iter() {
+        deps.entry(p.clone()).or_insert_with(|| c.0.clone());
+    }
+    let root = Pkg::new("root", "");
+    let mut solution = solve_deps(
+        elm_home,
+        network,
+        connectivity,
+        &deps,
+        root.clone(),
+        SemVer::zero(),
+    )
+    .context("Combining the example dependencies with the ones of the package failed")?;
+    solution.remove(&root);
+    let dependencies = AppDependencies {
+        direct: solution
+            .clone()
+            .into_iter()
+            .filter(|(d, _)| deps.contains_key(d))
+            .collect(),
+        indirect: solution
+            .into_iter()
+            .filter(|(d, _)| !deps.contains_key(d))
+            .collect(),
+    };
+    Ok(ApplicationConfig {
+        source_directories: src_dirs
+            .iter()
+            .filter_map(|p| crate::utils::elm_json_path(p.as_ref()))
+            .collect(),
+        elm_version: SemVer::new(0, 19, 1),
+        dependencies,
+        test_dependencies: AppDependencies {
+            direct: BTreeMap::new(),
+            indirect: BTreeMap::new(),
+        },
+    })
+}
+
+#[allow(clippy::ptr_arg, clippy::too_many_arguments)]
 fn solve_helper<P: AsRef<Path>>(
     elm_home: &Path,
+    network: &Network,
     connectivity: &ConnectivityStrategy,
     src_dirs: &[P],
     pkg_id: &Pkg,
     version: SemVer,
     direct_deps: Map<Pkg, Range<SemVer>>,
+    indirect_deps: &Map<Pkg, Range<SemVer>>,
+    extra_deps: &[(Pkg, Range<SemVer>)],
+    pins: &[(Pkg, SemVer)],
 ) -> anyhow::Result<ApplicationConfig> {
     // TODO: there might be an issue if that was already in the dependencies.
     let mut deps = direct_deps;
+    // Extra dependencies asked with --extra-dependency, for generated code or custom runners.
+    for (pkg, range) in extra_deps {
+        let constraint = deps.entry(pkg.clone()).or_insert_with(Range::any);
+        *constraint = constraint.intersection(range);
+    }
     deps.insert(
         Pkg::new("mpizenberg", "elm-test-runner"),
         Range::exact((4, 0, 6)),
@@ -225,8 +355,33 @@ fn solve_helper<P: AsRef<Path>>(
     // TODO: maybe not the best way to handle but should work most of the time.
     deps.entry(Pkg::new("elm", "json"))
         .or_insert_with(|| Range::between((1, 0, 0), (2, 0, 0)));
-    let mut solution = solve_deps(elm_home, connectivity, &deps, pkg_id.clone(), version)
-        .context("Combining the project dependencies with the ones of the test runner failed")?;
+    // Versions forced with --pin replace the constraints on those packages,
+    // so that their own dependencies are solved with them.
+    // Pinned packages which are not dependencies of the project become direct ones.
+    for (pkg, version) in pins {
+        if deps.contains_key(pkg) || !indirect_deps.contains_key(pkg) {
+            deps.insert(pkg.clone(), Range::exact(*version));
+        }
+    }
+    // Indirect dependencies constrain the solution, but stay indirect.
+    let mut constraints = deps.clone();
+    for (pkg, range) in indirect_deps {
+        constraints
+            .entry(pkg.clone())
+            .or_insert_with(|| range.clone());
+    }
+    for (pkg, version) in pins {
+        constraints.insert(pkg.clone(), Range::exact(*version));
+    }
+    let mut solution = solve_deps(
+        elm_home,
+        network,
+        connectivity,
+        &constraints,
+        pkg_id.clone(),
+        version,
+    )
+    .context("Combining the project dependencies with the ones of the test runner failed")?;
     solution.remove(pkg_id);
 
     // Split solution into direct and indirect deps.
@@ -241,35 +396,43 @@ fn solve_helper<P: AsRef<Path>>(
             .filter(|(d, _)| !deps.contains_key(d))
             .collect(),
     };
+    Ok(application_config(src_dirs, dependencies))
+}
+
+/// Configuration of the generated tests application, with the given dependencies.
+pub fn application_config<P: AsRef<Path>>(
+    src_dirs: &[P],
+    dependencies: AppDependencies,
+) -> ApplicationConfig {
     let test_dependencies = AppDependencies {
         direct: BTreeMap::new(),
         indirect: BTreeMap::new(),
     };
     let source_directories: Vec<String> = src_dirs
         .iter()
-        .filter_map(|p| p.as_ref().to_str())
-        .map(|s| s.to_string())
+        .filter_map(|p| crate::utils::elm_json_path(p.as_ref()))
         .collect();
-    Ok(ApplicationConfig {
+    ApplicationConfig {
         source_directories,
         // TODO: might have to change that
         elm_version: SemVer::new(0, 19, 1),
         dependencies,
         test_dependencies,
-    })
+    }
 }
 
 /// Check that those dependencies are correct.
 /// Use progressive connectivity mode.
 fn solve_check(
     elm_home: &Path,
+    network: &Network,
     deps: &Map<Pkg, Range<SemVer>>,
     strategy: &ConnectivityStrategy,
     is_app: bool,
 ) -> anyhow::Result<()> {
     let pkg_id = Pkg::new("root", "");
     let version = SemVer::zero();
-    let mut solution = solve_deps(elm_home, strategy, deps, pkg_id.clone(), version)?;
+    let mut solution = solve_deps(elm_home, network, strategy, deps, pkg_id.clone(), version)?;
     // Check that indirect deps are correct if this is for an application.
     // All packages in the solution must exist in the original dependencies.
     if is_app {
@@ -283,9 +446,122 @@ fn solve_check(
     Ok(())
 }
 
+/// Parse a pinned package version, such as "elm/json@1.1.3".
+pub fn parse_pin(pin: &str) -> anyhow::Result<(Pkg, SemVer)> {
+    let invalid = || {
+        format!(
+            "Invalid package version \"{}\", expected author/name@version",
+            pin
+        )
+    };
+    let (package, version) = pin.split_once('@').with_context(invalid)?;
+    let version: SemVer = version.parse().ok().with_context(invalid)?;
+    Ok((parse_package(package).with_context(invalid)?, version))
+}
+
+/// Parse an extra dependency, such as "elm/random" for any version or "elm/random@1.0.0".
+pub fn parse_extra_dependency(dependency: &str) -> anyhow::Result<(Pkg, Range<SemVer>)> {
+    let invalid = || {
+        format!(
+            "Invalid dependency \"{}\", expected author/name or author/name@version",
+            dependency
+        )
+    };
+    match dependency.split_once('@') {
+        None => Ok((
+            parse_package(dependency).with_context(invalid)?,
+            Range::any(),
+        )),
+        Some(_) => {
+            let (pkg, version) = parse_pin(dependency).with_context(invalid)?;
+            Ok((pkg, Range::exact(version)))
+        }
+    }
+}
+
+/// Parse a local package, such as "elm/parser=vendor/parser",
+/// mapping a package name to the directory of a vendored fork.
+pub fn parse_local_package(local_package: &str) -> anyhow::Result<(Pkg, PathBuf)> {
+    let invalid = || {
+        format!(
+            "Invalid local package \"{}\", expected author/name=path",
+            local_package
+        )
+    };
+    let (package, dir) = local_package.split_once('=').with_context(invalid)?;
+    if dir.is_empty() {
+        anyhow::bail!(invalid());
+    }
+    Ok((parse_package(package).with_context(invalid)?, dir.into()))
+}
+
+/// Replace vendored packages in the project dependencies by their own dependencies,
+/// which are added to the extra dependencies, since their source is compiled with the tests.
+/// Return the source directory of each vendored package, relative to the project root if not absolute.
+pub fn use_local_packages(
+    project_root: &Path,
+    config: &mut ProjectConfig,
+    local_packages: &[(Pkg, PathBuf)],
+    extra_deps: &mut Vec<(Pkg, Range<SemVer>)>,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let is_local = |pkg: &Pkg| local_packages.iter().any(|(local, _)| local == pkg);
+    match config {
+        ProjectConfig::Application(app_config) => {
+            for deps in [
+                &mut app_config.dependencies,
+                &mut app_config.test_dependencies,
+            ] {
+                deps.direct.retain(|pkg, _| !is_local(pkg));
+                deps.indirect.retain(|pkg, _| !is_local(pkg));
+            }
+        }
+        ProjectConfig::Package(pkg_config) => {
+            pkg_config.dependencies.retain(|pkg, _| !is_local(pkg));
+            pkg_config.test_dependencies.retain(|pkg, _| !is_local(pkg));
+        }
+    }
+    let mut src_dirs = Vec::new();
+    for (pkg, dir) in local_packages {
+        let dir = project_root.join(dir);
+        let elm_json = dir.join("elm.json");
+        let elm_json_str = std::fs::read_to_string(&elm_json)
+            .context(format!("Unable to read {}", elm_json.display()))?;
+        let local_config: PackageConfig = serde_json::from_str(&elm_json_str).context(format!(
+            "Invalid elm.json for the local package {}: {}",
+            pkg,
+            elm_json.display()
+        ))?;
+        if &local_config.name != pkg {
+            log::warn!(
+                "The local package {} in {} is named {} in its elm.json",
+                pkg,
+                dir.display(),
+                local_config.name
+            );
+        }
+        for (dep, constraint) in local_config.dependencies {
+            if !is_local(&dep) {
+                extra_deps.push((dep, constraint.0));
+            }
+        }
+        src_dirs.push(dir.join("src"));
+    }
+    Ok(src_dirs)
+}
+
+/// Parse a package name, such as "elm/json".
+pub fn parse_package(package: &str) -> Option<Pkg> {
+    let (author, name) = package.split_once('/')?;
+    if author.is_empty() || name.is_empty() || name.contains('/') {
+        return None;
+    }
+    Some(Pkg::new(author, name))
+}
+
 /// Solve project dependencies.
 fn solve_deps(
     elm_home: &Path,
+    network: &Network,
     connectivity: &ConnectivityStrategy,
     deps: &Map<Pkg, Range<SemVer>>,
     pkg_id: Pkg,
@@ -339,13 +615,23 @@ fn solve_deps(
             let offline_provider = ElmPackageProviderOffline::new(elm_home, "0.19.1");
             let deps_provider =
                 ProjectAdapter::new(pkg_id.clone(), version, deps, &offline_provider);
-            solution(resolve(&deps_provider, pkg_id, version))
+            solution(resolve(&deps_provider, pkg_id, version)).map_err(|err| {
+                let missing = missing_packages(elm_home, deps);
+                if missing.is_empty() {
+                    return err;
+                }
+                err.context(format!(
+                    "Some packages are not installed in {}, and cannot be downloaded in offline mode:\n\n    {}\n",
+                    elm_home.join("0.19.1").join("packages").display(),
+                    missing.join("\n    ")
+                ))
+            })
         }
         ConnectivityStrategy::Online(strategy) => {
             let online_provider = match ElmPackageProviderOnline::new(
                 elm_home,
                 "0.19.1",
-                "https://package.elm-lang.org",
+                &network.registry_url(),
                 crate::utils::http_fetch,
                 strategy.clone(),
             ) {
@@ -356,10 +642,14 @@ fn solve_deps(
             // correctly implemented errors with thiserror.
             let deps_provider =
                 ProjectAdapter::new(pkg_id.clone(), version, deps, &online_provider);
-            solution(resolve(&deps_provider, pkg_id, version))
+            // The fetcher is a plain function, so the proxy is given to it for this solve only.
+            crate::utils::with_proxy(network.proxy.as_deref(), || {
+                solution(resolve(&deps_provider, pkg_id, version))
+            })
         }
         ConnectivityStrategy::Progressive => solve_deps(
             elm_home,
+            network,
             &ConnectivityStrategy::Offline,
             deps,
             pkg_id.clone(),
@@ -368,6 +658,7 @@ fn solve_deps(
         .or_else(|_| {
             solve_deps(
                 elm_home,
+                network,
                 &ConnectivityStrategy::Online(VersionStrategy::Newest),
                 deps,
                 pkg_id,
@@ -376,3 +667,93 @@ fn solve_deps(
         }),
     }
 }
+
+/// Dependencies with no installed version in their range, followed by that range.
+/// Those cannot be picked when solving dependencies offline.
+pub fn missing_packages(elm_home: &Path, deps: &Map<Pkg, Range<SemVer>>) -> Vec<String> {
+    let packages_dir = elm_home.join("0.19.1").join("packages");
+    let mut missing: Vec<String> = deps
+        .iter()
+        .filter(|(pkg, range)| {
+            let versions_dir = packages_dir.join(&pkg.author).join(&pkg.name);
+            !std::fs::read_dir(versions_dir)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter_map(|entry| entry.file_name().to_str()?.parse::<SemVer>().ok())
+                .any(|v| range.contains(&v))
+        })
+        .map(|(pkg, range)| format!("{} {}", pkg, range))
+        .collect();
+    missing.sort();
+    missing
+}
+
+/// Modules exposed by an installed package, read from its elm.json.
+pub fn exposed_modules(elm_home: &Path, pkg: &Pkg, version: SemVer) -> anyhow::Result<Vec<String>> {
+    let elm_json = elm_home
+        .join("0.19.1")
+        .join("packages")
+        .join(&pkg.author)
+        .join(&pkg.name)
+        .join(version.to_string())
+        .join("elm.json");
+    let elm_json_str = std::fs::read_to_string(&elm_json)
+        .context(format!("Unable to read {}", elm_json.display()))?;
+    let config: PackageConfig = serde_json::from_str(&elm_json_str)
+        .context(format!("Invalid elm.json of {} {}", pkg, version))?;
+    Ok(match config.exposed_modules {
+        ExposedModules::NoCategory(modules) => modules,
+        ExposedModules::WithCategories(categories) => {
+            categories.values().flatten().cloned().collect()
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_extra_dependency, parse_local_package, parse_pin};
+    use pubgrub::range::Range;
+    use pubgrub::version::SemanticVersion as SemVer;
+    use pubgrub_dependency_provider_elm::project_config::Pkg;
+
+    #[test]
+    fn pinned_versions() {
+        assert_eq!(
+            parse_pin("elm/json@1.1.3").ok(),
+            Some((Pkg::new("elm", "json"), SemVer::new(1, 1, 3)))
+        );
+        assert!(parse_pin("elm/json").is_err());
+        assert!(parse_pin("json@1.1.3").is_err());
+        assert!(parse_pin("elm/@1.1.3").is_err());
+        assert!(parse_pin("elm/json@1.1").is_err());
+    }
+
+    #[test]
+    fn extra_dependencies() {
+        assert_eq!(
+            parse_extra_dependency("elm/random").ok(),
+            Some((Pkg::new("elm", "random"), Range::any()))
+        );
+        assert_eq!(
+            parse_extra_dependency("elm/random@1.0.0").ok(),
+            Some((
+                Pkg::new("elm", "random"),
+                Range::exact(SemVer::new(1, 0, 0))
+            ))
+        );
+        assert!(parse_extra_dependency("random").is_err());
+        assert!(parse_extra_dependency("elm/random@latest").is_err());
+    }
+
+    #[test]
+    fn local_packages() {
+        assert_eq!(
+            parse_local_package("elm/parser=vendor/parser").ok(),
+            Some((Pkg::new("elm", "parser"), "vendor/parser".into()))
+        );
+        assert!(parse_local_package("elm/parser").is_err());
+        assert!(parse_local_package("elm/parser=").is_err());
+        assert!(parse_local_package("parser=vendor/parser").is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! Compare the tests results of the current worktree with the ones at a given git ref.
+//!
+//! The given ref is checked out in a temporary git worktree,
+//! and the tests are run in both places before comparing each test outcome.
+
+use anyhow::Context;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::project::Project;
+use crate::summary::{RunSummary, Status};
+
+/// Differences between the tests outcomes of two runs.
+#[derive(Debug, Default, PartialEq)]
+pub struct Comparison {
+    pub newly_failing: Vec<String>,
+    pub newly_passing: Vec<String>,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// Run the tests in the current worktree and at the given git ref, then report the differences.
+/// Returns 1 if some tests are newly failing, 0 otherwise.
+pub fn main(
+    elm_home: &Path,
+    elm_project_root: &Path,
+    git_ref: &str,
+    make_options: crate::make::Options,
+    run_options: crate::run::Options,
+) -> anyhow::Result<i32> {
+    // Prints to stderr the current version
+    crate::utils::print_title();
+    let run_options = crate::run::Options {
+        quiet: true,
+        ..run_options
+    };
+
+    // Run the tests in the current worktree.
+    log::error!("Running tests in the current worktree ...");
+    let project = Project::from_dir(elm_project_root)?;
+    let current = run_summary(elm_home, &project, &make_options, &run_options)
+        .context("Failed to run the tests in the current worktree")?;
+
+    // Check out the git ref in a temporary worktree.
+    let git_root = git(
+        elm_project_root,
+        &["rev-parse", "--show-toplevel"],
+        "Failed to find the git repository of the project",
+    )?;
+    let git_root = crate::utils::absolute_path(git_root.trim())?;
+    let worktree = std::env::temp_dir().join(format!("elm-test-rs-diff-{}", std::process::id()));
+    let worktree_str = worktree.to_str().context(format!(
+        "Could not convert path into a String: {}",
+        worktree.display()
+    ))?;
+    git(
+        &git_root,
+        &["worktree", "add", "--detach", worktree_str, git_ref],
+        &format!("Failed to check out {} in a temporary worktree", git_ref),
+    )?;
+
+    // Run the tests at the git ref, and remove the temporary worktree whatever happens.
+    log::error!("Running tests at {} ...", git_ref);
+    let previous = elm_project_root
+        .strip_prefix(&git_root)
+        .context("The project is not inside its git repository")
+        .and_then(|relative_root| {
+            let ref_root = worktree.join(relative_root);
+            let ref_project = Project::from_dir(&ref_root)?;
+            let ref_make_options = crate::make::Options {
+                files: rebase_files(&make_options.files, elm_project_root, &ref_root)?,
+                ..make_options.clone()
+            };
+            run_summary(elm_home, &ref_project, &ref_make_options, &run_options)
+        })
+        .context(format!("Failed to run the tests at {}", git_ref));
+    git(
+        &git_root,
+        &["worktree", "remove", "--force", worktree_str],
+        "Failed to remove the temporary worktree",
+    )?;
+    let comparison = compare(&previous?, &current);
+
+    // Report the differences.
+    let sections = [
+        ("Newly failing tests", &comparison.newly_failing),
+        ("Newly passing tests", &comparison.newly_passing),
+        ("Added tests", &comparison.added),
+        ("Removed tests", &comparison.removed),
+    ];
+    for (title, tests) in sections.iter() {
+        println!("\n{} ({}):", title, tests.len());
+        for name in tests.iter() {
+            println!("    {}", name);
+        }
+    }
+    if comparison.newly_failing.is_empty() {
+        Ok(0)
+    } else {
+        Ok(1)
+    }
+}
+
+/// Run the tests of a project and read the summary of that run.
+/// The summary of a previous run is removed first, so that tests failing to compile
+/// are reported as such instead of being compared with stale results.
+fn run_summary(
+    elm_home: &Path,
+    project: &Project,
+    make_options: &crate::make::Options,
+    run_options: &crate::run::Options,
+) -> anyhow::Result<RunSummary> {
+    let summary_file = RunSummary::path(&crate::make::tests_root(project));
+    let _ = std::fs::remove_file(&summary_file);
+    crate::run::main_helper(elm_home, project, make_options, run_options)?;
+    if !summary_file.exists() {
+        anyhow::bail!("The tests did not run, check that they compile");
+    }
+    RunSummary::read(&summary_file)
+}
+
+/// Transform the tests files given as arguments into their counterpart in another worktree.
+fn rebase_files(files: &[String], from: &Path, to: &Path) -> anyhow::Result<Vec<String>> {
+    if files.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut rebased = Vec::new();
+    for path in crate::make::get_elm_modules_abs_paths(files)? {
+        let relative = path
+            .strip_prefix(from)
+            .context(format!("{} is not inside the project", path.display()))?;
+        let target: PathBuf = to.join(relative);
+        if target.exists() {
+            rebased.push(target.to_string_lossy().to_string());
+        }
+    }
+    Ok(rebased)
+}
+
+/// Run a git command and return its stdout.
+fn git(current_dir: &Path, args: &[&str], error_msg: &str) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(current_dir)
+        .output()
+        .context(format!("{}. Is git in your PATH?", error_msg))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{}:\n{}",
+            error_msg,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Compare the outcome of every test between two runs.
+pub fn compare(before: &RunSummary, after: &RunSummary) -> Comparison {
+    let by_name = |summary: &RunSummary| -> BTreeMap<String, Status> {
+        summary
+            .tests
+            .iter()
+            .map(|test| (test.name(), test.status))
+            .collect()
+    };
+    let before = by_name(before);
+    let after = by_name(after);
+    let mut comparison = Comparison::default();
+    for (name, status) in after.iter() {
+        match (before.get(name), status) {
+            (None, _) => comparison.added.push(name.clone()),
+            (Some(Status::Fail), Status::Fail) => (),
+            (Some(_), Status::Fail) => comparison.newly_failing.push(name.clone()),
+            (Some(Status::Fail), _) => comparison.newly_passing.push(name.clone()),
+            _ => (),
+        }
+    }
+    comparison.removed = before
+        .keys()
+        .filter(|name| !after.contains_key(*name))
+        .cloned()
+        .collect();
+    comparison
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare, Comparison};
+    use crate::summary::{RunSummary, Status, TestOutcome};
+
+    fn summary(tests: &[(&str, Status)]) -> RunSummary {
+        RunSummary {
+            exit_code: 0,
+            seed: 0,
+            fuzz_runs: 100,
+            tests: tests
+                .iter()
+                .map(|(name, status)| TestOutcome {
+                    labels: vec![name.to_string()],
+                    module: None,
+                    status: *status,
+                    duration: 0.0,
+                    heap_growth: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn compare_outcomes() {
+        let before = summary(&[
+            ("a", Status::Pass),
+            ("b", Status::Fail),
+            ("c", Status::Pass),
+            ("d", Status::Fail),
+        ]);
+        let after = summary(&[
+            ("a", Status::Fail),
+            ("b", Status::Pass),
+            ("d", Status::Fail),
+            ("e", Status::Pass),
+        ]);
+        assert_eq!(
+            compare(&before, &after),
+            Comparison {
+                newly_failing: vec!["a".to_string()],
+                newly_passing: vec!["b".to_string()],
+                added: vec!["e".to_string()],
+                removed: vec!["c".to_string()],
+            }
+        );
+    }
+}
@@ -0,0 +1,447 @@
+//! Run the examples written in documentation comments as tests.
+//!
+//! This is in the spirit of elm-verify-examples.
+//! Examples are code lines inside a doc comment `{-| ... -}` written either as
+//!
+//! ```elm
+//!     >>> add 1 2
+//!     3
+//! ```
+//!
+//! or in the elm-verify-examples style
+//!
+//! ```elm
+//!     add 1 2
+//!     --> 3
+//! ```
+//!
+//! where the expected value can also follow the expression on the same line,
+//! such as in a one-line doc comment `{-| add 1 2 --> 3 -}`.
+//! Imports written in the code of doc comments, such as `import Dict`,
+//! are added to the generated tests module.
+
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+
+use crate::include_template;
+use crate::project::Project;
+
+/// An example extracted from a doc comment.
+#[derive(Debug, PartialEq)]
+pub struct Example {
+    /// Line of the example in the source file (starting at 1).
+    pub line: usize,
+    /// The Elm expression to evaluate, potentially on multiple lines.
+    pub expression: String,
+    /// The Elm expression of the expected result.
+    pub expected: String,
+}
+
+/// Wrapper for the main_helper function with "watch" functionality.
+/// This will extract the examples, generate the corresponding tests modules,
+/// and run them like regular tests.
+pub fn main(
+    elm_home: &Path,
+    elm_project_root: &Path,
+    make_options: crate::make::Options,
+    run_options: crate::run::Options,
+) -> anyhow::Result<i32> {
+    // Prints to stderr the current version
+    crate::utils::print_title();
+
+    let mut project = Project::from_dir(elm_project_root)?;
+    if make_options.watch {
+        project.watch(|project| {
+            main_helper(elm_home, project, &make_options, &run_options).map(|_| ())
+        })?;
+        Ok(0)
+    } else {
+        main_helper(elm_home, &project, &make_options, &run_options)
+    }
+}
+
+/// Generate one tests module per source module containing examples,
+/// and run those as if they were the tests of the project.
+fn main_helper(
+    elm_home: &Path,
+    project: &Project,
+    make_options: &crate::make::Options,
+    run_options: &crate::run::Options,
+) -> anyhow::Result<i32> {
+    // Source modules to look into for examples.
+    let source_dirs = project.source_dirs();
+    let source_files: Vec<PathBuf> = if make_options.files.is_empty() {
+        source_dirs
+            .iter()
+            .flat_map(crate::make::elm_files_within)
+            .map(crate::utils::absolute_path)
+            .collect::<Result<_, _>>()?
+    } else {
+        crate::make::get_elm_modules_abs_paths(&make_options.files)?
+            .into_iter()
+            .collect()
+    };
+
+    // Generate the tests modules in a dedicated source directory.
+    let doc_tests_dir = project
+        .root_directory
+        .join("elm-stuff")
+        .join("tests-0.19.1")
+        .join("doc-tests");
+    if doc_tests_dir.exists() {
+        std::fs::remove_dir_all(&doc_tests_dir).context(format!(
+            "Could not clean the directory {}",
+            doc_tests_dir.display()
+        ))?;
+    }
+    log::info!("Extracting examples from documentation comments ...");
+    let mut generated_files = Vec::new();
+    for path in source_files.iter() {
+        let source =
+            std::fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+        let examples = extract_examples(&source);
+        if examples.is_empty() {
+            continue;
+        }
+        let module_name = crate::make::get_module_name(&source_dirs, path)?;
+        log::info!("Found {} examples in {}", examples.len(), module_name);
+        let doc_module_name = format!("DocTests.{}", module_name);
+        let doc_module_path = doc_module_name
+            .split('.')
+            .fold(doc_tests_dir.clone(), |dir, part| dir.join(part))
+            .with_extension("elm");
+        std::fs::create_dir_all(doc_module_path.parent().unwrap()) // unwrap is fine since it is in doc_tests_dir
+            .context(format!(
+                "Could not create the directory for {}",
+                doc_module_path.display()
+            ))?;
+        let mut imports: Vec<String> = Vec::new();
+        for import in std::iter::once(format!("import {} exposing (..)", module_name))
+            .chain(source_imports(&source))
+            .chain(doc_imports(&source))
+        {
+            if !imports.contains(&import) {
+                imports.push(import);
+            }
+        }
+        let tests: Vec<String> = examples.iter().map(example_test).collect();
+        crate::make::create_templated(
+            include_template!("DocTests.elm"),
+            &doc_module_path,
+            &[
+                ("{{ module_name }}", &doc_module_name),
+                ("{{ imports }}", &imports.join("\n")),
+                ("{{ source_module }}", &module_name),
+                ("{{ tests }}", &tests.join("\n        , ")),
+            ],
+        )
+        .context(format!("Failed to write {}", doc_module_path.display()))?;
+        generated_files.push(doc_module_path);
+    }
+
+    if generated_files.is_empty() {
+        anyhow::bail!("No example was found in the documentation comments of your source modules");
+    }
+
+    // Run the generated tests modules with the doc-tests directory as an additional source directory.
+    let mut doc_project = Project::from_dir(&project.root_directory)?;
+    doc_project.src_and_test_dirs.insert(doc_tests_dir);
+    let files = generated_files
+        .iter()
+        .map(|p| {
+            p.to_str().map(|s| s.to_string()).context(format!(
+                "Could not convert path into a String: {}",
+                p.display()
+            ))
+        })
+        .collect::<Result<_, _>>()?;
+    let doc_make_options = crate::make::Options {
+        files,
+        ..make_options.clone()
+    };
+    crate::run::main_helper(elm_home, &doc_project, &doc_make_options, run_options)
+}
+
+/// Generate the code of the test corresponding to one example.
+fn example_test(example: &Example) -> String {
+    let one_line: Vec<&str> = example.expression.lines().map(|l| l.trim()).collect();
+    let label = format!("line {}: {}", example.line, one_line.join(" "))
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"");
+    format!(
+        "Test.test \"{}\" <|\n            \\() ->\n                Expect.equal\n                    ({})\n                    ({})",
+        label,
+        indent(&example.expected, 21),
+        indent(&example.expression, 21),
+    )
+}
+
+/// Indent all lines except the first one.
+fn indent(code: &str, spaces: usize) -> String {
+    let padding = " ".repeat(spaces);
+    code.lines()
+        .collect::<Vec<_>>()
+        .join(&format!("\n{}", padding))
+}
+
+/// Retrieve the import lines of a source module,
+/// except the ones for Test and Expect already present in the template.
+fn source_imports(source: &str) -> Vec<String> {
+    let mut imports: Vec<String> = Vec::new();
+    let mut in_import = false;
+    for line in source.lines() {
+        if line.starts_with("import ") {
+            imports.push(line.to_string());
+            in_import = true;
+        } else if in_import && line.starts_with(char::is_whitespace) && !line.trim().is_empty() {
+            // Continuation of a multi-line import.
+            if let Some(last) = imports.last_mut() {
+                last.push(' ');
+                last.push_str(line.trim());
+            }
+        } else {
+            in_import = false;
+        }
+    }
+    imports
+        .into_iter()
+        .filter(|import| {
+            let module = import.split_whitespace().nth(1).unwrap_or("");
+            module != "Test" && module != "Expect"
+        })
+        .collect()
+}
+
+/// Retrieve the imports written in the code of the doc comments of a source module,
+/// which the examples may need.
+fn doc_imports(source: &str) -> Vec<String> {
+    doc_comments(source)
+        .into_iter()
+        .flat_map(|(_, content)| content.lines().skip(1))
+        .map(|line| line.trim())
+        .filter(|line| line.starts_with("import "))
+        .map(|line| line.to_string())
+        .collect()
+}
+
+/// Extract all examples of all doc comments in the source of an Elm module.
+pub fn extract_examples(source: &str) -> Vec<Example> {
+    doc_comments(source)
+        .into_iter()
+        .flat_map(|(line, content)| examples_in_comment(line, content))
+        .collect()
+}
+
+/// Find all doc comments `{-| ... -}` and the line at which their content starts.
+fn doc_comments(source: &str) -> Vec<(usize, &str)> {
+    let mut comments = Vec::new();
+    let mut rest = source;
+    let mut offset = 0;
+    while let Some(start) = rest.find("{-|") {
+        let content_start = start + 3;
+        let mut depth = 1;
+        let mut index = content_start;
+        while index < rest.len() && depth > 0 {
+            if rest[index..].starts_with("{-") {
+                depth += 1;
+                index += 2;
+            } else if rest[index..].starts_with("-}") {
+                depth -= 1;
+                index += 2;
+            } else {
+                index += 1;
+                while index < rest.len() && !rest.is_char_boundary(index) {
+                    index += 1;
+                }
+            }
+        }
+        if depth > 0 {
+            break;
+        }
+        let content = &rest[content_start..index - 2];
+        let line = 1 + source[..offset + content_start].matches('\n').count();
+        comments.push((line, content));
+        offset += index;
+        rest = &rest[index..];
+    }
+    comments
+}
+
+/// Extract the examples of one doc comment.
+/// The `first_line` argument is the line at which the comment content starts.
+/// The text following `{-|` is a summary, unless the whole comment is on one line.
+fn examples_in_comment(first_line: usize, content: &str) -> Vec<Example> {
+    let mut examples = Vec::new();
+    // Code lines accumulated since the last blank line,
+    // which may be the expression of an elm-verify-examples "-->" example.
+    let mut code: Vec<(usize, &str)> = Vec::new();
+    let one_line = content.lines().count() == 1;
+    let mut lines = content.lines().enumerate().peekable();
+    while let Some((i, line)) = lines.next() {
+        let line_nb = first_line + i;
+        let trimmed = line.trim();
+        let is_code = line.starts_with(char::is_whitespace) && (i > 0 || one_line);
+        if !is_code || trimmed.is_empty() || trimmed.starts_with("import ") {
+            code.clear();
+        } else if let Some((expression, expected)) = trimmed
+            .rsplit_once("-->")
+            .filter(|(expression, _)| !expression.trim().is_empty() && !trimmed.starts_with(">>>"))
+        {
+            // elm-verify-examples style on a single line.
+            examples.push(Example {
+                line: line_nb,
+                expression: expression.trim().to_string(),
+                expected: expected.trim().to_string(),
+            });
+            code.clear();
+        } else if let Some(expression) = trimmed.strip_prefix(">>>") {
+            // Doctest style: the expected value is on the following code lines.
+            code.clear();
+            let mut expected = Vec::new();
+            while let Some((_, next)) = lines.peek() {
+                let next_trimmed = next.trim();
+                if next_trimmed.is_empty() || next_trimmed.starts_with(">>>") {
+                    break;
+                }
+                expected.push(next_trimmed);
+                lines.next();
+            }
+            if !expected.is_empty() {
+                examples.push(Example {
+                    line: line_nb,
+                    expression: expression.trim().to_string(),
+                    expected: expected.join("\n"),
+                });
+            }
+        } else if let Some(expected) = trimmed.strip_prefix("-->") {
+            // elm-verify-examples style: the expression is on the previous code lines.
+            if !code.is_empty() {
+                let min_indent = code
+                    .iter()
+                    .map(|(_, l)| l.len() - l.trim_start().len())
+                    .min()
+                    .unwrap_or(0); // unwrap_or is fine since code is not empty
+                let expression: Vec<&str> = code
+                    .iter()
+                    .map(|(_, l)| l[min_indent..].trim_end())
+                    .collect();
+                examples.push(Example {
+                    line: code[0].0,
+                    expression: expression.join("\n"),
+                    expected: expected.trim().to_string(),
+                });
+            }
+            code.clear();
+        } else {
+            code.push((line_nb, line));
+        }
+    }
+    examples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{doc_imports, extract_examples, Example};
+
+    #[test]
+    fn doctest_style() {
+        let source = r#"
+module Math exposing (add)
+
+{-| Add two numbers.
+
+    >>> add 1 2
+    3
+
+-}
+add : Int -> Int -> Int
+add a b =
+    a + b
+"#;
+        assert_eq!(
+            extract_examples(source),
+            vec![Example {
+                line: 6,
+                expression: "add 1 2".to_string(),
+                expected: "3".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn verify_examples_style() {
+        let source = r#"
+{-| Some {- nested -} comment.
+
+    import Math
+
+    Math.add
+        1
+        2
+    --> 3
+
+    add 0 0 --> 0
+-}
+"#;
+        assert_eq!(
+            extract_examples(source),
+            vec![
+                Example {
+                    line: 6,
+                    expression: "Math.add\n    1\n    2".to_string(),
+                    expected: "3".to_string(),
+                },
+                Example {
+                    line: 11,
+                    expression: "add 0 0".to_string(),
+                    expected: "0".to_string(),
+                }
+            ]
+        );
+        assert_eq!(doc_imports(source), vec!["import Math".to_string()]);
+    }
+
+    #[test]
+    fn single_line_forms() {
+        let source = r#"
+{-| add 1 2 --> 3 -}
+add =
+    (+)
+
+{-| Summary --> not an example.
+    import Dict
+    Dict.size Dict.empty
+    --> 0
+-}
+"#;
+        assert_eq!(
+            extract_examples(source),
+            vec![
+                Example {
+                    line: 2,
+                    expression: "add 1 2".to_string(),
+                    expected: "3".to_string(),
+                },
+                Example {
+                    line: 8,
+                    expression: "Dict.size Dict.empty".to_string(),
+                    expected: "0".to_string(),
+                }
+            ]
+        );
+        assert_eq!(doc_imports(source), vec!["import Dict".to_string()]);
+    }
+
+    #[test]
+    fn no_example_outside_doc_comments() {
+        let source = r#"
+{- Not a doc comment
+
+    >>> add 1 2
+    3
+-}
+-- >>> add 1 2
+"#;
+        assert_eq!(extract_examples(source), vec![]);
+    }
+}
@@ -0,0 +1,158 @@
+//! Compile the example applications of a package against its local source.
+//!
+//! Every directory with an elm.json inside `examples/` is an example application.
+//! For each of them, an elm.json is generated in `elm-stuff/tests-0.19.1/examples/`,
+//! replacing the package dependency by the package source directory,
+//! and every module defining a `main` is compiled.
+
+use anyhow::Context;
+use pubgrub_dependency_provider_elm::project_config::ProjectConfig;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::project::Project;
+
+/// Compile all examples and report which ones break.
+/// Returns 0 if all examples compile, 1 otherwise.
+pub fn main(
+    elm_home: &Path,
+    elm_project_root: &Path,
+    make_options: crate::make::Options,
+) -> anyhow::Result<i32> {
+    // Prints to stderr the current version
+    crate::utils::print_title();
+
+    let project = Project::from_dir(elm_project_root)?;
+    let pkg_config = match &project.config {
+        ProjectConfig::Package(pkg_config) => pkg_config,
+        ProjectConfig::Application(_) => {
+            anyhow::bail!("The examples subcommand is only available for packages")
+        }
+    };
+    let package_src = project.root_directory.join("src");
+    let examples_dir = project.root_directory.join("examples");
+    let example_roots: Vec<PathBuf> = WalkDir::new(&examples_dir)
+        .follow_links(true)
+        .into_iter()
+        .filter_entry(|e| e.file_name() != "elm-stuff" && e.file_name() != "node_modules")
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name() == "elm.json")
+        .filter_map(|e| e.path().parent().map(|p| p.to_path_buf()))
+        .collect();
+    if example_roots.is_empty() {
+        anyhow::bail!(
+            "No example application was found in {}",
+            examples_dir.display()
+        );
+    }
+
+    let generated_root = project
+        .root_directory
+        .join("elm-stuff")
+        .join("tests-0.19.1")
+        .join("examples");
+    let mut reports = Vec::new();
+    for (i, example_root) in example_roots.iter().enumerate() {
+        let example_name = example_root
+            .strip_prefix(&project.root_directory)
+            .unwrap_or(example_root)
+            .display()
+            .to_string();
+        let elm_json_str = std::fs::read_to_string(example_root.join("elm.json"))
+            .context(format!("Unable to read the elm.json of {}", example_name))?;
+        let example_config = match crate::project::parse_config(&elm_json_str)
+            .context(format!("Invalid elm.json in {}", example_name))?
+        {
+            ProjectConfig::Application(app_config) => app_config,
+            ProjectConfig::Package(_) => {
+                log::error!("Skipping {} since it is not an application", example_name);
+                continue;
+            }
+        };
+
+        // Source directories of the example, and the package source replacing the package itself.
+        let mut example_src_dirs: Vec<PathBuf> = example_config
+            .source_directories
+            .iter()
+            .map(|src| crate::utils::absolute_path(example_root.join(src)))
+            .collect::<Result<_, _>>()
+            .context(format!(
+                "It seems source directories of {} do not all exist",
+                example_name
+            ))?;
+        let main_modules: Vec<PathBuf> = example_src_dirs
+            .iter()
+            .filter(|dir| **dir != package_src)
+            .flat_map(crate::make::elm_files_within)
+            .filter(|path| defines_main(path))
+            .collect();
+        if !example_src_dirs.contains(&package_src) {
+            example_src_dirs.push(package_src.clone());
+        }
+
+        // Generate the elm.json of the example.
+        let generated_dir = generated_root.join(i.to_string());
+        std::fs::create_dir_all(&generated_dir).context(format!(
+            "Could not create the directory {}",
+            generated_dir.display()
+        ))?;
+        let relative_src_dirs = example_src_dirs
+            .iter()
+            .map(|path| crate::utils::relative_source_dir(path, &generated_dir))
+            .collect::<Vec<PathBuf>>();
+        let config = crate::deps::solve_example(
+            elm_home,
+            &make_options.network,
+            &make_options.connectivity,
+            pkg_config,
+            &example_config,
+            &relative_src_dirs,
+        )
+        .context(format!("Failed to solve dependencies of {}", example_name))?;
+        let config_str = serde_json::to_string(&ProjectConfig::Application(config))
+            .context("Failed to convert to JSON the elm.json of the example")?;
+        std::fs::write(generated_dir.join("elm.json"), config_str)
+            .context("Unable to write the generated elm.json of the example")?;
+
+        // Compile every main module of the example.
+        for main_module in main_modules.iter() {
+            log::error!("Compiling {} ...", main_module.display());
+            let success = crate::make::compile(
+                elm_home,
+                &make_options.network,
+                &generated_dir,
+                &make_options.compiler,
+                generated_dir.join("main.js"),
+                &make_options.report,
+                [main_module],
+            )?
+            .success();
+            let module_name = main_module
+                .strip_prefix(&project.root_directory)
+                .unwrap_or(main_module)
+                .display()
+                .to_string();
+            reports.push((module_name, success));
+        }
+    }
+
+    // Report the examples that failed to compile.
+    let failures = reports.iter().filter(|(_, success)| !success).count();
+    log::error!("\nCompiled example modules:\n");
+    for (module, success) in reports.iter() {
+        log::error!("  {} {}", if *success { "✓" } else { "✗" }, module);
+    }
+    log::error!(
+        "\n{} out of {} example modules compiled successfully\n",
+        reports.len() - failures,
+        reports.len()
+    );
+    Ok(if failures == 0 { 0 } else { 1 })
+}
+
+/// Check if an elm file defines a top-level `main` value.
+fn defines_main(path: &Path) -> bool {
+    std::fs::read_to_string(path)
+        .map(|source| crate::parser::defines_value(&source, "main"))
+        .unwrap_or(false)
+}
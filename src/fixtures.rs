@@ -0,0 +1,242 @@
+//! Fixtures available to the tests: HTTP responses and files.
+//!
+//! The `--http-fixtures` file is a JSON list of fixtures such as
+//! `{ "method": "GET", "url": "https://example.com/api/*", "status": 200, "body": "..." }`.
+//! The method is optional, and a url ending with `*` matches every url starting with it.
+//! The runners then replace their no-op `XMLHttpRequest` polyfill with `templates/http_fixtures.js`,
+//! answering the `XMLHttpRequest` and `fetch` requests with the first matching fixture,
+//! and failing the other ones like a network error.
+//!
+//! Tests read fixture files with `ElmTestRs.Fixture.read`, generated from `templates/Fixture.elm`.
+//! Only the files of the `tests/fixtures/` directory can be read:
+//! those read with a literal path are embedded in the compiled tests,
+//! and the Elm function reading them is kernel-patched to look them up.
+
+use anyhow::Context;
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Read and check the fixtures file, returning the fixtures as JSON for the polyfills.
+pub fn load(file: &Path) -> anyhow::Result<String> {
+    let contents = std::fs::read_to_string(file).context(format!(
+        "Failed to read the HTTP fixtures {}",
+        file.display()
+    ))?;
+    let fixtures = parse(&contents).context(format!("Invalid HTTP fixtures {}", file.display()))?;
+    Ok(fixtures.to_string())
+}
+
+/// Parse a list of fixtures, checking the types of their fields.
+fn parse(contents: &str) -> anyhow::Result<Value> {
+    let fixtures: Value = serde_json::from_str(contents).context("Invalid JSON")?;
+    let list = fixtures
+        .as_array()
+        .context("The fixtures must be a JSON list")?;
+    for (index, fixture) in list.iter().enumerate() {
+        let check = |key: &str, valid: fn(&Value) -> bool, expected: &str| {
+            if fixture[key].is_null() || valid(&fixture[key]) {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!(
+                    "The {} of fixture {} must be {}",
+                    key,
+                    index,
+                    expected
+                ))
+            }
+        };
+        if !fixture["url"].is_string() {
+            anyhow::bail!("Fixture {} has no url", index);
+        }
+        check("method", Value::is_string, "a string")?;
+        check("status", |v| v.as_u64().is_some(), "a number")?;
+        check("statusText", Value::is_string, "a string")?;
+        check("headers", Value::is_object, "an object")?;
+    }
+    Ok(fixtures)
+}
+
+/// Embed the fixture files read by the tests with `ElmTestRs.Fixture.read` in the compiled tests.
+/// Only the files read with a literal path are embedded, or all of them if some path is computed.
+/// Files that are not UTF-8 text are skipped with a warning, and tests cannot read them.
+pub fn embed_files(elm_js: &str, fixtures_dir: &Path) -> anyhow::Result<String> {
+    if !elm_js.contains("$author$project$ElmTestRs$Fixture$contentsReplaceMe___") {
+        return Ok(elm_js.to_string());
+    }
+    let readable = readable_files(fixtures_dir).context("Failed to list the fixture files")?;
+    let read: Vec<&String> = match referenced_paths(elm_js) {
+        None => readable.keys().collect(),
+        Some(paths) => {
+            for path in paths.iter().filter(|path| !readable.contains_key(*path)) {
+                log::warn!(
+                    "Tests read \"{}\", which is not a file of {}",
+                    path,
+                    fixtures_dir.display()
+                );
+            }
+            readable.keys().filter(|key| paths.contains(*key)).collect()
+        }
+    };
+    let mut files = Map::new();
+    for key in read {
+        let file = &readable[key];
+        let bytes = std::fs::read(file).context(format!("Failed to read {}", file.display()))?;
+        match String::from_utf8(bytes) {
+            Ok(contents) => {
+                files.insert(key.clone(), contents.into());
+            }
+            Err(_) => log::warn!(
+                "The fixture file {} is not UTF-8 text, tests cannot read it",
+                file.display()
+            ),
+        }
+    }
+    patch_contents(elm_js, &Value::Object(files))
+}
+
+/// Literal paths given to `ElmTestRs.Fixture.read` in the compiled tests,
+/// or `None` if some path is computed, or the function passed around, so every file may be read.
+fn referenced_paths(elm_js: &str) -> Option<BTreeSet<String>> {
+    let mut paths = BTreeSet::new();
+    for rest in elm_js
+        .split("$author$project$ElmTestRs$Fixture$read")
+        .skip(1)
+    {
+        if rest.trim_start().starts_with('=') {
+            // Definition of the function
+            continue;
+        }
+        let path = rest.strip_prefix("('")?;
+        let end = path.find('\'')?;
+        if path[..end].contains('\\') || !path[end..].starts_with("')") {
+            return None;
+        }
+        paths.insert(path[..end].to_string());
+    }
+    Some(paths)
+}
+
+/// Files of the fixtures directory that tests are allowed to read,
+/// by their path relative to it with `/` separators.
+/// Symbolic links are followed only when they stay within the directory.
+fn readable_files(dir: &Path) -> anyhow::Result<BTreeMap<String, PathBuf>> {
+    let mut files = BTreeMap::new();
+    if !dir.is_dir() {
+        return Ok(files);
+    }
+    let root = dir
+        .canonicalize()
+        .context(format!("Failed to resolve {}", dir.display()))?;
+    for entry in WalkDir::new(dir).follow_links(true) {
+        let entry = entry.context(format!("Failed to list the files of {}", dir.display()))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let target = entry
+            .path()
+            .canonicalize()
+            .context(format!("Failed to resolve {}", entry.path().display()))?;
+        if !target.starts_with(&root) {
+            log::warn!(
+                "Ignoring the fixture file {}, which links outside of {}",
+                entry.path().display(),
+                dir.display()
+            );
+            continue;
+        }
+        let relative = entry.path().strip_prefix(dir)?;
+        let key = crate::utils::elm_json_path(relative).context(format!(
+            "Could not convert path into a String: {}",
+            relative.display()
+        ))?;
+        files.insert(key, entry.path().to_path_buf());
+    }
+    Ok(files)
+}
+
+/// Contents of the files of a directory, by their path relative to it with `/` separators.
+/// There are none if the directory does not exist.
+pub fn read_files(dir: &Path) -> anyhow::Result<Map<String, Value>> {
+    let mut files = Map::new();
+    if !dir.is_dir() {
+        return Ok(files);
+    }
+    for entry in WalkDir::new(dir).follow_links(true) {
+        let entry = entry.context(format!("Failed to list the files of {}", dir.display()))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(dir)?;
+        let key = crate::utils::elm_json_path(relative).context(format!(
+            "Could not convert path into a String: {}",
+            relative.display()
+        ))?;
+        let contents = std::fs::read_to_string(entry.path())
+            .context(format!("Failed to read {}", entry.path().display()))?;
+        files.insert(key, contents.into());
+    }
+    Ok(files)
+}
+
+/// Replace the body of the Elm function giving the contents of a fixture
+/// by a lookup in the embedded files.
+fn patch_contents(elm_js: &str, files: &Value) -> anyhow::Result<String> {
+    let contents_definition = Regex::new(
+        r#"(?m)^var\s+\$author\$project\$ElmTestRs\$Fixture\$contentsReplaceMe___\s*=\s*function\s*\((\w+)\)\s*\{"#,
+    )?;
+    let elm_js = contents_definition.replace(
+        elm_js,
+        "$0 if (Object.prototype.hasOwnProperty.call(__elmTestFixtures, ${1})) { return $$elm$$core$$Maybe$$Just(__elmTestFixtures[${1}]); }",
+    );
+    Ok(format!("const __elmTestFixtures = {};\n{}", files, elm_js))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, patch_contents, referenced_paths};
+    use serde_json::json;
+
+    #[test]
+    fn patch_fixture_contents() {
+        let elm_js = "var $author$project$ElmTestRs$Fixture$contentsReplaceMe___ = function (_v0) {\n\treturn $elm$core$Maybe$Nothing;\n};";
+        let patched = patch_contents(elm_js, &json!({ "a.txt": "A" })).unwrap();
+        assert_eq!(
+            patched,
+            "const __elmTestFixtures = {\"a.txt\":\"A\"};\nvar $author$project$ElmTestRs$Fixture$contentsReplaceMe___ = function (_v0) { if (Object.prototype.hasOwnProperty.call(__elmTestFixtures, _v0)) { return $elm$core$Maybe$Just(__elmTestFixtures[_v0]); }\n\treturn $elm$core$Maybe$Nothing;\n};"
+        );
+    }
+
+    #[test]
+    fn parse_fixtures() {
+        let fixtures = r#"[
+            { "url": "https://example.com/users/*", "body": [1, 2] },
+            { "method": "POST", "url": "https://example.com/login", "status": 401, "headers": { "x-a": "b" } }
+        ]"#;
+        assert_eq!(parse(fixtures).unwrap().as_array().unwrap().len(), 2);
+        assert!(parse("[]").is_ok());
+        assert!(parse(r#"{ "url": "https://example.com" }"#).is_err());
+        assert!(parse(r#"[{ "body": "no url" }]"#).is_err());
+        assert!(parse(r#"[{ "url": "https://example.com", "status": "200" }]"#).is_err());
+        assert!(parse(r#"[{ "url": "https://example.com", "headers": [] }]"#).is_err());
+    }
+
+    #[test]
+    fn find_referenced_paths() {
+        let elm_js = "var $author$project$ElmTestRs$Fixture$read = function (path) {};\nvar a = $author$project$ElmTestRs$Fixture$read('users/alice.json');\nvar b = $author$project$ElmTestRs$Fixture$read('b.txt');";
+        assert_eq!(
+            referenced_paths(elm_js)
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec!["b.txt".to_string(), "users/alice.json".to_string()]
+        );
+        let computed = "var a = $author$project$ElmTestRs$Fixture$read('users/' + name);";
+        assert_eq!(referenced_paths(computed), None);
+        let passed =
+            "var a = A2($elm$core$List$map, $author$project$ElmTestRs$Fixture$read, names);";
+        assert_eq!(referenced_paths(passed), None);
+    }
+}
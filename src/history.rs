@@ -0,0 +1,215 @@
+//! History of tests runs.
+//!
+//! The summary of every run is appended to `elm-stuff/tests-0.19.1/history.jsonl`,
+//! with one JSON object per line.
+//! The history subcommand then reads it to find slow or unstable tests,
+//! and the supervisor uses it to dispatch the slowest tests first.
+
+use anyhow::Context;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::summary::{RunSummary, Status};
+
+/// Maximum number of runs kept in the history.
+const MAX_RUNS: usize = 200;
+
+/// A recorded run.
+#[derive(Debug, PartialEq)]
+pub struct Run {
+    /// Time of the run, in seconds since the Unix epoch.
+    pub time: u64,
+    pub summary: RunSummary,
+}
+
+/// Path of the history file.
+pub fn path(tests_root: &Path) -> PathBuf {
+    tests_root.join("history.jsonl")
+}
+
+/// Append the summary of a run to the history.
+pub fn record(tests_root: &Path, summary: &RunSummary) -> anyhow::Result<()> {
+    let history_file = path(tests_root);
+    let time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut entry = summary.to_json();
+    entry["time"] = serde_json::Value::from(time);
+    let mut lines: Vec<String> = std::fs::read_to_string(&history_file)
+        .unwrap_or_default()
+        .lines()
+        .map(|l| l.to_string())
+        .collect();
+    lines.push(serde_json::to_string(&entry).context("Failed to convert the run summary to JSON")?);
+    let first_kept = lines.len().saturating_sub(MAX_RUNS);
+    let mut content = lines[first_kept..].join("\n");
+    content.push('\n');
+    std::fs::write(&history_file, content)
+        .context(format!("Failed to write {}", history_file.display()))
+}
+
+/// Read all runs recorded in the history, from the oldest to the most recent.
+pub fn read(tests_root: &Path) -> anyhow::Result<Vec<Run>> {
+    let history_file = path(tests_root);
+    let content = std::fs::read_to_string(&history_file).context(
+        "No tests run was recorded yet. The history is filled every time you run your tests",
+    )?;
+    content
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|line| {
+            let value: serde_json::Value =
+                serde_json::from_str(line).context("Invalid line in the history file")?;
+            Ok(Run {
+                time: value["time"].as_u64().unwrap_or(0),
+                summary: RunSummary::from_json(&value)?,
+            })
+        })
+        .collect()
+}
+
+/// Print the slowest tests, the most frequently failing tests,
+/// and the duration trend of the recorded runs.
+pub fn main(elm_project_root: &Path, top: usize) -> anyhow::Result<()> {
+    let tests_root = elm_project_root.join("elm-stuff").join("tests-0.19.1");
+    let runs = read(&tests_root)?;
+    println!("{} recorded runs\n", runs.len());
+
+    println!("Slowest tests (average duration):\n");
+    for (name, duration) in slowest(&runs).into_iter().take(top) {
+        println!("{:>10.1} ms  {}", duration, name);
+    }
+
+    println!("\nMost frequently failing tests:\n");
+    let failing = most_failing(&runs);
+    if failing.is_empty() {
+        println!("    No test failed in the recorded runs");
+    }
+    for (name, failures, count) in failing.into_iter().take(top) {
+        println!("{:>6} / {:<4} {}", failures, count, name);
+    }
+
+    println!("\nDuration trend (most recent runs last):\n");
+    let now = runs.last().map(|r| r.time).unwrap_or(0);
+    for run in runs.iter().skip(runs.len().saturating_sub(top)) {
+        println!(
+            "{:>10.1} ms  {} tests, {} failed  ({} ago)",
+            run.summary.duration(),
+            run.summary.tests.len(),
+            run.summary.count(Status::Fail),
+            human_duration(now.saturating_sub(run.time)),
+        );
+    }
+    Ok(())
+}
+
+/// Average duration of every test, the slowest first.
+pub fn slowest(runs: &[Run]) -> Vec<(String, f64)> {
+    let mut durations: HashMap<String, (f64, usize)> = HashMap::new();
+    for test in runs.iter().flat_map(|r| r.summary.tests.iter()) {
+        let entry = durations.entry(test.name()).or_insert((0.0, 0));
+        entry.0 += test.duration;
+        entry.1 += 1;
+    }
+    let mut averages: Vec<(String, f64)> = durations
+        .into_iter()
+        .map(|(name, (total, count))| (name, total / count as f64))
+        .collect();
+    averages.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    averages
+}
+
+/// Average duration of every test in the recorded runs, by name, for the supervisor.
+/// It is empty when no run was recorded yet.
+pub fn durations(tests_root: &Path) -> serde_json::Value {
+    let runs = read(tests_root).unwrap_or_default();
+    let mut durations = serde_json::Map::new();
+    for (name, duration) in slowest(&runs) {
+        durations.insert(name, duration.into());
+    }
+    serde_json::Value::Object(durations)
+}
+
+/// Number of failures and number of runs of every test that failed at least once,
+/// the most frequently failing first.
+pub fn most_failing(runs: &[Run]) -> Vec<(String, usize, usize)> {
+    let mut counts: HashMap<String, (usize, usize)> = HashMap::new();
+    for test in runs.iter().flat_map(|r| r.summary.tests.iter()) {
+        let entry = counts.entry(test.name()).or_insert((0, 0));
+        if test.status == Status::Fail {
+            entry.0 += 1;
+        }
+        entry.1 += 1;
+    }
+    let mut failing: Vec<(String, usize, usize)> = counts
+        .into_iter()
+        .filter(|(_, (failures, _))| *failures > 0)
+        .map(|(name, (failures, count))| (name, failures, count))
+        .collect();
+    failing.sort_by(|a, b| {
+        (b.1 as f64 / b.2 as f64)
+            .partial_cmp(&(a.1 as f64 / a.2 as f64))
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.0.cmp(&b.0))
+    });
+    failing
+}
+
+fn human_duration(seconds: u64) -> String {
+    match seconds {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}min", s / 60),
+        s if s < 86400 => format!("{}h", s / 3600),
+        s => format!("{} days", s / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{most_failing, slowest, Run};
+    use crate::summary::{RunSummary, Status, TestOutcome};
+
+    fn run(tests: &[(&str, Status, f64)]) -> Run {
+        Run {
+            time: 0,
+            summary: RunSummary {
+                exit_code: 0,
+                seed: 0,
+                fuzz_runs: 100,
+                tests: tests
+                    .iter()
+                    .map(|(name, status, duration)| TestOutcome {
+                        labels: vec!["Tests".to_string(), name.to_string()],
+                        module: None,
+                        status: *status,
+                        duration: *duration,
+                        heap_growth: None,
+                    })
+                    .collect(),
+            },
+        }
+    }
+
+    #[test]
+    fn aggregate_runs() {
+        let runs = vec![
+            run(&[("a", Status::Pass, 10.0), ("b", Status::Fail, 1.0)]),
+            run(&[("a", Status::Fail, 30.0), ("b", Status::Fail, 3.0)]),
+        ];
+        assert_eq!(
+            slowest(&runs),
+            vec![
+                ("Tests > a".to_string(), 20.0),
+                ("Tests > b".to_string(), 2.0)
+            ]
+        );
+        assert_eq!(
+            most_failing(&runs),
+            vec![
+                ("Tests > b".to_string(), 2, 2),
+                ("Tests > a".to_string(), 1, 2)
+            ]
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! Graph of the imports between the modules of the project.
+//!
+//! It tells which modules depend on a given one, directly or not,
+//! to know which tests may be impacted by a change.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::parse_cache::Cache;
+use crate::project::Project;
+
+/// Modules of the source and tests directories of a project, with their imports.
+/// Imports of modules from packages are kept, but those modules are not part of the graph.
+#[derive(Debug)]
+pub struct ImportGraph {
+    /// Modules imported by each module of the project.
+    imports: HashMap<String, Vec<String>>,
+    /// Module name of each file of the project.
+    modules: HashMap<PathBuf, String>,
+}
+
+impl ImportGraph {
+    /// Parse the imports of all the modules in the source and tests directories of the project,
+    /// with the parse cache so that only the modules which changed are parsed again.
+    /// Files whose module name cannot be guessed from their path, or which cannot be read, are ignored.
+    pub fn build(project: &Project, parse_cache: &mut Cache) -> ImportGraph {
+        let mut imports = HashMap::new();
+        let mut modules = HashMap::new();
+        for dir in &project.src_and_test_dirs {
+            for path in crate::make::elm_files_within(dir) {
+                let module = match crate::make::get_module_name(&project.src_and_test_dirs, &path) {
+                    Ok(module) => module,
+                    Err(e) => {
+                        log::debug!("Not in the imports graph: {:#}", e);
+                        continue;
+                    }
+                };
+                let module_imports = parse_cache.parse(&path).imports;
+                if parse_cache.is_unreadable(&path) {
+                    log::debug!("Not in the imports graph: {}", path.display());
+                    continue;
+                }
+                imports.insert(module.clone(), module_imports);
+                modules.insert(path, module);
+            }
+        }
+        ImportGraph { imports, modules }
+    }
+
+    /// Update the module of the given file after it changed, with the parse cache,
+    /// instead of parsing the imports of all the modules again.
+    /// A deleted or unreadable file leaves the graph.
+    pub fn update(&mut self, project: &Project, parse_cache: &mut Cache, path: &Path) {
+        if let Some(module) = self.modules.remove(path) {
+            self.imports.remove(&module);
+        }
+        if !path.is_file() {
+            return;
+        }
+        let module = match crate::make::get_module_name(&project.src_and_test_dirs, path) {
+            Ok(module) => module,
+            Err(e) => {
+                log::debug!("Not in the imports graph: {:#}", e);
+                return;
+            }
+        };
+        parse_cache.invalidate(path);
+        let module_imports = parse_cache.parse(path).imports;
+        if parse_cache.is_unreadable(path) {
+            log::debug!("Not in the imports graph: {}", path.display());
+            return;
+        }
+        self.imports.insert(module.clone(), module_imports);
+        self.modules.insert(path.to_path_buf(), module);
+    }
+
+    /// Files of the project, with the name of the module they define.
+    pub fn modules(&self) -> impl Iterator<Item = (&Path, &str)> {
+        self.modules
+            .iter()
+            .map(|(path, module)| (path.as_path(), module.as_str()))
+    }
+
+    /// Modules imported by the given one, including modules of packages.
+    pub fn imports(&self, module: &str) -> &[String] {
+        self.imports
+            .get(module)
+            .map_or(&[], |imports| imports.as_slice())
+    }
+
+    /// Name of the module defined in the given file, if it is part of the project.
+    pub fn module(&self, path: &Path) -> Option<&str> {
+        self.modules.get(path).map(|m| m.as_str())
+    }
+
+    /// File of the given module, if it is part of the project.
+    pub fn path(&self, module: &str) -> Option<&Path> {
+        self.modules
+            .iter()
+            .find(|(_, m)| m.as_str() == module)
+            .map(|(path, _)| path.as_path())
+    }
+
+    /// Modules of the project imported by the given one, directly or transitively,
+    /// including itself.
+    pub fn dependencies(&self, module: &str) -> BTreeSet<String> {
+        let mut dependencies = BTreeSet::new();
+        let mut to_visit = vec![module.to_string()];
+        while let Some(current) = to_visit.pop() {
+            if !self.imports.contains_key(&current) || !dependencies.insert(current.clone()) {
+                continue;
+            }
+            to_visit.extend(self.imports[&current].iter().cloned());
+        }
+        dependencies
+    }
+
+    /// Modules depending on the given one, directly or transitively, including itself.
+    pub fn dependents(&self, module: &str) -> BTreeSet<String> {
+        let mut dependents = BTreeSet::new();
+        let mut to_visit = vec![module.to_string()];
+        while let Some(current) = to_visit.pop() {
+            if !dependents.insert(current.clone()) {
+                continue;
+            }
+            for (other, other_imports) in self.imports.iter() {
+                if other_imports.contains(&current) && !dependents.contains(other) {
+                    to_visit.push(other.clone());
+                }
+            }
+        }
+        dependents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ImportGraph;
+    use std::collections::{BTreeSet, HashMap};
+
+    #[test]
+    fn transitive_dependents() {
+        let imports: HashMap<String, Vec<String>> = vec![
+            ("Parser", vec!["Lexer", "Dict"]),
+            ("Lexer", vec!["Char"]),
+            ("ParserTests", vec!["Parser", "Test"]),
+            ("LexerTests", vec!["Lexer", "Test"]),
+            ("Unrelated", vec!["Parser"]),
+            ("OtherTests", vec!["Test"]),
+        ]
+        .into_iter()
+        .map(|(m, i)| {
+            (
+                m.to_string(),
+                i.into_iter().map(|s| s.to_string()).collect(),
+            )
+        })
+        .collect();
+        let graph = ImportGraph {
+            imports,
+            modules: HashMap::new(),
+        };
+        let set = |modules: &[&str]| -> BTreeSet<String> {
+            modules.iter().map(|s| s.to_string()).collect()
+        };
+        assert_eq!(
+            graph.dependents("Lexer"),
+            set(&["Lexer", "LexerTests", "Parser", "ParserTests", "Unrelated"])
+        );
+        assert_eq!(graph.dependents("ParserTests"), set(&["ParserTests"]));
+        assert_eq!(
+            graph.dependencies("ParserTests"),
+            set(&["Lexer", "Parser", "ParserTests"])
+        );
+    }
+}
@@ -3,14 +3,21 @@
 use crate::include_template;
 use crate::project::Project;
 use anyhow::Context;
+use pubgrub_dependency_provider_elm::project_config::{Pkg, ProjectConfig};
+use std::io::{BufRead, Write};
 use std::path::Path;
 
 /// Add elm-explorations/test to test dependencies
 /// and initialize a template tests/Tests.elm file.
-pub fn main<P: AsRef<Path>>(elm_home: P, project_root: P, offline: bool) -> anyhow::Result<()> {
+pub fn main<P: AsRef<Path>>(
+    elm_home: P,
+    project_root: P,
+    network: &crate::deps::Network,
+    offline: bool,
+) -> anyhow::Result<()> {
     // Install elm-explorations/test in the tests dependencies
     let project = Project::from_dir(project_root)?;
-    let updated_config = crate::deps::init(elm_home, project.config, offline).context(
+    let updated_config = crate::deps::init(elm_home, network, project.config, offline).context(
         "Something went wrong when installing elm-explorations/test to the tests dependencies",
     )?;
     crate::utils::json_write(project.root_directory.join("elm.json"), &updated_config)
@@ -28,3 +35,48 @@ pub fn main<P: AsRef<Path>>(elm_home: P, project_root: P, offline: bool) -> anyh
     }
     Ok(())
 }
+
+/// Offer to add elm-explorations/test to the tests dependencies when it is missing,
+/// instead of failing later with a compilation error about an unknown import.
+/// The prompt is skipped with `--yes`, and the project is reloaded with its updated elm.json.
+pub fn ensure_test_dependency(
+    elm_home: &Path,
+    network: &crate::deps::Network,
+    project: Project,
+    yes: bool,
+    offline: bool,
+) -> anyhow::Result<Project> {
+    let test_pkg = Pkg::new("elm-explorations", "test");
+    let has_test_pkg = match &project.config {
+        ProjectConfig::Application(app_config) => {
+            app_config.dependencies.direct.contains_key(&test_pkg)
+                || app_config.test_dependencies.direct.contains_key(&test_pkg)
+        }
+        ProjectConfig::Package(pkg_config) => {
+            pkg_config.dependencies.contains_key(&test_pkg)
+                || pkg_config.test_dependencies.contains_key(&test_pkg)
+        }
+    };
+    if has_test_pkg {
+        return Ok(project);
+    }
+    if !yes {
+        if !atty::is(atty::Stream::Stdin) {
+            anyhow::bail!("elm-explorations/test is missing from the tests dependencies. Add it with elm-test-rs init, or run with --yes to add it automatically");
+        }
+        eprint!("elm-explorations/test is missing from the tests dependencies. Add it to elm.json? [Y/n] ");
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().lock().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "" | "y" | "yes") {
+            anyhow::bail!("The tests cannot compile without elm-explorations/test");
+        }
+    }
+    let updated_config = crate::deps::init(elm_home, network, project.config, offline).context(
+        "Something went wrong when installing elm-explorations/test to the tests dependencies",
+    )?;
+    crate::utils::json_write(project.root_directory.join("elm.json"), &updated_config)
+        .context("Unable to write the updated elm.json")?;
+    log::error!("elm-explorations/test was added to the tests dependencies in elm.json");
+    Project::from_dir(&project.root_directory)
+}
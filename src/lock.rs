@@ -0,0 +1,208 @@
+//! Lockfile of the solved tests dependencies.
+//!
+//! With `--lock`, the dependencies picked for the generated tests application are saved in
+//! `elm-test-rs.lock` at the root of the project, with the inputs of the solver:
+//! the project dependencies, the `--dependencies` strategy,
+//! and the `--extra-dependency` and `--pin` options.
+//! With `--locked`, those dependencies are reused as is instead of being solved again,
+//! and the run fails if the lockfile is missing or if its inputs changed.
+//! With `--frozen`, the locked packages must also be installed already,
+//! so that the run does not depend on the package website at all.
+
+use anyhow::Context;
+use pubgrub::range::Range;
+use pubgrub::type_aliases::Map;
+use pubgrub::version::SemanticVersion as SemVer;
+use pubgrub_dependency_provider_elm::dependency_provider::VersionStrategy;
+use pubgrub_dependency_provider_elm::project_config::{AppDependencies, Pkg, ProjectConfig};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Name of the lockfile, at the root of the project.
+pub const LOCK_FILE: &str = "elm-test-rs.lock";
+
+/// How the lockfile is used.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Solve the dependencies and update the lockfile, with `--lock`.
+    Update,
+    /// Solve the dependencies without reading nor writing the lockfile,
+    /// which is the default.
+    Ignore,
+    /// Reuse the locked dependencies, failing if they are stale.
+    Locked,
+    /// Like `Locked`, and also fail if the locked packages are not installed.
+    Frozen,
+}
+
+/// Inputs of the dependency solver, which must not change for a lockfile to be reused.
+pub fn inputs(
+    config: &ProjectConfig,
+    connectivity: &crate::deps::ConnectivityStrategy,
+    extra_dependencies: &[(Pkg, Range<SemVer>)],
+    pins: &[(Pkg, SemVer)],
+) -> Value {
+    let to_strings = |deps: Vec<(&Pkg, String)>| -> Vec<String> {
+        deps.into_iter()
+            .map(|(pkg, version)| format!("{} {}", pkg, version))
+            .collect()
+    };
+    let project = match config {
+        // The indirect dependencies of an application are fixed in its elm.json as well,
+        // and the solver keeps them.
+        ProjectConfig::Application(app_config) => to_strings(
+            app_config
+                .dependencies
+                .direct
+                .iter()
+                .chain(app_config.dependencies.indirect.iter())
+                .chain(app_config.test_dependencies.direct.iter())
+                .chain(app_config.test_dependencies.indirect.iter())
+                .map(|(pkg, version)| (pkg, version.to_string()))
+                .collect(),
+        ),
+        ProjectConfig::Package(pkg_config) => to_strings(
+            pkg_config
+                .dependencies
+                .iter()
+                .chain(pkg_config.test_dependencies.iter())
+                .map(|(pkg, constraint)| (pkg, constraint.to_string()))
+                .collect(),
+        ),
+    };
+    let extra = to_strings(
+        extra_dependencies
+            .iter()
+            .map(|(pkg, range)| (pkg, range.to_string()))
+            .collect(),
+    );
+    let pins = to_strings(
+        pins.iter()
+            .map(|(pkg, version)| (pkg, version.to_string()))
+            .collect(),
+    );
+    // Offline and progressive solving both pick the newest versions available.
+    let strategy = match connectivity {
+        crate::deps::ConnectivityStrategy::Online(VersionStrategy::Oldest) => "oldest",
+        _ => "newest",
+    };
+    // The version of elm-test-rs is included since it picks the version of the runner package.
+    json!({
+        "elmTestRs": std::env!("CARGO_PKG_VERSION"),
+        "project": project,
+        "strategy": strategy,
+        "extra": extra,
+        "pins": pins,
+    })
+}
+
+/// Write the solved dependencies to the lockfile, if they changed.
+pub fn write(
+    project_root: &Path,
+    inputs: &Value,
+    dependencies: &AppDependencies,
+) -> anyhow::Result<()> {
+    let versions = |deps: &BTreeMap<Pkg, SemVer>| -> serde_json::Map<String, Value> {
+        deps.iter()
+            .map(|(pkg, version)| (pkg.to_string(), Value::String(version.to_string())))
+            .collect()
+    };
+    let lock = json!({
+        "inputs": inputs,
+        "direct": versions(&dependencies.direct),
+        "indirect": versions(&dependencies.indirect),
+    });
+    let lock_str = serde_json::to_string_pretty(&lock).context("Failed to convert the lockfile")?;
+    let lock_file = project_root.join(LOCK_FILE);
+    match std::fs::read_to_string(&lock_file) {
+        Ok(old_lock) if old_lock == lock_str => Ok(()),
+        _ => std::fs::write(&lock_file, lock_str)
+            .context(format!("Failed to write {}", lock_file.display())),
+    }
+}
+
+/// Read the locked dependencies, failing if the lockfile is missing or was written for other inputs.
+/// In frozen mode, also fail if some locked packages are not installed.
+pub fn read(
+    elm_home: &Path,
+    project_root: &Path,
+    inputs: &Value,
+    mode: Mode,
+) -> anyhow::Result<AppDependencies> {
+    let lock_file = project_root.join(LOCK_FILE);
+    let lock_str = std::fs::read_to_string(&lock_file).context(format!(
+        "There is no {}, run elm-test-rs once with --lock to create it",
+        LOCK_FILE
+    ))?;
+    let lock: Value =
+        serde_json::from_str(&lock_str).context(format!("Invalid {}", lock_file.display()))?;
+    if &lock["inputs"] != inputs {
+        anyhow::bail!(
+            "{} is stale since the dependencies of the project changed, run elm-test-rs with --lock to update it",
+            LOCK_FILE
+        );
+    }
+    let versions = |key: &str| -> anyhow::Result<BTreeMap<Pkg, SemVer>> {
+        let invalid = || format!("Invalid {} dependencies in {}", key, lock_file.display());
+        let mut deps = BTreeMap::new();
+        for (pkg, version) in lock[key].as_object().with_context(invalid)? {
+            let pkg = crate::deps::parse_package(pkg).with_context(invalid)?;
+            let version = version.as_str().and_then(|v| v.parse().ok());
+            deps.insert(pkg, version.with_context(invalid)?);
+        }
+        Ok(deps)
+    };
+    let dependencies = AppDependencies {
+        direct: versions("direct")?,
+        indirect: versions("indirect")?,
+    };
+    if mode == Mode::Frozen {
+        let locked: Map<Pkg, Range<SemVer>> = dependencies
+            .direct
+            .iter()
+            .chain(dependencies.indirect.iter())
+            .map(|(pkg, version)| (pkg.clone(), Range::exact(*version)))
+            .collect();
+        let missing = crate::deps::missing_packages(elm_home, &locked);
+        if !missing.is_empty() {
+            anyhow::bail!(
+                "Some locked packages are not installed in {}, which --frozen forbids:\n\n    {}\n",
+                elm_home.join("0.19.1").join("packages").display(),
+                missing.join("\n    ")
+            );
+        }
+    }
+    Ok(dependencies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read, write, Mode};
+    use pubgrub::version::SemanticVersion as SemVer;
+    use pubgrub_dependency_provider_elm::project_config::{AppDependencies, Pkg};
+    use serde_json::json;
+
+    #[test]
+    fn locked_dependencies() {
+        let dir = std::env::temp_dir().join(format!("elm-test-rs-lock-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dependencies = AppDependencies {
+            direct: vec![(Pkg::new("elm", "json"), SemVer::new(1, 1, 3))]
+                .into_iter()
+                .collect(),
+            indirect: vec![(Pkg::new("elm", "core"), SemVer::new(1, 0, 5))]
+                .into_iter()
+                .collect(),
+        };
+        let inputs = json!({ "project": ["elm/json 1.1.3"] });
+        write(&dir, &inputs, &dependencies).unwrap();
+        let locked = read(&dir, &dir, &inputs, Mode::Locked).unwrap();
+        assert_eq!(locked.direct, dependencies.direct);
+        assert_eq!(locked.indirect, dependencies.indirect);
+        let changed = json!({ "project": ["elm/json 1.1.2"] });
+        assert!(read(&dir, &dir, &changed, Mode::Locked).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(read(&dir, &dir, &inputs, Mode::Locked).is_err());
+    }
+}
@@ -1,19 +1,45 @@
+mod baseline;
+mod bounds;
+mod browser;
+mod coverage;
+mod daemon;
 mod deps;
+mod diff;
+mod docs;
+mod examples;
+mod fixtures;
+mod history;
+mod imports;
 mod init;
 mod install;
+mod lock;
 mod make;
+mod monorepo;
+mod mutate;
+mod parse_cache;
 mod parser;
+mod profile;
 mod project;
+mod protocol;
 mod run;
+mod snapshot;
+mod stack;
+mod summary;
 mod utils;
 
 use anyhow::Context;
 use clap::{App, AppSettings, Arg, SubCommand};
 use pubgrub_dependency_provider_elm::dependency_provider::VersionStrategy;
 use std::num::NonZeroU32;
+use std::path::PathBuf;
 
 /// Main entry point of elm-test-rs.
 fn main() -> anyhow::Result<()> {
+    let report_help = format!(
+        "Print results to stdout in the given format, or to a file with format=path. Can be repeated to generate multiple reports [possible formats: {}]",
+        run::Report::formats().collect::<Vec<_>>().join(", ")
+    );
+
     // Arguments available to all subcommands.
     let global_args = vec![
         Arg::with_name("elm-home")
@@ -33,6 +59,19 @@ fn main() -> anyhow::Result<()> {
             .long("offline")
             .global(true)
             .help("No network call made by elm-test-rs"),
+        Arg::with_name("registry")
+            .long("registry")
+            .global(true)
+            .takes_value(true)
+            .value_name("url")
+            .env(deps::REGISTRY_VAR)
+            .help("Use a custom package registry, such as an internal mirror of https://package.elm-lang.org"),
+        Arg::with_name("proxy")
+            .long("proxy")
+            .global(true)
+            .takes_value(true)
+            .value_name("url")
+            .help("Use this proxy for network calls, instead of the one in HTTPS_PROXY or HTTP_PROXY"),
         Arg::with_name("verbose")
             .short("v")
             .multiple(true)
@@ -41,6 +80,9 @@ fn main() -> anyhow::Result<()> {
     ];
     // Arguments shared with the "make" subcommand.
     let make_args = vec![
+        Arg::with_name("all")
+            .long("all")
+            .help("Run or compile the tests of all the Elm projects beneath the --project directory, such as in a monorepo"),
         Arg::with_name("watch")
             .long("watch")
             .help("Rerun tests on file changes"),
@@ -52,24 +94,67 @@ fn main() -> anyhow::Result<()> {
             .long("dependencies")
             .takes_value(true)
             .value_name("strategy")
-            .possible_values(&["newest", "oldest"])
+            .possible_values(&["newest", "oldest", "bounds"])
             .conflicts_with("offline")
-            .help("Choose the newest or oldest compatible dependencies (mostly useful for package authors)"),
+            .help("Choose the newest or oldest compatible dependencies, or run the tests with both with \"bounds\" (mostly useful for package authors)"),
         Arg::with_name("report")
             .long("report")
             .default_value("console")
-            .possible_value("console")
-            .possible_value("consoleDebug")
-            .possible_value("json")
-            .possible_value("junit")
-            .possible_value("exercism")
-            .help("Print results to stdout in the given format"),
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("format[=path]")
+            .validator(|arg| run::Report::parse(&arg).map(|_| ()).map_err(|e| e.to_string()))
+            .help(&report_help),
         Arg::with_name("output")
             .long("output")
             .takes_value(true)
             .value_name("output_path")
             .possible_values(&["/dev/null"])
             .help("This argument is ignored, and only present for compatibility with `elm make --output=/dev/null` for the make subcommand"),
+        Arg::with_name("yes")
+            .long("yes")
+            .help("Answer yes to the prompts, such as adding elm-explorations/test to the dependencies when it is missing"),
+        Arg::with_name("lock")
+            .long("lock")
+            .conflicts_with_all(&["locked", "frozen"])
+            .help("Save the solved dependencies in elm-test-rs.lock, to reuse them later with --locked or --frozen"),
+        Arg::with_name("locked")
+            .long("locked")
+            .help("Reuse the dependencies of elm-test-rs.lock instead of solving them, and fail if it is stale"),
+        Arg::with_name("frozen")
+            .long("frozen")
+            .conflicts_with("locked")
+            .help("Same as --locked, and also fail if the locked packages are not installed"),
+        Arg::with_name("extra-dependency")
+            .long("extra-dependency")
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("author/name[@version]")
+            .validator(|arg| deps::parse_extra_dependency(&arg).map(|_| ()).map_err(|e| e.to_string()))
+            .help("Add a package to the dependencies of the tests, such as elm/random, for generated code or custom runners"),
+        Arg::with_name("local-package")
+            .long("local-package")
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("author/name=path")
+            .validator(|arg| deps::parse_local_package(&arg).map(|_| ()).map_err(|e| e.to_string()))
+            .help("Compile a vendored fork of a package from its local directory instead of using it as a dependency"),
+        Arg::with_name("pin")
+            .long("pin")
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("author/name@version")
+            .validator(|arg| deps::parse_pin(&arg).map(|_| ()).map_err(|e| e.to_string()))
+            .help("Force the version of a package in the dependencies of the tests, such as elm/json@1.1.3"),
+        Arg::with_name("forbid-only")
+            .long("forbid-only")
+            .help("Fail before compiling if a test module calls Test.only"),
+        Arg::with_name("forbid-skip")
+            .long("forbid-skip")
+            .help("Fail before compiling if a test module calls Test.skip"),
+        Arg::with_name("forbid-todo")
+            .long("forbid-todo")
+            .help("Fail before compiling if a test module calls Test.todo"),
         Arg::with_name("PATH or GLOB")
             .multiple(true)
             .help("Path to a test module, or glob pattern such as tests/*.elm")
@@ -94,9 +179,94 @@ fn main() -> anyhow::Result<()> {
             .takes_value(true)
             .value_name("string")
             .help("Keep only tests whose description contains the given string"),
+        Arg::with_name("tags")
+            .long("tags")
+            .takes_value(true)
+            .value_name("tag,...")
+            .help("Only run the tests with one of the given tags in their labels or those of their groups, such as \"[slow]\""),
+        Arg::with_name("skip-tags")
+            .long("skip-tags")
+            .takes_value(true)
+            .value_name("tag,...")
+            .help("Do not run the tests with one of the given tags in their labels or those of their groups"),
+        Arg::with_name("report-output")
+            .long("report-output")
+            .takes_value(true)
+            .value_name("path")
+            .help("Write the first report without a path to the given file instead of stdout (a directory for allure and junit-per-module, an SVG file for badge)"),
+        Arg::with_name("profile")
+            .long("profile")
+            .takes_value(true)
+            .min_values(0)
+            .require_equals(true)
+            .value_name("N")
+            .help("Print the N slowest tests and modules after the run [default: 10]"),
+        Arg::with_name("timeout")
+            .long("timeout")
+            .takes_value(true)
+            .value_name("seconds")
+            .help("Fail the run, naming the test, when a single test runs longer than the given number of seconds"),
+        Arg::with_name("worker-max-memory")
+            .long("worker-max-memory")
+            .takes_value(true)
+            .value_name("MB")
+            .help("Limit the heap of each worker to the given number of megabytes, naming the test running out of memory (Node and Bun)"),
+        Arg::with_name("heap-stats")
+            .long("heap-stats")
+            .help("Measure the peak heap of each test (Node), reported with --profile, in the json, json-compat and ndjson reports and in the run summary"),
+        Arg::with_name("detect-leaks")
+            .long("detect-leaks")
+            .help("Warn about the timers left active by a test, which keep running during the next tests"),
+        Arg::with_name("isolate-modules")
+            .long("isolate-modules")
+            .help("Run the tests of each module in a fresh worker, so that global state of kernel code or ports does not leak between modules"),
+        Arg::with_name("granularity")
+            .long("granularity")
+            .takes_value(true)
+            .possible_values(&["test", "module"])
+            .value_name("unit")
+            .help("Dispatch tests to workers one by one, for the best balancing, or by whole modules, for the best locality [default: test]"),
+        Arg::with_name("max-failure-output")
+            .long("max-failure-output")
+            .takes_value(true)
+            .value_name("chars")
+            .help("Elide the middle of expected and actual values longer than the given number of characters in the tree report"),
+        Arg::with_name("duration-baseline")
+            .long("duration-baseline")
+            .takes_value(true)
+            .value_name("file")
+            .help("Compare tests durations with the run summary in the given file, recorded by the first passing run if it does not exist"),
+        Arg::with_name("max-regression")
+            .long("max-regression")
+            .default_value("20")
+            .value_name("percent")
+            .help("Fail the run if the total duration of tests regressed more than this compared to the --duration-baseline"),
+        Arg::with_name("post-results")
+            .long("post-results")
+            .takes_value(true)
+            .value_name("url")
+            .help("POST the JSON run summary to the given url at the end of the run, with the bearer token in ELM_TEST_RS_POST_TOKEN if set"),
+        Arg::with_name("dom")
+            .long("dom")
+            .help("Load a lightweight DOM into the runners, for tests starting programs without a real browser"),
+        Arg::with_name("http-fixtures")
+            .long("http-fixtures")
+            .takes_value(true)
+            .value_name("file")
+            .help("Answer the HTTP requests of the tests with the fixtures of the given JSON file, other requests fail like network errors"),
+        Arg::with_name("update-snapshots")
+            .long("update-snapshots")
+            .help("Write all the snapshots compared by the tests in tests/__snapshots__/, instead of only the missing ones"),
         Arg::with_name("deno")
             .long("deno")
             .help("Rerun tests with Deno instead of Node"),
+        Arg::with_name("runtime")
+            .long("runtime")
+            .takes_value(true)
+            .possible_values(&["node", "deno", "bun", "browser"])
+            .conflicts_with("deno")
+            .value_name("name")
+            .help("Run tests with Node, Deno, Bun, or a headless Chromium found in the PATH or given by ELM_TEST_RS_BROWSER [default: node, or deno if only Deno is installed]"),
     ];
     let matches = App::new("elm-test-rs")
         .version(std::env!("CARGO_PKG_VERSION"))
@@ -124,6 +294,80 @@ fn main() -> anyhow::Result<()> {
                 .args(&make_args)
                 .setting(AppSettings::DisableVersion),
         )
+        .subcommand(
+            SubCommand::with_name("coverage")
+                .about("Run the tests and report which top-level functions of source modules they call (only functions are counted, not branches)")
+                .arg(
+                    Arg::with_name("coverage-min")
+                        .long("coverage-min")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .value_name("[module=]percent")
+                        .validator(|arg| coverage::Threshold::parse(&arg).map(|_| ()).map_err(|e| e.to_string()))
+                        .help("Fail the run if the total coverage, or the one of the given module, is below the percentage"),
+                )
+                .args(&make_args)
+                .args(&run_args)
+                .setting(AppSettings::DisableVersion),
+        )
+        .subcommand(
+            SubCommand::with_name("mutate")
+                .about("Check that tests fail when introducing small mutations in source modules")
+                .args(&make_args)
+                .args(&run_args)
+                .setting(AppSettings::DisableVersion),
+        )
+        .subcommand(
+            SubCommand::with_name("examples")
+                .about("Compile the example applications of a package against its local source")
+                .args(&make_args)
+                .setting(AppSettings::DisableVersion),
+        )
+        .subcommand(
+            SubCommand::with_name("history")
+                .about("Show the slowest and most frequently failing tests of previous runs")
+                .arg(
+                    Arg::with_name("top")
+                        .long("top")
+                        .default_value("10")
+                        .value_name("N")
+                        .help("Number of tests listed in each section"),
+                )
+                .setting(AppSettings::DisableVersion),
+        )
+        .subcommand(
+            SubCommand::with_name("daemon")
+                .about("Stay resident and run tests on requests received on a local socket")
+                .arg(
+                    Arg::with_name("port")
+                        .long("port")
+                        .default_value("0")
+                        .value_name("N")
+                        .help("Local port to listen on (0 picks any available port)"),
+                )
+                .args(&make_args)
+                .args(&run_args)
+                .setting(AppSettings::DisableVersion),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Compare tests results of the current worktree with the ones at a git ref")
+                .arg(
+                    Arg::with_name("GIT_REF")
+                        .required(true)
+                        .help("Git branch, tag or commit to compare with"),
+                )
+                .args(&make_args)
+                .args(&run_args)
+                .setting(AppSettings::DisableVersion),
+        )
+        .subcommand(
+            SubCommand::with_name("docs")
+                .about("Run the examples in documentation comments of source modules")
+                .args(&make_args)
+                .args(&run_args)
+                .setting(AppSettings::DisableVersion),
+        )
         .get_matches();
 
     // Retrieve the path to the elm home.
@@ -137,9 +381,6 @@ fn main() -> anyhow::Result<()> {
         }
     };
 
-    // Retrieve the path to the project root directory.
-    let elm_project_root = utils::elm_project_root(matches.value_of("project").unwrap())?; // unwrap is fine since project has a default value
-
     // Set log verbosity.
     let verbosity = matches.occurrences_of("verbose");
     stderrlog::new()
@@ -150,10 +391,49 @@ fn main() -> anyhow::Result<()> {
         .init()
         .context("Failed to initialize log verbosity")?;
 
+    // Run or compile the tests of every project beneath the project directory.
+    let project_dir = matches.value_of("project").unwrap(); // unwrap is fine since project has a default value
+    let all = |arg_matches: &clap::ArgMatches| arg_matches.is_present("all");
+    match matches.subcommand() {
+        (_, None) if all(&matches) => {
+            let make_options = get_make_options(&matches)?;
+            let run_options = get_run_options(&matches)?;
+            let dir = utils::absolute_path(project_dir)?;
+            let exit_code = monorepo::main(&dir, &make_options, |project| {
+                run::main(
+                    &elm_home,
+                    project,
+                    make_options.clone(),
+                    run_options.clone(),
+                )
+            })?;
+            std::process::exit(exit_code);
+        }
+        ("make", Some(sub_matches)) if all(sub_matches) || all(&matches) => {
+            let make_options = get_make_options(sub_matches)?;
+            let dir = utils::absolute_path(project_dir)?;
+            let exit_code = monorepo::main(&dir, &make_options, |project| {
+                make::main(&elm_home, project, make_options.clone()).map(|_| 0)
+            })?;
+            std::process::exit(exit_code);
+        }
+        (_, Some(sub_matches)) if all(sub_matches) || all(&matches) => {
+            anyhow::bail!(
+                "--all only runs or compiles the tests, with elm-test-rs or elm-test-rs make"
+            )
+        }
+        _ => {}
+    }
+
+    // Retrieve the path to the project root directory.
+    let elm_project_root = utils::elm_project_root(project_dir)
+        .map_err(|e| monorepo::with_candidate_projects(e, project_dir))?;
+
     match matches.subcommand() {
         ("init", Some(sub_matches)) => init::main(
             elm_home,
             elm_project_root,
+            &get_network(sub_matches),
             sub_matches.is_present("offline"),
         ),
         ("install", Some(sub_matches)) => {
@@ -165,9 +445,79 @@ fn main() -> anyhow::Result<()> {
                 .collect();
             install::main(packages)
         }
+        ("examples", Some(sub_matches)) => {
+            let make_options = get_make_options(sub_matches)?;
+            let exit_code = examples::main(&elm_home, &elm_project_root, make_options)?;
+            std::process::exit(exit_code);
+        }
+        ("history", Some(sub_matches)) => {
+            let top: usize = sub_matches
+                .value_of("top")
+                .unwrap() // unwrap is fine since there is a default value
+                .parse()
+                .context("Invalid --top value")?;
+            history::main(&elm_project_root, top)
+        }
         ("make", Some(sub_matches)) => {
             make::main(&elm_home, &elm_project_root, get_make_options(sub_matches)?)
         }
+        ("coverage", Some(sub_matches)) => {
+            let make_options = get_make_options(sub_matches)?;
+            let coverage_min = match sub_matches.values_of("coverage-min") {
+                None => Vec::new(),
+                Some(args) => args
+                    .map(coverage::Threshold::parse)
+                    .collect::<anyhow::Result<_>>()?,
+            };
+            let run_options = run::Options {
+                coverage: true,
+                coverage_min,
+                ..get_run_options(sub_matches)?
+            };
+            let exit_code = run::main(&elm_home, &elm_project_root, make_options, run_options)?;
+            std::process::exit(exit_code);
+        }
+        ("mutate", Some(sub_matches)) => {
+            let make_options = get_make_options(sub_matches)?;
+            let run_options = get_run_options(sub_matches)?;
+            let exit_code = mutate::main(&elm_home, &elm_project_root, make_options, run_options)?;
+            std::process::exit(exit_code);
+        }
+        ("daemon", Some(sub_matches)) => {
+            let port: u16 = sub_matches
+                .value_of("port")
+                .unwrap() // unwrap is fine since there is a default value
+                .parse()
+                .context("Invalid --port value")?;
+            let make_options = get_make_options(sub_matches)?;
+            let run_options = get_run_options(sub_matches)?;
+            daemon::main(
+                &elm_home,
+                &elm_project_root,
+                port,
+                make_options,
+                run_options,
+            )
+        }
+        ("diff", Some(sub_matches)) => {
+            let git_ref = sub_matches.value_of("GIT_REF").unwrap(); // unwrap is fine since it is required
+            let make_options = get_make_options(sub_matches)?;
+            let run_options = get_run_options(sub_matches)?;
+            let exit_code = diff::main(
+                &elm_home,
+                &elm_project_root,
+                git_ref,
+                make_options,
+                run_options,
+            )?;
+            std::process::exit(exit_code);
+        }
+        ("docs", Some(sub_matches)) => {
+            let make_options = get_make_options(sub_matches)?;
+            let run_options = get_run_options(sub_matches)?;
+            let exit_code = docs::main(&elm_home, &elm_project_root, make_options, run_options)?;
+            std::process::exit(exit_code);
+        }
         _ => {
             let make_options = get_make_options(&matches)?;
             let run_options = get_run_options(&matches)?;
@@ -177,6 +527,14 @@ fn main() -> anyhow::Result<()> {
     }
 }
 
+/// Retrieve the package registry and proxy given with --registry and --proxy.
+fn get_network(arg_matches: &clap::ArgMatches) -> deps::Network {
+    deps::Network {
+        registry: arg_matches.value_of("registry").map(|s| s.to_string()),
+        proxy: arg_matches.value_of("proxy").map(|s| s.to_string()),
+    }
+}
+
 /// Retrieve options related to the make subcommand.
 fn get_make_options(arg_matches: &clap::ArgMatches) -> anyhow::Result<make::Options> {
     let connectivity = match (
@@ -188,6 +546,8 @@ fn get_make_options(arg_matches: &clap::ArgMatches) -> anyhow::Result<make::Opti
         (true, Some(_)) => anyhow::bail!("--offline is incompatible with --dependencies"),
         (false, Some("newest")) => deps::ConnectivityStrategy::Online(VersionStrategy::Newest),
         (false, Some("oldest")) => deps::ConnectivityStrategy::Online(VersionStrategy::Oldest),
+        // Each run of the tests then picks its own strategy.
+        (false, Some("bounds")) => deps::ConnectivityStrategy::Online(VersionStrategy::Newest),
         (false, Some(_)) => anyhow::bail!("Invalid --dependencies value"),
     };
 
@@ -213,13 +573,55 @@ fn get_make_options(arg_matches: &clap::ArgMatches) -> anyhow::Result<make::Opti
         .flatten()
         .map(|s| s.to_string())
         .collect();
+
+    let forbid = ["only", "skip", "todo"]
+        .iter()
+        .filter(|function| arg_matches.is_present(format!("forbid-{}", function)))
+        .map(|function| function.to_string())
+        .collect();
+    let lock = if arg_matches.is_present("frozen") {
+        lock::Mode::Frozen
+    } else if arg_matches.is_present("locked") {
+        lock::Mode::Locked
+    } else if arg_matches.is_present("lock") {
+        lock::Mode::Update
+    } else {
+        lock::Mode::Ignore
+    };
+    let extra_dependencies = arg_matches
+        .values_of("extra-dependency")
+        .into_iter()
+        .flatten()
+        .map(deps::parse_extra_dependency)
+        .collect::<Result<_, _>>()?;
+    let local_packages = arg_matches
+        .values_of("local-package")
+        .into_iter()
+        .flatten()
+        .map(deps::parse_local_package)
+        .collect::<Result<_, _>>()?;
+    let pins = arg_matches
+        .values_of("pin")
+        .into_iter()
+        .flatten()
+        .map(deps::parse_pin)
+        .collect::<Result<_, _>>()?;
     Ok(make::Options {
         verbosity: arg_matches.occurrences_of("verbose"),
         watch: arg_matches.is_present("watch"),
         compiler,
         connectivity,
+        network: get_network(arg_matches),
+        dependency_bounds: arg_matches.value_of("dependencies") == Some("bounds"),
         files,
         report,
+        forbid,
+        filter: arg_matches.value_of("filter").map(|s| s.to_string()),
+        pins,
+        extra_dependencies,
+        local_packages,
+        lock,
+        yes: arg_matches.is_present("yes"),
     })
 }
 
@@ -241,27 +643,153 @@ fn get_run_options(arg_matches: &clap::ArgMatches) -> anyhow::Result<run::Option
         Some(str_workers) => str_workers.parse().context("Invalid --workers value")?,
     };
 
-    let reporter = match arg_matches.value_of("report").unwrap() {
+    // Each report is a format, optionally followed by "=path" to write it to a file.
+    // The supervisor changes its working directory, so paths are made absolute.
+    let current_dir = std::env::current_dir().context("Could not get the current directory")?;
+    let mut report_output = arg_matches.value_of("report-output");
+    let mut reports = Vec::new();
+    for arg in arg_matches.values_of("report").unwrap() {
         // unwrap is fine since there is a default value
-        "console" => String::from(console_color_mode()),
-        r => String::from(r),
-    };
+        let mut report = run::Report::parse(arg)?;
+        if report.output.is_none() {
+            // --report-output applies to the first report without a path.
+            report.output = match report_output.take() {
+                Some(path) => Some(PathBuf::from(path)),
+                None => report.default_output(),
+            };
+        }
+        report.output = report.output.map(|path| current_dir.join(path));
+        report.color = report.output.is_none() && console_color_mode() == "consoleColor";
+        if report.format == "console" {
+            report.format = match report.output {
+                Some(_) => String::from("consoleNoColor"),
+                None => String::from(console_color_mode()),
+            };
+        }
+        reports.push(report);
+    }
 
-    let runtime = if arg_matches.is_present("deno") {
-        run::Runtime::Deno
+    let profile = if arg_matches.is_present("profile") {
+        match arg_matches.value_of("profile") {
+            None => Some(10),
+            Some(count) => Some(count.parse().context("Invalid --profile value")?),
+        }
     } else {
-        run::Runtime::Node
+        None
+    };
+
+    let max_failure_output =
+        match arg_matches.value_of("max-failure-output") {
+            None => None,
+            Some(chars) => Some(chars.parse().context(
+                "Invalid --max-failure-output value. It must be a number of characters.",
+            )?),
+        };
+
+    let timeout = match arg_matches.value_of("timeout") {
+        None => None,
+        Some(seconds) => Some(
+            seconds
+                .parse()
+                .context("Invalid --timeout value. It must be a positive number of seconds.")?,
+        ),
+    };
+
+    let worker_max_memory = match arg_matches.value_of("worker-max-memory") {
+        None => None,
+        Some(megabytes) => Some(megabytes.parse().context(
+            "Invalid --worker-max-memory value. It must be a positive number of megabytes.",
+        )?),
+    };
+
+    let duration_baseline = match arg_matches.value_of("duration-baseline") {
+        None => None,
+        Some(file) => {
+            let max_regression = arg_matches.value_of("max-regression").unwrap(); // unwrap is fine since there is a default value
+            Some(baseline::Options {
+                file: current_dir.join(file),
+                max_regression: max_regression
+                    .parse()
+                    .context("Invalid --max-regression value. It must be a percentage.")?,
+            })
+        }
+    };
+
+    let runtime = match arg_matches.value_of("runtime") {
+        _ if arg_matches.is_present("deno") => run::Runtime::Deno,
+        Some("deno") => run::Runtime::Deno,
+        Some("browser") => run::Runtime::Browser,
+        Some("bun") => run::Runtime::Bun,
+        Some(_) => run::Runtime::Node,
+        None => default_runtime(),
     };
+    if worker_max_memory.is_some() {
+        if let run::Runtime::Deno | run::Runtime::Browser = runtime {
+            log::error!("--worker-max-memory only limits the workers of Node and Bun");
+        }
+    }
+    if arg_matches.is_present("heap-stats") {
+        if let run::Runtime::Deno | run::Runtime::Browser | run::Runtime::Bun = runtime {
+            log::error!("--heap-stats only measures the heap of Node workers");
+        }
+    }
     Ok(run::Options {
         seed,
         fuzz,
         workers,
         filter: arg_matches.value_of("filter").map(|s| s.to_string()),
-        reporter,
+        reports,
         runtime,
+        coverage: false,
+        coverage_min: Vec::new(),
+        profile,
+        max_failure_output,
+        timeout,
+        worker_max_memory,
+        heap_stats: arg_matches.is_present("heap-stats"),
+        detect_leaks: arg_matches.is_present("detect-leaks"),
+        isolate_modules: arg_matches.is_present("isolate-modules"),
+        granularity: match arg_matches.value_of("granularity") {
+            Some("module") => run::Granularity::Module,
+            _ => run::Granularity::Test,
+        },
+        duration_baseline,
+        post_results: arg_matches.value_of("post-results").map(|s| s.to_string()),
+        tags: tags_list(arg_matches.value_of("tags")),
+        skip_tags: tags_list(arg_matches.value_of("skip-tags")),
+        quiet: false,
+        dom: arg_matches.is_present("dom"),
+        http_fixtures: arg_matches
+            .value_of("http-fixtures")
+            .map(|file| current_dir.join(file)),
+        update_snapshots: arg_matches.is_present("update-snapshots"),
     })
 }
 
+/// Node, unless only Deno is installed.
+fn default_runtime() -> run::Runtime {
+    if which::which("node").is_err() && which::which("deno").is_ok() {
+        log::info!("Node was not found, running the tests with Deno");
+        run::Runtime::Deno
+    } else {
+        run::Runtime::Node
+    }
+}
+
+/// Parse a comma separated list of tags, with or without their brackets.
+fn tags_list(tags: Option<&str>) -> Vec<String> {
+    tags.into_iter()
+        .flat_map(|tags| tags.split(','))
+        .map(|tag| {
+            tag.trim()
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string()
+        })
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
 /// Returns "consoleColor" or "consoleNoColor" based on the following two standards:
 ///  - https://bixense.com/clicolors/
 ///  - https://no-color.org/
@@ -2,10 +2,12 @@
 
 use anyhow::Context;
 use glob::glob;
-use pubgrub_dependency_provider_elm::project_config::ProjectConfig;
-use std::collections::HashSet;
+use pubgrub::range::Range;
+use pubgrub::version::SemanticVersion as SemVer;
+use pubgrub_dependency_provider_elm::project_config::{AppDependencies, Pkg, ProjectConfig};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
-use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use walkdir::WalkDir;
@@ -13,15 +15,44 @@ use walkdir::WalkDir;
 use crate::include_template;
 use crate::project::Project;
 
-#[derive(Debug)]
+/// File next to the generated elm.json, with the hash of the inputs its dependencies were solved for.
+const SOLVED_INPUTS_FILE: &str = "elm.json.inputs";
+
+thread_local! {
+    /// Dependencies already solved by this process, by solver inputs,
+    /// reused when running the tests of several projects, or again in watch mode.
+    static SOLVED: RefCell<HashMap<String, AppDependencies>> = RefCell::new(HashMap::new());
+}
+
+#[derive(Debug, Clone)]
 /// Options passed as arguments.
 pub struct Options {
     pub verbosity: u64,
     pub watch: bool,
     pub compiler: String,
     pub connectivity: crate::deps::ConnectivityStrategy,
+    /// Package registry and proxy, used when solving dependencies and by the compiler.
+    pub network: crate::deps::Network,
+    /// Run the tests with both the oldest and the newest allowed dependencies,
+    /// with `--dependencies bounds`.
+    pub dependency_bounds: bool,
     pub files: Vec<String>,
     pub report: String,
+    /// Functions of the Test module, among "only", "skip" and "todo",
+    /// whose calls fail the compilation instead of only being warned about.
+    pub forbid: Vec<String>,
+    /// Only keep the tests which may match this filter.
+    pub filter: Option<String>,
+    /// Package versions forced in the generated elm.json, as exact constraints of the solver.
+    pub pins: Vec<(Pkg, SemVer)>,
+    /// Packages added to the dependencies of the tests before solving them.
+    pub extra_dependencies: Vec<(Pkg, Range<SemVer>)>,
+    /// Vendored packages, replaced in the dependencies by their local source directories.
+    pub local_packages: Vec<(Pkg, PathBuf)>,
+    /// Whether the solved dependencies are written to the lockfile or read from it.
+    pub lock: crate::lock::Mode,
+    /// Answer yes to the prompts, such as adding elm-explorations/test when it is missing.
+    pub yes: bool,
 }
 
 /// Main function, generating and compiling a Runner.elm file.
@@ -34,23 +65,48 @@ pub struct Options {
 ///  5. Compile it.
 pub fn main(elm_home: &Path, elm_project_root: &Path, options: Options) -> anyhow::Result<()> {
     // Prints to stderr the current version
-    let title = format!(
-        "elm-test-rs {} for elm 0.19.1",
-        std::env!("CARGO_PKG_VERSION")
-    );
-    log::warn!("\n{}\n{}\n", &title, "-".repeat(title.len()));
+    crate::utils::print_title();
 
-    let mut project = Project::from_dir(elm_project_root.to_path_buf())?;
+    if options.dependency_bounds {
+        anyhow::bail!(
+            "--dependencies bounds runs the tests twice, use newest or oldest to only compile them"
+        );
+    }
+    let project = Project::from_dir(elm_project_root.to_path_buf())?;
+    let offline = matches!(
+        options.connectivity,
+        crate::deps::ConnectivityStrategy::Offline
+    );
+    let mut project = crate::init::ensure_test_dependency(
+        elm_home,
+        &options.network,
+        project,
+        options.yes,
+        offline,
+    )?;
+    let make = |project: &Project| {
+        let _lock = crate::utils::lock_dir(&tests_root(project))?;
+        main_helper(elm_home, project, &options)
+    };
     if options.watch {
-        project.watch(|proj| main_helper(elm_home, proj, &options).map(|_| ()))
+        project.watch(|proj| make(proj).map(|_| ()))
     } else {
-        match main_helper(elm_home, &project, &options)? {
+        match make(&project)? {
             Output::MakeFailure => anyhow::bail!("Compilation failed"),
             Output::MakeSuccess { .. } => Ok(()),
         }
     }
 }
 
+/// Directory of the generated tests project.
+/// Callers of `main_helper` hold its lock while generating files there.
+pub fn tests_root(project: &Project) -> PathBuf {
+    project
+        .root_directory
+        .join("elm-stuff")
+        .join("tests-0.19.1")
+}
+
 /// Output of running "elm make" on all the tests files.
 pub enum Output {
     MakeFailure,
@@ -58,6 +114,8 @@ pub enum Output {
         tests_root: PathBuf,
         modules_abs_paths: HashSet<PathBuf>,
         compiled_runner: PathBuf,
+        /// Parsed tests modules, to find the positions of their tests without parsing them again.
+        parse_cache: crate::parse_cache::Cache,
     },
 }
 
@@ -92,41 +150,157 @@ pub fn main_helper(
         }
     }
 
-    let tests_root = project
-        .root_directory
-        .join("elm-stuff")
-        .join("tests-0.19.1");
+    let tests_root = tests_root(project);
+
+    // Parse all the tests modules, in parallel, unless they did not change since the last run.
+    let mut parse_cache = crate::parse_cache::Cache::load(&tests_root);
+    parse_cache.parse_all(&modules_abs_paths, num_cpus::get());
+
+    // Leave out the modules quarantined with a skip-module comment,
+    // and replace each of them by a skipped test giving the reason.
+    // Effect modules, which only some packages can define, are also left out
+    // since importing them would break the compilation of the runner.
+    let mut skipped_modules = Vec::new();
+    let mut kept_modules = HashSet::new();
+    for path in modules_abs_paths {
+        let parsed = parse_cache.parse(&path);
+        if parsed.effect_module {
+            let file = pathdiff::diff_paths(&path, &project.root_directory)
+                .unwrap_or_else(|| path.clone());
+            log::warn!(
+                "{} is an effect module, which only elm packages can define, it is left out of the tests",
+                file.display()
+            );
+            continue;
+        }
+        match parsed.skip_module_reason {
+            Some(reason) => skipped_modules.push((path, reason)),
+            None => {
+                kept_modules.insert(path);
+            }
+        }
+    }
+    let modules_abs_paths = kept_modules;
+    skipped_modules.sort();
+    let mut skipped_tests = Vec::new();
+    for (path, reason) in skipped_modules.iter() {
+        let module_name = get_module_name(&project.src_and_test_dirs, path)?;
+        log::warn!("Skipping the module {}: {}", module_name, reason);
+        let label = if reason.is_empty() {
+            module_name
+        } else {
+            format!("{} -- {}", module_name, reason)
+        };
+        skipped_tests.push(format!(
+            "Just (Test.skip (Test.test {} (\\_ -> Expect.pass)))",
+            elm_string(&label)
+        ));
+    }
+
+    // With a filter, only keep the tests which may match it, and the modules defining them.
+    let selected_tests = options
+        .filter
+        .as_ref()
+        .map(|filter| filter_tests(project, &modules_abs_paths, filter, &mut parse_cache));
+    let modules_abs_paths = match &selected_tests {
+        Some(selected) => modules_abs_paths
+            .into_iter()
+            .filter(|path| selected.contains_key(path))
+            .collect(),
+        None => modules_abs_paths,
+    };
+    // Vendored packages are compiled from their local source instead of being dependencies.
+    let mut config = project.config.clone();
+    let mut extra_dependencies = options.extra_dependencies.clone();
+    let local_src_dirs = crate::deps::use_local_packages(
+        &project.root_directory,
+        &mut config,
+        &options.local_packages,
+        &mut extra_dependencies,
+    )?;
+
     // Make src dirs relative to the generated tests root
     let source_directories_for_runner = project
         .src_and_test_dirs
         .iter()
-        .map(|path| {
-            pathdiff::diff_paths(&path, &tests_root).context(format!(
-                "Could not get path {} relative to path {}",
-                path.display(),
-                tests_root.display()
-            ))
-        })
+        .chain(local_src_dirs.iter())
+        .map(|path| crate::utils::relative_source_dir(path, &tests_root))
         .chain(
             // Add src/ to the source directories for Runner.elm
-            std::iter::once(Ok("src".into())),
+            std::iter::once("src".into()),
         )
-        .collect::<Result<Vec<PathBuf>, _>>()?;
+        .collect::<Vec<PathBuf>>();
 
     // Generate an elm.json for the to-be-generated Runner.elm.
     log::info!("Generating the elm.json for the Runner.elm");
-    let tests_config = crate::deps::solve(
-        elm_home,
+    let lock_inputs = crate::lock::inputs(
+        &config,
         &options.connectivity,
-        &project.config,
-        source_directories_for_runner.as_slice(),
-    )
-    .context("Failed to solve dependencies for tests to run")?;
+        &extra_dependencies,
+        &options.pins,
+    );
+    // Inputs of the solved dependencies, saved next to the generated elm.json to reuse them.
+    let mut solved_inputs = None;
+    let tests_config = match options.lock {
+        crate::lock::Mode::Locked | crate::lock::Mode::Frozen => {
+            let dependencies = crate::lock::read(
+                elm_home,
+                &project.root_directory,
+                &lock_inputs,
+                options.lock,
+            )?;
+            crate::deps::application_config(source_directories_for_runner.as_slice(), dependencies)
+        }
+        crate::lock::Mode::Update | crate::lock::Mode::Ignore => {
+            let solved_key = solved_key(&options.connectivity, &options.network, &lock_inputs);
+            let cached = SOLVED
+                .with(|solved| solved.borrow().get(&solved_key).cloned())
+                .or_else(|| previously_solved(&tests_root, &solved_key));
+            let tests_config = match cached {
+                Some(dependencies) => {
+                    log::info!("Reusing the dependencies already solved with the same inputs");
+                    crate::deps::application_config(
+                        source_directories_for_runner.as_slice(),
+                        dependencies,
+                    )
+                }
+                None => crate::deps::solve(
+                    elm_home,
+                    &options.network,
+                    &options.connectivity,
+                    &config,
+                    source_directories_for_runner.as_slice(),
+                    &extra_dependencies,
+                    &options.pins,
+                )
+                .context("Failed to solve dependencies for tests to run")?,
+            };
+            SOLVED.with(|solved| {
+                solved
+                    .borrow_mut()
+                    .insert(solved_key.clone(), tests_config.dependencies.clone())
+            });
+            solved_inputs = Some(solved_key);
+            if options.lock == crate::lock::Mode::Update {
+                // The lockfile is only a convenience for later runs, so failing to write it
+                // must not fail this one.
+                if let Err(e) = crate::lock::write(
+                    &project.root_directory,
+                    &lock_inputs,
+                    &tests_config.dependencies,
+                ) {
+                    log::warn!("Failed to save the lockfile: {:#}", e);
+                }
+            }
+            tests_config
+        }
+    };
     log::info!(
         "The dependencies picked to run the tests are:\n{}",
         serde_json::to_string_pretty(&tests_config.dependencies)
             .context("Failed to convert to JSON the picked dependencies")?,
     );
+    let test_only_packages = test_only_packages(&config, &tests_config.dependencies);
     let tests_config = ProjectConfig::Application(tests_config);
     let tests_config_path = tests_root.join("elm.json");
     std::fs::create_dir_all(tests_root.join("src")).context(format!(
@@ -138,9 +312,17 @@ pub fn main_helper(
         .context("Failed to convert to JSON the string generated for the tests elm.json")?;
     match std::fs::read_to_string(&tests_config_path) {
         Ok(old_conf) if tests_config_str == old_conf => (),
-        _ => std::fs::write(tests_config_path, tests_config_str)
+        _ => crate::utils::write_atomic(tests_config_path, tests_config_str)
             .context("Unable to write to generated elm.json")?,
     };
+    let solved_inputs_path = tests_root.join(SOLVED_INPUTS_FILE);
+    match solved_inputs {
+        Some(inputs) => crate::utils::write_atomic(&solved_inputs_path, hash(&inputs))
+            .context(format!("Unable to write {}", solved_inputs_path.display()))?,
+        None => {
+            let _ = std::fs::remove_file(&solved_inputs_path);
+        }
+    }
 
     // Find module names
     let mut module_names = Vec::new();
@@ -154,15 +336,63 @@ pub fn main_helper(
         .map(|m| format!("import {}", m))
         .collect();
 
-    // Find all potential tests
+    // Find all potential tests, and report calls to Test.only, Test.skip and Test.todo,
+    // and duplicated labels, before spending time compiling.
     log::info!("Finding all potential tests ...");
     let mut potential_tests = Vec::new();
-    for (module_name, path) in module_names.iter().zip(&modules_abs_paths) {
-        let source =
-            fs::read_to_string(&path).context(format!("Failed to read {}", path.display()))?;
-        for potential_test in crate::parser::potential_tests(&source) {
-            potential_tests.push(format!("check {}.{}", module_name, potential_test));
+    let mut modules_without_tests = Vec::new();
+    let mut allowed = true;
+    let mut discovered: Vec<(&String, &PathBuf)> =
+        module_names.iter().zip(&modules_abs_paths).collect();
+    discovered.sort_unstable_by_key(|(module_name, _)| *module_name);
+    for (module_name, path) in discovered {
+        let parsed = parse_cache.parse(path);
+        allowed &= check_test_module(project, module_name, path, &parsed, &options.forbid);
+        if parsed.potential_tests.is_empty() && !parse_cache.has_problems(path) {
+            modules_without_tests.push(module_name.as_str());
+        }
+        let is_selected = |test: &String| match &selected_tests {
+            Some(selected) => selected[path].contains(test),
+            None => true,
+        };
+        for potential_test in parsed.potential_tests.iter().filter(|t| is_selected(t)) {
+            potential_tests.push(format!(
+                "check {} {}.{}",
+                elm_string(module_name),
+                module_name,
+                potential_test
+            ));
         }
+        let unexposed = parsed.unexposed_tests;
+        if !unexposed.is_empty() {
+            log::warn!(
+                "{} defines tests that are not exposed, and will never run: {}",
+                module_name,
+                unexposed.join(", ")
+            );
+        }
+    }
+    if !modules_without_tests.is_empty() {
+        // A helper module was probably misplaced, or an exposing list is wrong.
+        modules_without_tests.sort_unstable();
+        log::warn!(
+            "No tests found in these modules, since they expose no value which could be a test:\n    {}",
+            modules_without_tests.join("\n    ")
+        );
+    }
+    if !allowed {
+        report_diagnostics(project, &modules_abs_paths, &parse_cache);
+        return Ok(Output::MakeFailure);
+    }
+    potential_tests.extend(skipped_tests);
+
+    let unreadable = report_diagnostics(project, &modules_abs_paths, &parse_cache);
+    if let Err(e) = parse_cache.save() {
+        log::warn!("Failed to save the parse cache: {:#}", e);
+    }
+    if unreadable {
+        log::error!("Some tests modules cannot be read, stopping before compiling them");
+        return Ok(Output::MakeFailure);
     }
 
     // Generate templated src/Runner.elm
@@ -178,6 +408,19 @@ pub fn main_helper(
     )
     .context(format!("Failed to write {}", runner_elm_file.display()))?;
 
+    // Generate the src/ElmTestRs/ modules, for the tests to read fixture files and compare snapshots
+    let elm_test_rs_dir = tests_root.join("src").join("ElmTestRs");
+    std::fs::create_dir_all(&elm_test_rs_dir)
+        .context("Could not create the ElmTestRs directory of the tests")?;
+    for (module, template) in &[
+        ("Fixture.elm", include_template!("Fixture.elm")),
+        ("Snapshot.elm", include_template!("Snapshot.elm")),
+    ] {
+        let module_file = elm_test_rs_dir.join(module);
+        crate::utils::write_atomic(&module_file, template)
+            .context(format!("Failed to write {}", module_file.display()))?;
+    }
+
     // Compile the src/Runner.elm file into Runner.elm.js
     let _preparation_time = start_time.elapsed().as_secs_f32();
     log::info!("Spent {}s generating Runner.elm", _preparation_time);
@@ -185,6 +428,7 @@ pub fn main_helper(
     let compiled_runner = tests_root.join("js").join("Runner.elm.js");
     if compile(
         elm_home,
+        &options.network,
         &tests_root,       // current_dir
         &options.compiler, // compiler
         &compiled_runner,  // output
@@ -194,18 +438,342 @@ pub fn main_helper(
     .success()
     {
         log::warn!("✓ Compilation of tests modules succeeded");
+        // Modules of the source directories importing test-only packages compile with the tests,
+        // since all their dependencies are direct ones, but not in the project itself.
+        let allowed =
+            check_test_only_imports(elm_home, project, &test_only_packages, &mut parse_cache)?;
+        if let Err(e) = parse_cache.save() {
+            log::warn!("Failed to save the parse cache: {:#}", e);
+        }
+        if !allowed {
+            return Ok(Output::MakeFailure);
+        }
         Ok(Output::MakeSuccess {
             tests_root,
             modules_abs_paths,
             compiled_runner,
+            parse_cache,
         })
     } else {
         Ok(Output::MakeFailure)
     }
 }
 
+/// Key of the dependencies solved for the given lockfile inputs,
+/// which also depends on the connectivity since offline solving may pick other versions,
+/// and on the registry since a mirror may not have the same ones.
+fn solved_key(
+    connectivity: &crate::deps::ConnectivityStrategy,
+    network: &crate::deps::Network,
+    lock_inputs: &serde_json::Value,
+) -> String {
+    format!(
+        "{:?} {} {}",
+        connectivity,
+        network.registry_url(),
+        lock_inputs
+    )
+}
+
+/// Hash of the inputs of the dependency solver, to know if they changed since the previous run.
+fn hash(inputs: &str) -> String {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    inputs.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Dependencies of the generated elm.json, if they were solved for the same inputs,
+/// to avoid solving them again at every run.
+fn previously_solved(tests_root: &Path, inputs: &str) -> Option<AppDependencies> {
+    let solved_hash = std::fs::read_to_string(tests_root.join(SOLVED_INPUTS_FILE)).ok()?;
+    if solved_hash.trim() != hash(inputs) {
+        return None;
+    }
+    let elm_json_str = std::fs::read_to_string(tests_root.join("elm.json")).ok()?;
+    match serde_json::from_str(&elm_json_str).ok()? {
+        ProjectConfig::Application(app_config) => Some(app_config.dependencies),
+        ProjectConfig::Package(_) => None,
+    }
+}
+
+/// Packages of the tests dependencies which are not direct dependencies of the project itself,
+/// such as elm-explorations/test or the test runner.
+fn test_only_packages(
+    config: &ProjectConfig,
+    dependencies: &AppDependencies,
+) -> Vec<(Pkg, SemVer)> {
+    let is_direct = |pkg: &Pkg| match config {
+        ProjectConfig::Application(app_config) => app_config.dependencies.direct.contains_key(pkg),
+        ProjectConfig::Package(pkg_config) => pkg_config.dependencies.contains_key(pkg),
+    };
+    dependencies
+        .direct
+        .iter()
+        .chain(dependencies.indirect.iter())
+        .filter(|(pkg, _)| !is_direct(pkg))
+        .map(|(pkg, version)| (pkg.clone(), *version))
+        .collect()
+}
+
+/// Report the modules outside of the tests directory importing modules of test-only packages.
+/// Those imports compile with the tests, but not in the project itself.
+/// Returns false if there are some.
+fn check_test_only_imports(
+    elm_home: &Path,
+    project: &Project,
+    test_only_packages: &[(Pkg, SemVer)],
+    parse_cache: &mut crate::parse_cache::Cache,
+) -> anyhow::Result<bool> {
+    let mut package_of_module = HashMap::new();
+    for (pkg, version) in test_only_packages {
+        match crate::deps::exposed_modules(elm_home, pkg, *version) {
+            Ok(modules) => package_of_module.extend(modules.into_iter().map(|m| (m, pkg))),
+            Err(e) => log::warn!("Imports of {} are not checked: {:#}", pkg, e),
+        }
+    }
+    let tests_dir = project.root_directory.join("tests");
+    let graph = crate::imports::ImportGraph::build(project, parse_cache);
+    let mut problems = Vec::new();
+    for (path, module) in graph.modules() {
+        if path.starts_with(&tests_dir) {
+            continue;
+        }
+        for import in graph.imports(module) {
+            if graph.path(import).is_some() {
+                continue;
+            }
+            if let Some(pkg) = package_of_module.get(import) {
+                let file = pathdiff::diff_paths(path, &project.root_directory)
+                    .unwrap_or_else(|| path.to_path_buf());
+                problems.push(format!(
+                    "{} imports {} from {}",
+                    file.display(),
+                    import,
+                    pkg
+                ));
+            }
+        }
+    }
+    if problems.is_empty() {
+        return Ok(true);
+    }
+    problems.sort();
+    log::error!(
+        "These modules outside of tests/ import test-only packages, so they only compile with the tests:\n\n    {}\n",
+        problems.join("\n    ")
+    );
+    Ok(false)
+}
+
+/// Print together the problems found while parsing the tests modules,
+/// such as unreadable files or invalid syntax.
+/// Returns true if some modules could not be read at all.
+fn report_diagnostics(
+    project: &Project,
+    modules_abs_paths: &HashSet<PathBuf>,
+    parse_cache: &crate::parse_cache::Cache,
+) -> bool {
+    // Source modules parsed for their imports are checked by the compiler instead.
+    let mut diagnostics = parse_cache
+        .diagnostics()
+        .filter(|diagnostic| modules_abs_paths.contains(&diagnostic.path))
+        .peekable();
+    if diagnostics.peek().is_none() {
+        return false;
+    }
+    let mut unreadable = false;
+    log::error!("\nProblems found while looking for tests:\n");
+    for diagnostic in diagnostics {
+        unreadable |= diagnostic.unreadable;
+        let file = pathdiff::diff_paths(&diagnostic.path, &project.root_directory)
+            .unwrap_or_else(|| diagnostic.path.clone());
+        match diagnostic.line {
+            Some(line) => log::error!("{}:{}: {}", file.display(), line, diagnostic.message),
+            None => log::error!("{}: {}", file.display(), diagnostic.message),
+        }
+    }
+    log::error!("");
+    unreadable
+}
+
+/// Warn about the calls to Test.only and Test.skip in a tests module,
+/// with their file and line, list its todo tests, and report its top-level tests sharing the same label,
+/// which elm-explorations/test rejects at runtime.
+/// Returns false if some calls are forbidden or some labels are duplicated.
+fn check_test_module(
+    project: &Project,
+    module_name: &str,
+    path: &Path,
+    parsed: &crate::parse_cache::ParsedModule,
+    forbid: &[String],
+) -> bool {
+    let file =
+        pathdiff::diff_paths(path, &project.root_directory).unwrap_or_else(|| path.to_path_buf());
+    let mut allowed = true;
+    // Todo tests are reported below, with their labels.
+    for (function, line) in parsed.focus_calls.iter().filter(|(f, _)| f != "todo") {
+        if forbid.iter().any(|f| f == function) {
+            log::error!(
+                "{}:{}: Test.{} is forbidden by --forbid-{}",
+                file.display(),
+                line,
+                function,
+                function
+            );
+            allowed = false;
+        } else {
+            log::warn!("{}:{}: uses Test.{}", file.display(), line, function);
+        }
+    }
+    let todos = &parsed.todos;
+    if forbid.iter().any(|f| f == "todo") {
+        for (label, line) in todos.iter() {
+            log::error!(
+                "{}:{}: Test.todo {} is forbidden by --forbid-todo",
+                file.display(),
+                line,
+                todo_name(label)
+            );
+            allowed = false;
+        }
+    } else if !todos.is_empty() {
+        let names: Vec<String> = todos
+            .iter()
+            .map(|(label, line)| format!("{} (line {})", todo_name(label), line))
+            .collect();
+        let plural = if todos.len() > 1 { "s" } else { "" };
+        log::warn!(
+            "{} todo{} in {}: {}",
+            todos.len(),
+            plural,
+            file.display(),
+            names.join(", ")
+        );
+    }
+    for (label, first_line, line) in parsed.duplicate_labels.iter() {
+        log::error!(
+            "{}: the label \"{}\" is used by several sibling tests, in {} at lines {} and {}",
+            module_name,
+            label,
+            file.display(),
+            first_line,
+            line
+        );
+        allowed = false;
+    }
+    allowed
+}
+
+/// Quoted label of a todo test, unless it is not a string literal.
+fn todo_name(label: &str) -> String {
+    if label == crate::parser::COMPUTED_LABEL {
+        label.to_string()
+    } else {
+        format!("\"{}\"", label)
+    }
+}
+
+/// Potential tests which may have tests matching the filter, by tests module.
+/// The runner matches the filter against the labels of a test and its groups joined by spaces,
+/// so each word of the filter must be in one of the labels.
+/// Labels are among the string literals of the test declaration, of the values of its module
+/// it refers to, and of the project modules imported by its module, since they may define tests too.
+/// The runner then only runs the matching tests of the values kept.
+/// Modules where some labels are computed, such as `test ("case " ++ name)`, keep all their tests.
+/// If no test matches, they are all kept for the runner to report that no test matched.
+fn filter_tests(
+    project: &Project,
+    modules_abs_paths: &HashSet<PathBuf>,
+    filter: &str,
+    parse_cache: &mut crate::parse_cache::Cache,
+) -> HashMap<PathBuf, HashSet<String>> {
+    let words: Vec<&str> = filter.split_whitespace().collect();
+    let found_words = |literals: &[String]| -> HashSet<&str> {
+        let found = words
+            .iter()
+            .filter(|word| literals.iter().any(|l| l.contains(*word)));
+        found.copied().collect()
+    };
+    let graph = crate::imports::ImportGraph::build(project, parse_cache);
+    // Words of the filter found in the possible labels of each module, if none are computed.
+    let mut module_words: HashMap<String, Option<HashSet<&str>>> = HashMap::new();
+    let mut all_tests = HashMap::new();
+    let mut selected = HashMap::new();
+    for path in modules_abs_paths {
+        let parsed = parse_cache.parse(path);
+        let tests: HashSet<String> = parsed.potential_tests.iter().cloned().collect();
+        all_tests.insert(path.clone(), tests.clone());
+        // Words found in the imported modules, for all the tests of the module.
+        let mut imported_words = match (graph.module(path), &parsed.possible_labels) {
+            (Some(_), Some(_)) if !words.is_empty() => Some(HashSet::new()),
+            _ => None,
+        };
+        for dependency in graph.dependencies(graph.module(path).unwrap_or_default()) {
+            if Some(dependency.as_str()) == graph.module(path) {
+                continue;
+            }
+            let found = module_words.entry(dependency.clone()).or_insert_with(|| {
+                let dependency_path = graph.path(&dependency).unwrap(); // unwrap is fine since it is a module of the project
+                let labels = parse_cache.parse(dependency_path).possible_labels;
+                labels.map(|labels| found_words(&labels))
+            });
+            imported_words = match (imported_words, found) {
+                (Some(all), Some(found)) => Some(all.union(found).copied().collect()),
+                _ => None,
+            };
+        }
+        let imported_words = match imported_words {
+            Some(imported_words) => imported_words,
+            None => {
+                selected.insert(path.clone(), tests);
+                continue;
+            }
+        };
+        let declarations: HashMap<&str, (&[String], &[String])> = parsed
+            .declarations
+            .iter()
+            .map(|(name, literals, references)| (name.as_str(), (&literals[..], &references[..])))
+            .collect();
+        let may_match = |test: &str| {
+            let mut found = imported_words.clone();
+            let mut visited = HashSet::new();
+            let mut to_visit = vec![test];
+            while let Some(name) = to_visit.pop() {
+                if !visited.insert(name) {
+                    continue;
+                }
+                match declarations.get(name) {
+                    Some((literals, references)) => {
+                        found.extend(found_words(literals));
+                        to_visit.extend(references.iter().map(|r| r.as_str()));
+                    }
+                    // Not a known declaration, it may be anything.
+                    None => return true,
+                }
+            }
+            found.len() == words.len()
+        };
+        let matching: HashSet<String> = tests.into_iter().filter(|t| may_match(t)).collect();
+        if !matching.is_empty() {
+            selected.insert(path.clone(), matching);
+        }
+    }
+    let count = |tests: &HashMap<PathBuf, HashSet<String>>| tests.values().map(|t| t.len()).sum();
+    let (total, kept): (usize, usize) = (count(&all_tests), count(&selected));
+    if kept == 0 {
+        return all_tests;
+    }
+    log::info!(
+        "Leaving out {} of the {} potential tests, which cannot match the filter",
+        total - kept,
+        total
+    );
+    selected
+}
+
 /// List recursively all elm files within a given directory.
-fn elm_files_within<P: AsRef<Path>>(directory: P) -> impl Iterator<Item = PathBuf> {
+pub fn elm_files_within<P: AsRef<Path>>(directory: P) -> impl Iterator<Item = PathBuf> {
     let walker = WalkDir::new(directory).follow_links(true);
     let entries = walker.into_iter().filter_map(|e| e.ok());
     entries.map(|e| e.into_path()).filter(|p| is_elm_file(&p))
@@ -216,7 +784,7 @@ fn is_elm_file<P: AsRef<Path>>(p: P) -> bool {
 }
 
 /// Collect absolute paths of all elm files matching the patterns given as arguments.
-fn get_elm_modules_abs_paths(args: &[String]) -> anyhow::Result<HashSet<PathBuf>> {
+pub fn get_elm_modules_abs_paths(args: &[String]) -> anyhow::Result<HashSet<PathBuf>> {
     let mut glob_err = Ok(());
     let abs_paths: HashSet<PathBuf> = args
         .iter()
@@ -261,6 +829,7 @@ fn absolute_elm_path(path: &Path) -> anyhow::Result<PathBuf> {
 /// Compile an Elm module into a JS file (without --optimized)
 pub fn compile<P1, P2, I, S>(
     elm_home: &Path,
+    network: &crate::deps::Network,
     current_dir: P1,
     compiler: &str,
     output: P2,
@@ -301,6 +870,7 @@ If you installed elm locally with npm, maybe try running with npx such as:
     if executable.extension() == Some(OsStr::new("cmd")) {
         shell_command(
             elm_home,
+            network,
             compiler,
             src,
             output,
@@ -311,6 +881,7 @@ If you installed elm locally with npm, maybe try running with npx such as:
     } else {
         Command::new(executable)
             .env("ELM_HOME", elm_home)
+            .envs(network_env(network))
             .arg("make")
             .arg(format!("--output={}", output))
             .args(report_arg)
@@ -325,10 +896,26 @@ If you installed elm locally with npm, maybe try running with npx such as:
     }
 }
 
+/// Environment variables giving the registry and proxy to the compiler,
+/// for the packages it downloads itself.
+fn network_env(network: &crate::deps::Network) -> Vec<(&str, &str)> {
+    let proxy = network.proxy.as_deref();
+    let registry = network.registry.as_deref();
+    let vars = vec![
+        ("HTTPS_PROXY", proxy),
+        ("HTTP_PROXY", proxy),
+        (crate::deps::REGISTRY_VAR, registry),
+    ];
+    vars.into_iter()
+        .filter_map(|(var, value)| Some((var, value?)))
+        .collect()
+}
+
 /// Uses cmd to execute a command on Windows
 #[cfg(windows)]
 fn shell_command<I, S>(
     elm_home: &Path,
+    network: &crate::deps::Network,
     compiler: &str,
     src: I,
     output: &str,
@@ -342,6 +929,7 @@ where
     log::debug!("Trying with a cmd shell");
     Command::new("cmd")
         .env("ELM_HOME", elm_home)
+        .envs(network_env(network))
         .arg("/D")
         .arg("/Q")
         .arg("/C")
@@ -363,6 +951,7 @@ where
 #[allow(unused_variables)]
 fn shell_command<I, S>(
     elm_home: &Path,
+    network: &crate::deps::Network,
     compiler: &str,
     src: I,
     output: &str,
@@ -386,29 +975,29 @@ pub fn create_templated<P: AsRef<Path>>(
     replacements
         .iter()
         .for_each(|(from, to)| output_str = output_str.replacen(from, to, 1));
-    std::fs::write(output, output_str)
+    crate::utils::write_atomic(output, output_str)
 }
 
 /// By finding the module name from the file path we can import it even if
 /// the file is full of errors. Elm will then report what’s wrong.
-fn get_module_name(
+pub fn get_module_name(
     source_dirs: impl IntoIterator<Item = impl AsRef<Path>>,
     file: impl AsRef<Path>,
 ) -> anyhow::Result<String> {
     log::debug!("get_module_name of: {}", file.as_ref().display());
     let file = file.as_ref();
-    let matching_source_dir = {
-        let mut matching = source_dirs.into_iter().filter(|dir| file.starts_with(dir));
-        match (matching.next(), matching.next()) {
-            (None, None) => {
-                anyhow::bail!(
-                    "This file \"{}\" matches no source directory! Imports won’t work then.",
-                    file.display()
-                )
-            }
-            (Some(dir), None) => dir,
-            _ => anyhow::bail!("2+ matching source dirs for file \"{}\".", file.display()),
-        }
+    // With nested source directories, such as "src" and "src/generated",
+    // the module name is relative to the deepest one, like the compiler does.
+    let matching_source_dir = match source_dirs
+        .into_iter()
+        .filter(|dir| file.starts_with(dir))
+        .max_by_key(|dir| dir.as_ref().components().count())
+    {
+        Some(dir) => dir,
+        None => anyhow::bail!(
+            "This file \"{}\" matches no source directory! Imports won’t work then.",
+            file.display()
+        ),
     };
 
     let trimmed: PathBuf = file
@@ -442,8 +1031,101 @@ fn get_module_name(
     Ok(module_name_parts.join("."))
 }
 
+/// Elm string literal with the given content.
+fn elm_string(content: &str) -> String {
+    let escaped = content
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+/// Module names start with an uppercase letter, followed by letters, digits or underscores,
+/// where letters and digits are not limited to ASCII, like `Données.Élève`.
 fn is_valid_module_name(name: &str) -> bool {
-    !name.is_empty()
-        && name.chars().next().unwrap().is_uppercase() // unwrap() is fine here
-        && name.chars().all(|c| c == '_' || c.is_alphanumeric())
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.is_uppercase() && chars.all(|c| c == '_' || c.is_alphanumeric()),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_module_name, hash, previously_solved, solved_key, SOLVED_INPUTS_FILE};
+    use crate::deps::ConnectivityStrategy;
+    use pubgrub::version::SemanticVersion as SemVer;
+    use pubgrub_dependency_provider_elm::project_config::{
+        AppDependencies, ApplicationConfig, Pkg, ProjectConfig,
+    };
+
+    #[test]
+    fn module_name_in_nested_source_dirs() {
+        let dirs = ["/project/src", "/project/src/generated", "/project/tests"];
+        let name = |file: &str| get_module_name(dirs, file).ok();
+        assert_eq!(
+            name("/project/src/Data/User.elm"),
+            Some("Data.User".to_string())
+        );
+        assert_eq!(
+            name("/project/src/generated/Api.elm"),
+            Some("Api".to_string())
+        );
+        assert_eq!(name("/project/tests/Tests.elm"), Some("Tests".to_string()));
+        assert_eq!(name("/elsewhere/Tests.elm"), None);
+    }
+
+    #[test]
+    fn unicode_module_names() {
+        let dirs = ["/projet/tésts", "/プロジェクト/src"];
+        let name = |file: &str| get_module_name(dirs, file).ok();
+        assert_eq!(
+            name("/projet/tésts/Données/Élève.elm"),
+            Some("Données.Élève".to_string())
+        );
+        assert_eq!(
+            name("/projet/tésts/Ωmega_2.elm"),
+            Some("Ωmega_2".to_string())
+        );
+        assert_eq!(
+            name("/プロジェクト/src/Ünicode.elm"),
+            Some("Ünicode".to_string())
+        );
+        assert_eq!(name("/projet/tésts/élève.elm"), None);
+        assert_eq!(name("/projet/tésts/Élève-2.elm"), None);
+    }
+
+    #[test]
+    fn solved_dependencies_miss_on_indirect_changes() {
+        let config = |core: SemVer| {
+            ProjectConfig::Application(ApplicationConfig {
+                source_directories: vec!["src".to_string()],
+                elm_version: SemVer::new(0, 19, 1),
+                dependencies: AppDependencies {
+                    direct: vec![(Pkg::new("elm", "browser"), SemVer::new(1, 0, 2))]
+                        .into_iter()
+                        .collect(),
+                    indirect: vec![(Pkg::new("elm", "core"), core)].into_iter().collect(),
+                },
+                test_dependencies: AppDependencies {
+                    direct: Default::default(),
+                    indirect: Default::default(),
+                },
+            })
+        };
+        let key = |core: SemVer| {
+            let inputs =
+                crate::lock::inputs(&config(core), &ConnectivityStrategy::Progressive, &[], &[]);
+            let network = crate::deps::Network::default();
+            solved_key(&ConnectivityStrategy::Progressive, &network, &inputs)
+        };
+        let solved = key(SemVer::new(1, 0, 5));
+        let changed = key(SemVer::new(1, 0, 4));
+        assert_ne!(solved, changed);
+        let dir = std::env::temp_dir().join(format!("elm-test-rs-solved-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(SOLVED_INPUTS_FILE), hash(&solved)).unwrap();
+        assert!(previously_solved(&dir, &changed).is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,146 @@
+//! Run the tests of all the projects of a monorepo.
+//!
+//! Candidate projects are the directories beneath the given one
+//! with both an `elm.json` and a `tests/` directory.
+//! Hidden directories, `elm-stuff/` and `node_modules/` are not searched.
+//! The tests of each project are run one after the other, in the same process,
+//! so dependencies solved for one project are reused by the next ones with the same inputs.
+
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Find the candidate projects beneath a directory, sorted by path.
+pub fn find_projects(dir: &Path) -> Vec<PathBuf> {
+    let searched = |entry: &walkdir::DirEntry| {
+        let name = entry.file_name().to_string_lossy();
+        entry.depth() == 0
+            || !(name.starts_with('.') || name == "elm-stuff" || name == "node_modules")
+    };
+    let mut projects: Vec<PathBuf> = WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(searched)
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_dir())
+        .map(|entry| entry.into_path())
+        .filter(|path| path.join("elm.json").is_file() && path.join("tests").is_dir())
+        .collect();
+    projects.sort();
+    projects
+}
+
+/// Path of a project, relative to the searched directory.
+fn display(dir: &Path, project: &Path) -> String {
+    match pathdiff::diff_paths(project, dir) {
+        Some(path) if path.as_os_str().is_empty() => ".".to_string(),
+        Some(path) => path.display().to_string(),
+        None => project.display().to_string(),
+    }
+}
+
+/// Error when no elm.json was found, followed by the candidate projects beneath the directory if any.
+pub fn with_candidate_projects(error: anyhow::Error, dir: &str) -> anyhow::Error {
+    let dir = match crate::utils::absolute_path(dir) {
+        Ok(dir) => dir,
+        Err(_) => return error,
+    };
+    let projects = find_projects(&dir);
+    if projects.is_empty() {
+        return error;
+    }
+    let listed: Vec<String> = projects.iter().map(|p| display(&dir, p)).collect();
+    anyhow::anyhow!(
+        "{:#}\n\nThere are Elm projects beneath this directory:\n\n    {}\n\nPick one with --project <path>, or run the tests of all of them with --all",
+        error,
+        listed.join("\n    ")
+    )
+}
+
+/// Run the tests of every project beneath the directory, or only compile them.
+/// Headers and the final summary are printed to stderr,
+/// so that the reports printed to stdout can be aggregated, one per project.
+/// Returns the highest exit code of all runs.
+pub fn main(
+    dir: &Path,
+    make_options: &crate::make::Options,
+    run_project: impl Fn(&Path) -> anyhow::Result<i32>,
+) -> anyhow::Result<i32> {
+    if make_options.watch {
+        anyhow::bail!("--all is incompatible with --watch");
+    }
+    if !make_options.files.is_empty() {
+        anyhow::bail!(
+            "--all runs all the tests of each project, and is incompatible with files arguments"
+        );
+    }
+    let projects = find_projects(dir);
+    if projects.is_empty() {
+        anyhow::bail!(
+            "There is no Elm project with a tests/ directory beneath {}",
+            dir.display()
+        );
+    }
+
+    let mut results = Vec::new();
+    for project in projects.iter() {
+        let name = display(dir, project);
+        log::error!("\n==> {}", name);
+        let exit_code = match run_project(project) {
+            Ok(exit_code) => exit_code,
+            Err(e) => {
+                log::error!("{:#}", e);
+                1
+            }
+        };
+        results.push((name, exit_code));
+    }
+
+    let failed = results.iter().filter(|(_, code)| *code != 0).count();
+    let summary: Vec<String> = results
+        .iter()
+        .map(|(name, code)| {
+            let status = if *code == 0 { "passed" } else { "failed" };
+            format!("{}  {}", status, name)
+        })
+        .collect();
+    log::error!(
+        "\n==> {} projects, {} passed, {} failed\n    {}\n",
+        results.len(),
+        results.len() - failed,
+        failed,
+        summary.join("\n    ")
+    );
+    Ok(results.iter().map(|(_, code)| *code).max().unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find_projects;
+
+    #[test]
+    fn projects_beneath_directory() {
+        let dir = std::env::temp_dir().join(format!("elm-test-rs-monorepo-{}", std::process::id()));
+        for project in [
+            "app",
+            "packages/a",
+            "packages/b",
+            "lib/elm-stuff/x",
+            "review",
+        ] {
+            std::fs::create_dir_all(dir.join(project)).unwrap();
+            std::fs::write(dir.join(project).join("elm.json"), "{}").unwrap();
+        }
+        for project in ["app", "packages/a", "packages/b", "lib/elm-stuff/x"] {
+            std::fs::create_dir_all(dir.join(project).join("tests")).unwrap();
+        }
+        let found = find_projects(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            found,
+            vec![
+                dir.join("app"),
+                dir.join("packages/a"),
+                dir.join("packages/b")
+            ]
+        );
+    }
+}
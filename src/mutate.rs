@@ -0,0 +1,391 @@
+//! Mutation testing of the source modules.
+//!
+//! Simple mutations are applied one at a time to a copy of the source directories,
+//! such as flipping a comparison operator, swapping `True` and `False`,
+//! or dropping the first element of a list literal.
+//! The tests depending on the mutated module are then run again.
+//! If they still pass, the mutant "survived", meaning the tests did not
+//! catch that change of behavior.
+
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+
+use crate::imports::ImportGraph;
+use crate::project::Project;
+
+/// A mutation of the source of an Elm module.
+#[derive(Debug, PartialEq)]
+pub struct Mutation {
+    /// Byte offset of the mutated code.
+    pub offset: usize,
+    /// Line of the mutated code (starting at 1).
+    pub line: usize,
+    /// Column of the mutated code (starting at 1).
+    pub column: usize,
+    /// The original code.
+    pub original: String,
+    /// The code replacing the original one.
+    pub replacement: String,
+}
+
+impl Mutation {
+    /// Apply the mutation to the source it was found in.
+    pub fn apply(&self, source: &str) -> String {
+        [
+            &source[..self.offset],
+            &self.replacement,
+            &source[self.offset + self.original.len()..],
+        ]
+        .concat()
+    }
+
+    /// Short description of the mutation.
+    pub fn describe(&self) -> String {
+        if self.replacement.is_empty() {
+            "removed the first element of a list".to_string()
+        } else {
+            format!("replaced {} by {}", self.original, self.replacement)
+        }
+    }
+}
+
+/// Wrapper around the tests runs, applying every mutation one after the other.
+/// Returns 0 if all mutants were killed, 1 otherwise.
+pub fn main(
+    elm_home: &Path,
+    elm_project_root: &Path,
+    make_options: crate::make::Options,
+    run_options: crate::run::Options,
+) -> anyhow::Result<i32> {
+    // Prints to stderr the current version
+    crate::utils::print_title();
+
+    // The tests need to pass before introducing mutations.
+    let project = Project::from_dir(elm_project_root)?;
+    if crate::run::main_helper(elm_home, &project, &make_options, &run_options)? != 0 {
+        anyhow::bail!("The tests must pass before running mutation testing");
+    }
+    let run_options = crate::run::Options {
+        quiet: true,
+        ..run_options
+    };
+
+    // Copy the source directories, in which mutations will be applied.
+    let tests_dir = project.root_directory.join("tests");
+    let mutants_dir = project
+        .root_directory
+        .join("elm-stuff")
+        .join("tests-0.19.1")
+        .join("mutants");
+    if mutants_dir.exists() {
+        std::fs::remove_dir_all(&mutants_dir).context(format!(
+            "Could not clean the directory {}",
+            mutants_dir.display()
+        ))?;
+    }
+    let source_dirs: Vec<PathBuf> = project.source_dirs().into_iter().cloned().collect();
+    let mut copies = Vec::new();
+    for (i, dir) in source_dirs.iter().enumerate() {
+        let copy = mutants_dir.join(i.to_string());
+        copy_elm_files(dir, &copy)?;
+        copies.push(copy);
+    }
+
+    // Tests modules that should be run again for each mutated module.
+    let mut parse_cache = crate::parse_cache::Cache::load(&crate::make::tests_root(&project));
+    let graph = ImportGraph::build(&project, &mut parse_cache);
+    let tests_modules: Vec<PathBuf> = if make_options.files.is_empty() {
+        crate::make::elm_files_within(&tests_dir)
+            .map(crate::utils::absolute_path)
+            .collect::<Result<_, _>>()?
+    } else {
+        crate::make::get_elm_modules_abs_paths(&make_options.files)?
+            .into_iter()
+            .collect()
+    };
+
+    let mut mutants_count = 0;
+    let mut survivors = Vec::new();
+    let mut untested = 0;
+    for (dir, copy) in source_dirs.iter().zip(&copies) {
+        for path in crate::make::elm_files_within(dir) {
+            let path = crate::utils::absolute_path(path)?;
+            let module_name = crate::make::get_module_name(std::iter::once(dir), &path)?;
+            let affected_tests: Vec<String> = tests_modules
+                .iter()
+                .filter(|test| depends_on(&graph, test, &module_name))
+                .filter_map(|test| test.to_str().map(|s| s.to_string()))
+                .collect();
+            let source = std::fs::read_to_string(&path)
+                .context(format!("Failed to read {}", path.display()))?;
+            let mutations = mutations(&source);
+            if mutations.is_empty() {
+                continue;
+            }
+            log::error!("Mutating {} ({} mutants) ...", module_name, mutations.len());
+
+            // Run the tests with the mutated copy instead of the original source directory.
+            let mut mutant_project = Project::from_dir(&project.root_directory)?;
+            mutant_project.src_and_test_dirs.remove(dir);
+            mutant_project.src_and_test_dirs.insert(copy.clone());
+            let mutant_make_options = crate::make::Options {
+                files: affected_tests.clone(),
+                ..make_options.clone()
+            };
+            let copied_path = copy.join(path.strip_prefix(dir)?);
+            for mutation in mutations.iter() {
+                mutants_count += 1;
+                // A mutant which does not compile is killed by the compiler.
+                let killed = if affected_tests.is_empty() {
+                    false
+                } else {
+                    std::fs::write(&copied_path, mutation.apply(&source))
+                        .context(format!("Failed to write {}", copied_path.display()))?;
+                    let outcome = crate::run::main_helper(
+                        elm_home,
+                        &mutant_project,
+                        &mutant_make_options,
+                        &run_options,
+                    );
+                    match outcome {
+                        Ok(exit_code) => exit_code != 0,
+                        Err(e) => {
+                            log::warn!(
+                                "Could not test the mutant {}:{}:{}: {:#}",
+                                module_name,
+                                mutation.line,
+                                mutation.column,
+                                e
+                            );
+                            untested += 1;
+                            continue;
+                        }
+                    }
+                };
+                if !killed {
+                    survivors.push(format!(
+                        "{}:{}:{}  {}",
+                        path.strip_prefix(&project.root_directory)
+                            .unwrap_or(&path)
+                            .display(),
+                        mutation.line,
+                        mutation.column,
+                        mutation.describe()
+                    ));
+                }
+            }
+            std::fs::write(&copied_path, &source)
+                .context(format!("Failed to write {}", copied_path.display()))?;
+        }
+    }
+
+    // Report surviving mutants.
+    log::error!(
+        "\n{} mutants killed out of {}",
+        mutants_count - survivors.len() - untested,
+        mutants_count
+    );
+    if untested > 0 {
+        log::error!("{} mutants could not be tested", untested);
+    }
+    if survivors.is_empty() {
+        Ok(0)
+    } else {
+        log::error!("\nSurviving mutants:\n\n{}\n", survivors.join("\n"));
+        Ok(1)
+    }
+}
+
+/// Copy all elm files of a directory into another directory.
+fn copy_elm_files(from: &Path, to: &Path) -> anyhow::Result<()> {
+    for path in crate::make::elm_files_within(from) {
+        let target = to.join(path.strip_prefix(from)?);
+        std::fs::create_dir_all(target.parent().unwrap()) // unwrap is fine since it is in the "to" directory
+            .context(format!(
+                "Could not create the directory for {}",
+                target.display()
+            ))?;
+        std::fs::copy(&path, &target).context(format!(
+            "Failed to copy {} to {}",
+            path.display(),
+            target.display()
+        ))?;
+    }
+    Ok(())
+}
+
+/// Check if a tests module imports, directly or not, the given module.
+/// Tests modules missing from the imports graph may depend on anything.
+fn depends_on(graph: &ImportGraph, test_path: &Path, module: &str) -> bool {
+    match graph.module(test_path) {
+        Some(test_module) => graph.dependencies(test_module).contains(module),
+        None => true,
+    }
+}
+
+/// Find all mutations that can be applied to the source of an Elm module.
+/// Comments, strings and characters are left untouched.
+pub fn mutations(source: &str) -> Vec<Mutation> {
+    let bytes = source.as_bytes();
+    let mut found = Vec::new();
+    let mut record = |offset: usize, original: &str, replacement: &str| {
+        let line = 1 + source[..offset].matches('\n').count();
+        let line_start = source[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        found.push(Mutation {
+            offset,
+            line,
+            column: 1 + source[line_start..offset].chars().count(),
+            original: original.to_string(),
+            replacement: replacement.to_string(),
+        });
+    };
+    // Stack of opened brackets with their offset, and whether a comma was already seen.
+    let mut brackets: Vec<(u8, usize, bool)> = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &source[i..];
+        if rest.starts_with("--") {
+            i += rest.find('\n').unwrap_or(rest.len());
+        } else if rest.starts_with("{-") {
+            i += block_comment_len(rest);
+        } else if let Some(content) = rest.strip_prefix("\"\"\"") {
+            i += 3 + literal_len(content, "\"\"\"");
+        } else if let Some(content) = rest.strip_prefix('"') {
+            i += 1 + literal_len(content, "\"");
+        } else if let Some(content) = rest.strip_prefix('\'') {
+            i += 1 + literal_len(content, "'");
+        } else if b"[({".contains(&bytes[i]) {
+            brackets.push((bytes[i], i, false));
+            i += 1;
+        } else if b"])}".contains(&bytes[i]) {
+            brackets.pop();
+            i += 1;
+        } else if bytes[i] == b',' {
+            if let Some((b'[', open, seen @ false)) = brackets.last_mut() {
+                *seen = true;
+                let after_comma = &source[i + 1..];
+                let spaces = after_comma.len() - after_comma.trim_start().len();
+                let first_element = &source[*open + 1..i + 1 + spaces];
+                record(*open + 1, first_element, "");
+            }
+            i += 1;
+        } else if is_symbol(bytes[i]) {
+            let len = rest.bytes().take_while(|b| is_symbol(*b)).count();
+            let operator = &rest[..len];
+            let flipped = match operator {
+                "==" => Some("/="),
+                "/=" => Some("=="),
+                "<" => Some(">="),
+                ">" => Some("<="),
+                "<=" => Some(">"),
+                ">=" => Some("<"),
+                _ => None,
+            };
+            if let Some(flipped) = flipped {
+                record(i, operator, flipped);
+            }
+            i += len;
+        } else if bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_' {
+            let len = rest
+                .bytes()
+                .take_while(|b| b.is_ascii_alphanumeric() || *b == b'_')
+                .count();
+            match &rest[..len] {
+                "True" => record(i, "True", "False"),
+                "False" => record(i, "False", "True"),
+                _ => (),
+            }
+            i += len;
+        } else {
+            i += rest.chars().next().map(|c| c.len_utf8()).unwrap_or(1);
+        }
+    }
+    found
+}
+
+/// Characters that can be part of an operator.
+fn is_symbol(b: u8) -> bool {
+    b"+-*/<>=|&:^.!%?~".contains(&b)
+}
+
+/// Length of a (potentially nested) block comment starting at the beginning of the string.
+fn block_comment_len(src: &str) -> usize {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < src.len() {
+        if src[i..].starts_with("{-") {
+            depth += 1;
+            i += 2;
+        } else if src[i..].starts_with("-}") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += src[i..].chars().next().map(|c| c.len_utf8()).unwrap_or(1);
+        }
+    }
+    src.len()
+}
+
+/// Length of a string or char literal content, including its closing delimiter.
+fn literal_len(src: &str, delimiter: &str) -> usize {
+    let mut i = 0;
+    while i < src.len() {
+        if src[i..].starts_with('\\') {
+            i += 1;
+        } else if src[i..].starts_with(delimiter) {
+            return i + delimiter.len();
+        }
+        i += src[i..].chars().next().map(|c| c.len_utf8()).unwrap_or(1);
+    }
+    src.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mutations;
+
+    fn mutated(source: &str) -> Vec<String> {
+        mutations(source).iter().map(|m| m.apply(source)).collect()
+    }
+
+    #[test]
+    fn operators_and_booleans() {
+        assert_eq!(
+            mutated("f x = x <= 0 || x == 3 |> always True"),
+            vec![
+                "f x = x > 0 || x == 3 |> always True",
+                "f x = x <= 0 || x /= 3 |> always True",
+                "f x = x <= 0 || x == 3 |> always False",
+            ]
+        );
+    }
+
+    #[test]
+    fn drop_first_list_element() {
+        assert_eq!(
+            mutated("l = [ ( 1, 2 ), ( 3, 4 ) ]"),
+            vec!["l = [( 3, 4 ) ]"]
+        );
+    }
+
+    #[test]
+    fn ignore_comments_and_literals() {
+        let source = r#"
+{-| x == True -}
+s = "a < b \" > c" -- x == y
+c = '<'
+isTrue = isFalse
+"#;
+        assert_eq!(mutations(source), vec![]);
+    }
+
+    #[test]
+    fn position() {
+        let m = &mutations("a =\n    b == c")[0];
+        assert_eq!((m.line, m.column), (2, 7));
+        assert_eq!(m.describe(), "replaced == by /=");
+    }
+}
@@ -0,0 +1,476 @@
+//! Cache of the parsed tests modules.
+//!
+//! Parsing thousands of tests modules at every run, or at every change in watch mode,
+//! takes time even though most of them did not change.
+//! The result of parsing each module is thus saved in
+//! `elm-stuff/tests-0.19.1/parse-cache.json`, keyed by a hash of the module content,
+//! and only modules whose content changed are parsed again.
+//!
+//! Problems found in the modules, such as unreadable files or invalid syntax,
+//! do not stop the parsing of the other modules.
+//! They are collected as diagnostics, to be reported together at the end of the discovery.
+//!
+//! Modules can be parsed in parallel, since repositories with thousands of tests modules
+//! otherwise spend a noticeable fraction of the startup reading and parsing them.
+
+use anyhow::Context;
+use serde_json::{json, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// What elm-test-rs needs to know about a tests module, found by the parser.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ParsedModule {
+    /// Exposed values which may be tests.
+    pub potential_tests: Vec<String>,
+    /// Top-level tests which are not exposed.
+    pub unexposed_tests: Vec<String>,
+    /// Labels of the potential tests directly defined by a function of the Test module.
+    pub test_labels: Vec<(String, String)>,
+    /// Labels of all tests and groups, with their line and column.
+    pub label_positions: Vec<(Vec<String>, usize, usize)>,
+    /// Calls to Test.only and Test.skip, with their line.
+    pub focus_calls: Vec<(String, usize)>,
+    /// Labels of the todo tests, with their line.
+    pub todos: Vec<(String, usize)>,
+    /// Labels shared by several sibling tests, with both lines.
+    pub duplicate_labels: Vec<(String, usize, usize)>,
+    /// String literals which may be labels, unless some labels are computed.
+    pub possible_labels: Option<Vec<String>>,
+    /// Top-level values, with their string literals and the other top-level values they refer to.
+    pub declarations: Vec<(String, Vec<String>, Vec<String>)>,
+    /// Reason of the skip-module comment, if the whole module is skipped.
+    pub skip_module_reason: Option<String>,
+    /// Modules imported by the module.
+    pub imports: Vec<String>,
+    /// Effect modules cannot be compiled in a project, and are left out of the tests.
+    pub effect_module: bool,
+    /// Syntax problems which may prevent finding tests, with their line.
+    pub syntax_problems: Vec<(String, usize)>,
+}
+
+/// A problem found in a tests module, with its line when it is known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+    /// The module could not be read, or is not valid UTF-8, which fails the run.
+    pub unreadable: bool,
+}
+
+impl ParsedModule {
+    /// Parse the source of a tests module.
+    pub fn parse(src: &str) -> ParsedModule {
+        let potential_tests = crate::parser::potential_tests(src);
+        let test_labels = potential_tests
+            .iter()
+            .filter_map(|test| Some((test.to_string(), crate::parser::test_label(src, test)?)))
+            .collect();
+        ParsedModule {
+            potential_tests: potential_tests.iter().map(|t| t.to_string()).collect(),
+            unexposed_tests: crate::parser::unexposed_tests(src)
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+            test_labels,
+            label_positions: crate::parser::label_positions(src),
+            focus_calls: crate::parser::focus_calls(src)
+                .into_iter()
+                .map(|(function, line)| (function.to_string(), line))
+                .collect(),
+            todos: crate::parser::todos(src),
+            duplicate_labels: crate::parser::duplicate_labels(src),
+            possible_labels: crate::parser::possible_labels(src),
+            declarations: crate::parser::declarations(src),
+            skip_module_reason: crate::parser::skip_module_reason(src),
+            imports: crate::parser::imports(src)
+                .into_iter()
+                .map(|m| m.to_string())
+                .collect(),
+            effect_module: crate::parser::is_effect_module(src),
+            syntax_problems: crate::parser::syntax_problems(src),
+        }
+    }
+
+    /// Diagnostics of the syntax problems of the module at the given path.
+    fn syntax_diagnostics(&self, path: &Path) -> Vec<Diagnostic> {
+        self.syntax_problems
+            .iter()
+            .map(|(message, line)| Diagnostic {
+                path: path.to_path_buf(),
+                line: Some(*line),
+                message: message.clone(),
+                unreadable: false,
+            })
+            .collect()
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "potentialTests": self.potential_tests,
+            "unexposedTests": self.unexposed_tests,
+            "testLabels": self.test_labels,
+            "labelPositions": self.label_positions,
+            "focusCalls": self.focus_calls,
+            "todos": self.todos,
+            "duplicateLabels": self.duplicate_labels,
+            "possibleLabels": self.possible_labels,
+            "declarations": self.declarations,
+            "skipModuleReason": self.skip_module_reason,
+            "imports": self.imports,
+            "effectModule": self.effect_module,
+            "syntaxProblems": self.syntax_problems,
+        })
+    }
+
+    /// Returns `None` if the cached value does not have the expected shape,
+    /// such as one written by another version of elm-test-rs.
+    fn from_json(value: &Value) -> Option<ParsedModule> {
+        let array = |key: &str| value[key].as_array();
+        let string = |v: &Value| v.as_str().map(|s| s.to_string());
+        let number = |v: &Value| v.as_u64().map(|n| n as usize);
+        let strings = |key: &str| array(key)?.iter().map(string).collect::<Option<Vec<_>>>();
+        let with_line = |key: &str| {
+            array(key)?
+                .iter()
+                .map(|v| Some((string(&v[0])?, number(&v[1])?)))
+                .collect::<Option<Vec<_>>>()
+        };
+        let with_two_numbers = |key: &str| {
+            array(key)?
+                .iter()
+                .map(|v| Some((string(&v[0])?, number(&v[1])?, number(&v[2])?)))
+                .collect::<Option<Vec<_>>>()
+        };
+        Some(ParsedModule {
+            potential_tests: strings("potentialTests")?,
+            unexposed_tests: strings("unexposedTests")?,
+            test_labels: array("testLabels")?
+                .iter()
+                .map(|v| Some((string(&v[0])?, string(&v[1])?)))
+                .collect::<Option<Vec<_>>>()?,
+            label_positions: array("labelPositions")?
+                .iter()
+                .map(|v| {
+                    let path = v[0].as_array()?.iter().map(string).collect::<Option<_>>()?;
+                    Some((path, number(&v[1])?, number(&v[2])?))
+                })
+                .collect::<Option<Vec<_>>>()?,
+            focus_calls: with_line("focusCalls")?,
+            todos: with_line("todos")?,
+            duplicate_labels: with_two_numbers("duplicateLabels")?,
+            possible_labels: match value["possibleLabels"] {
+                Value::Null => None,
+                _ => Some(strings("possibleLabels")?),
+            },
+            declarations: array("declarations")?
+                .iter()
+                .map(|v| {
+                    let list = |v: &Value| v.as_array()?.iter().map(string).collect::<Option<_>>();
+                    Some((string(&v[0])?, list(&v[1])?, list(&v[2])?))
+                })
+                .collect::<Option<Vec<_>>>()?,
+            skip_module_reason: match &value["skipModuleReason"] {
+                Value::Null => None,
+                reason => Some(string(reason)?),
+            },
+            imports: strings("imports")?,
+            effect_module: value["effectModule"].as_bool()?,
+            syntax_problems: with_line("syntaxProblems")?,
+        })
+    }
+}
+
+/// Parsed modules, indexed by their path, with the hash of their content.
+pub struct Cache {
+    file: PathBuf,
+    modules: HashMap<PathBuf, (String, ParsedModule)>,
+    changed: bool,
+    /// Diagnostics of the modules parsed since the cache was loaded.
+    diagnostics: BTreeMap<PathBuf, Vec<Diagnostic>>,
+}
+
+impl Cache {
+    /// Load the cache of the given tests root, or start an empty one if there is none.
+    pub fn load(tests_root: &Path) -> Cache {
+        let file = tests_root.join("parse-cache.json");
+        let value: Value = fs::read_to_string(&file)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or(Value::Null);
+        let mut modules = HashMap::new();
+        if value["version"].as_str() == Some(std::env!("CARGO_PKG_VERSION")) {
+            for (path, entry) in value["modules"].as_object().into_iter().flatten() {
+                let hash = entry["hash"].as_str();
+                let parsed = ParsedModule::from_json(&entry["parsed"]);
+                if let (Some(hash), Some(parsed)) = (hash, parsed) {
+                    modules.insert(PathBuf::from(path), (hash.to_string(), parsed));
+                }
+            }
+        }
+        Cache {
+            file,
+            modules,
+            changed: false,
+            diagnostics: BTreeMap::new(),
+        }
+    }
+
+    /// Parse the module at the given path, unless its content did not change since last time.
+    /// A module which cannot be read, or is not valid UTF-8, has a diagnostic and nothing else.
+    /// Modules are only read once since the cache was loaded.
+    pub fn parse(&mut self, path: &Path) -> ParsedModule {
+        if self.diagnostics.contains_key(path) {
+            return match self.modules.get(path) {
+                Some((_, parsed)) => parsed.clone(),
+                None => ParsedModule::default(),
+            };
+        }
+        let cached_hash = self.modules.get(path).map(|(hash, _)| hash.clone());
+        let loaded = load_module(path, cached_hash.as_deref());
+        self.record(path.to_path_buf(), loaded)
+    }
+
+    /// Read the module at the given path again at its next `parse`, since it changed.
+    pub fn invalidate(&mut self, path: &Path) {
+        self.diagnostics.remove(path);
+    }
+
+    /// Parse the modules at the given paths in parallel, with the given number of threads,
+    /// before they are retrieved one at a time with `parse`.
+    /// This is faster than parsing them one after the other, when there are many of them.
+    pub fn parse_all(&mut self, paths: &HashSet<PathBuf>, threads: usize) {
+        let to_load: Vec<(PathBuf, Option<String>)> = paths
+            .iter()
+            .filter(|path| !self.diagnostics.contains_key(*path))
+            .map(|path| {
+                let cached_hash = self.modules.get(path).map(|(hash, _)| hash.clone());
+                (path.clone(), cached_hash)
+            })
+            .collect();
+        if to_load.is_empty() {
+            return;
+        }
+        let chunk_size = to_load.len() / threads.max(1) + 1;
+        let workers: Vec<_> = to_load
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let chunk = chunk.to_vec();
+                std::thread::spawn(move || {
+                    chunk
+                        .into_iter()
+                        .map(|(path, cached_hash)| {
+                            let loaded = load_module(&path, cached_hash.as_deref());
+                            (path, loaded)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for worker in workers {
+            match worker.join() {
+                Ok(loaded) => {
+                    for (path, loaded) in loaded {
+                        self.record(path, loaded);
+                    }
+                }
+                // The modules of a failed worker are parsed again by `parse`.
+                Err(_) => log::debug!("A thread parsing the tests modules failed"),
+            }
+        }
+    }
+
+    /// Keep the result of loading a module, and returns what was parsed.
+    fn record(&mut self, path: PathBuf, loaded: Result<Loaded, Diagnostic>) -> ParsedModule {
+        let (parsed, diagnostics) = match loaded {
+            Ok(None) => match self.modules.get(&path) {
+                Some((_, parsed)) => (parsed.clone(), parsed.syntax_diagnostics(&path)),
+                None => (ParsedModule::default(), Vec::new()),
+            },
+            Ok(Some((hash, parsed))) => {
+                let diagnostics = parsed.syntax_diagnostics(&path);
+                self.modules.insert(path.clone(), (hash, parsed.clone()));
+                self.changed = true;
+                (parsed, diagnostics)
+            }
+            Err(diagnostic) => {
+                if self.modules.remove(&path).is_some() {
+                    self.changed = true;
+                }
+                (ParsedModule::default(), vec![diagnostic])
+            }
+        };
+        self.diagnostics.insert(path, diagnostics);
+        parsed
+    }
+
+    /// Returns true if the module at the given path could not be read, or is not valid UTF-8.
+    pub fn is_unreadable(&self, path: &Path) -> bool {
+        self.diagnostics
+            .get(path)
+            .into_iter()
+            .flatten()
+            .any(|d| d.unreadable)
+    }
+
+    /// Returns true if problems were found in the module at the given path.
+    pub fn has_problems(&self, path: &Path) -> bool {
+        self.diagnostics
+            .get(path)
+            .into_iter()
+            .any(|d| !d.is_empty())
+    }
+
+    /// Diagnostics of the modules parsed since the cache was loaded, sorted by path.
+    pub fn diagnostics(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.values().flatten()
+    }
+
+    /// Write the cache to disk if some modules were parsed again, or were deleted.
+    pub fn save(&mut self) -> anyhow::Result<()> {
+        // Entries of deleted modules would otherwise stay in the cache forever.
+        let cached = self.modules.len();
+        self.modules.retain(|path, _| path.is_file());
+        if self.modules.len() != cached {
+            self.changed = true;
+        }
+        if !self.changed {
+            return Ok(());
+        }
+        let modules: serde_json::Map<String, Value> = self
+            .modules
+            .iter()
+            .map(|(path, (hash, parsed))| {
+                let entry = json!({ "hash": hash, "parsed": parsed.to_json() });
+                (path.display().to_string(), entry)
+            })
+            .collect();
+        let cache = json!({
+            "version": std::env!("CARGO_PKG_VERSION"),
+            "modules": modules,
+        });
+        if let Some(dir) = self.file.parent() {
+            fs::create_dir_all(dir).context(format!("Could not create {}", dir.display()))?;
+        }
+        crate::utils::write_atomic(&self.file, cache.to_string())
+            .context(format!("Failed to write {}", self.file.display()))?;
+        self.changed = false;
+        Ok(())
+    }
+}
+
+/// Content hash and parsed module, or `None` if the module did not change.
+type Loaded = Option<(String, ParsedModule)>;
+
+/// Read and parse the module at the given path, unless the hash of its content is the cached one.
+fn load_module(path: &Path, cached_hash: Option<&str>) -> Result<Loaded, Diagnostic> {
+    let source = read_source(path)?;
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    let hash = format!("{:016x}", hasher.finish());
+    if cached_hash == Some(hash.as_str()) {
+        return Ok(None);
+    }
+    log::debug!("Parsing {}", path.display());
+    Ok(Some((hash, ParsedModule::parse(&source))))
+}
+
+/// Read the source of a module, with a diagnostic explaining why it cannot be.
+fn read_source(path: &Path) -> Result<String, Diagnostic> {
+    let diagnostic = |line, message| Diagnostic {
+        path: path.to_path_buf(),
+        line,
+        message,
+        unreadable: true,
+    };
+    let bytes = fs::read(path).map_err(|e| diagnostic(None, format!("Failed to read: {}", e)))?;
+    String::from_utf8(bytes).map_err(|e| {
+        let valid = &e.as_bytes()[..e.utf8_error().valid_up_to()];
+        let line = valid.iter().filter(|b| **b == b'\n').count() + 1;
+        diagnostic(Some(line), "Invalid UTF-8 content".to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cache, ParsedModule};
+
+    #[test]
+    fn json_round_trip() {
+        let src = r#"module Tests exposing (suite, parser)
+
+suite : Test
+suite =
+    describe "Suite"
+        [ only <| test "first" <| \_ -> Expect.pass
+        , todo "later"
+        ]
+
+parser =
+    describe "Suite" []
+
+hidden : Test
+hidden =
+    concat []
+"#;
+        let parsed = ParsedModule::parse(src);
+        assert_eq!(parsed.potential_tests, vec!["suite", "parser"]);
+        assert_eq!(parsed.unexposed_tests, vec!["hidden"]);
+        assert_eq!(parsed.todos, vec![("later".to_string(), 7)]);
+        assert_eq!(ParsedModule::from_json(&parsed.to_json()), Some(parsed));
+    }
+
+    #[test]
+    fn parse_all_in_parallel() {
+        let dir = std::env::temp_dir().join(format!("elm-test-rs-parse-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths: std::collections::HashSet<_> = (0..10)
+            .map(|i| {
+                let path = dir.join(format!("Test{}.elm", i));
+                let src = format!("module Test{} exposing (suite{})\n\nsuite{} = 1\n", i, i, i);
+                std::fs::write(&path, src).unwrap();
+                path
+            })
+            .collect();
+        let missing = dir.join("Missing.elm");
+        let mut cache = Cache::load(&dir);
+        cache.parse_all(&paths, 3);
+        for (i, path) in (0..10).map(|i| (i, dir.join(format!("Test{}.elm", i)))) {
+            assert_eq!(
+                cache.parse(&path).potential_tests,
+                vec![format!("suite{}", i)]
+            );
+        }
+        assert_eq!(cache.parse(&missing), ParsedModule::default());
+        assert!(cache.has_problems(&missing));
+        assert_eq!(cache.diagnostics().count(), 1);
+        assert!(cache.diagnostics().all(|diagnostic| diagnostic.unreadable));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn prune_deleted_modules() {
+        let dir = std::env::temp_dir().join(format!("elm-test-rs-prune-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let kept = dir.join("Kept.elm");
+        let deleted = dir.join("Deleted.elm");
+        std::fs::write(&kept, "module Kept exposing (suite)\n\nsuite = 1\n").unwrap();
+        std::fs::write(&deleted, "module Deleted exposing (suite)\n\nsuite = 1\n").unwrap();
+        let mut cache = Cache::load(&dir);
+        cache.parse(&kept);
+        cache.parse(&deleted);
+        cache.save().unwrap();
+        std::fs::remove_file(&deleted).unwrap();
+        let mut cache = Cache::load(&dir);
+        assert_eq!(cache.modules.len(), 2);
+        cache.save().unwrap();
+        let cache = Cache::load(&dir);
+        assert!(cache.modules.contains_key(&kept));
+        assert!(!cache.modules.contains_key(&deleted));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
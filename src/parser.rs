@@ -1,6 +1,7 @@
 use nom::{
     branch::*, bytes::complete::*, character::complete::*, combinator::*, multi::*, sequence::*, *,
 };
+use std::collections::BTreeMap;
 
 #[derive(Debug)]
 enum Exposing<'a> {
@@ -9,11 +10,622 @@ enum Exposing<'a> {
 }
 
 /// Returns potential tests in the module.
+/// With an explicit exposing list, those are the exposed values,
+/// and with `exposing (..)`, or without module declaration, all the top-level values.
+/// An invalid module declaration has no potential tests,
+/// since the runner could otherwise reference values which are not exposed.
 /// Warning: does not support effect modules and definition of operators.
+/// Effect modules can be detected with `is_effect_module`.
 pub fn potential_tests(src: &str) -> Vec<&str> {
-    alt((parse_file, parse_content))(src)
+    let parsed = if declares_module(src) {
+        parse_file(src)
+    } else {
+        parse_content(src)
+    };
+    parsed.map(|x| x.1).unwrap_or_default()
+}
+
+/// Check if the module starts with a module declaration, valid or not.
+fn declares_module(src: &str) -> bool {
+    let code = match ignore_not_code(src) {
+        Ok((code, _)) => code,
+        Err(_) => src,
+    };
+    code.starts_with("module") || code.starts_with("port module")
+}
+
+/// Returns the names of the modules imported by the module.
+pub fn imports(src: &str) -> Vec<&str> {
+    let content = match preceded(ignore_not_code, module_declaration)(src) {
+        Ok((content, _)) => content,
+        Err(_) => src,
+    };
+    let import = preceded(
+        ignore_not_code,
+        terminated(imported_module, terminated(take_body, ignore_not_code)),
+    );
+    many0(import)(content).map(|x| x.1).unwrap_or_default()
+}
+
+fn imported_module(input: &str) -> IResult<&str, &str> {
+    preceded(
+        terminated(tag("import"), space_or_comment),
+        preceded(
+            ignore_not_code,
+            take_while1(is_allowed_in_module_identifier),
+        ),
+    )(input)
+}
+
+/// Functions of the Test module whose first string argument is the label of the test.
+const LABELED_TEST_FUNCTIONS: &[&str] = &[
+    "describe", "test", "fuzz", "fuzz2", "fuzz3", "fuzzWith", "todo", "only", "skip",
+];
+
+/// Returns the label of the given top-level test definition,
+/// if it is directly defined by one of the labeled functions of the Test module,
+/// such as `suite = describe "label" [ ... ]`.
+/// Warning: multiline string labels are not supported.
+pub fn test_label(src: &str, name: &str) -> Option<String> {
+    top_level_declarations(src, name)
+        .into_iter()
+        .find_map(definition_label)
+}
+
+/// Returns the labels shared by several exposed top-level tests of the module,
+/// or by several tests of the same `describe` list,
+/// with the line numbers of the first definition using it and of the duplicate one.
+/// Labels inside groups are preceded by the labels of their groups, separated by `" > "`.
+pub fn duplicate_labels(src: &str) -> Vec<(String, usize, usize)> {
+    // Labels already used, with the line of their first use.
+    type FirstLines = Vec<(String, usize)>;
+    let mut first_lines: FirstLines = Vec::new();
+    let mut duplicates = Vec::new();
+    for name in potential_tests(src) {
+        let declarations = top_level_declarations(src, name);
+        let labeled = declarations.into_iter().find_map(|rest| {
+            let label = definition_label(rest)?;
+            Some((label, line_at(src, src.len() - rest.len())))
+        });
+        if let Some((label, line)) = labeled {
+            match first_lines.iter().find(|(first, _)| first == &label) {
+                Some((_, first_line)) => duplicates.push((label, *first_line, line)),
+                None => first_lines.push((label, line)),
+            }
+        }
+    }
+    // Groups around the current call, with the byte offset where their list ends,
+    // and the labels already used in that list.
+    let mut groups: Vec<(String, usize, FirstLines)> = Vec::new();
+    for (offset, function, rest) in code_identifiers(src) {
+        if !LABELED_TEST_FUNCTIONS.contains(&function) {
+            continue;
+        }
+        let label = match first_label(rest) {
+            Some(label) => label,
+            None => continue,
+        };
+        while let Some((_, end, _)) = groups.last() {
+            if *end > offset {
+                break;
+            }
+            groups.pop();
+        }
+        let line = line_at(src, offset);
+        let path: Vec<String> = groups.iter().map(|(group, _, _)| group.clone()).collect();
+        if let Some((_, _, siblings)) = groups.last_mut() {
+            match siblings.iter().find(|(first, _)| first == &label) {
+                Some((_, first_line)) => duplicates.push((
+                    format!("{} > {}", path.join(" > "), label),
+                    *first_line,
+                    line,
+                )),
+                None => siblings.push((label.clone(), line)),
+            }
+        }
+        if function == "describe" {
+            if let Some(list_end) = group_list_end(rest) {
+                groups.push((label, src.len() - list_end.len(), Vec::new()));
+            }
+        }
+    }
+    duplicates.sort_by_key(|(_, _, line)| *line);
+    duplicates
+}
+
+/// Returns the top-level tests of the module that are not exposed, and will thus never run.
+/// Tests are the definitions annotated with the `Test` type,
+/// or directly defined by one of the labeled functions of the Test module.
+pub fn unexposed_tests(src: &str) -> Vec<&str> {
+    let (content, exposed) = match preceded(ignore_not_code, module_declaration)(src) {
+        Ok((content, Exposing::Many(exposed))) => (content, exposed),
+        _ => return Vec::new(),
+    };
+    parse_content(content)
         .map(|x| x.1)
         .unwrap_or_default()
+        .into_iter()
+        .filter(|name| !exposed.contains(name))
+        .filter(|name| has_test_annotation(src, name) || test_label(src, name).is_some())
+        .collect()
+}
+
+/// Check if the top-level definition with the given name is annotated with the `Test` type.
+fn has_test_annotation(src: &str, name: &str) -> bool {
+    let mut annotation = tuple((
+        delimited(ignore_not_code, tag(":"), ignore_not_code),
+        opt(tag("Test.")),
+        tag("Test"),
+        not(satisfy(is_allowed_in_identifier)),
+    ));
+    top_level_declarations(src, name)
+        .into_iter()
+        .any(|rest| annotation(rest).is_ok())
+}
+
+/// Returns the syntax problems which may prevent finding the tests of the module, with their line,
+/// such as an invalid module declaration or an unterminated comment or string.
+pub fn syntax_problems(src: &str) -> Vec<(String, usize)> {
+    let mut problems = Vec::new();
+    let code = match ignore_not_code(src) {
+        Ok((code, _)) => code,
+        Err(_) => src,
+    };
+    if declares_module(src) && module_declaration(code).is_err() {
+        problems.push((
+            "Invalid module declaration, its tests cannot be found".to_string(),
+            line_at(src, src.len() - code.len()),
+        ));
+    }
+    let mut input = src;
+    while let Some(c) = input.chars().next() {
+        let skipped: IResult<&str, &str> = alt((
+            block_comment,
+            line_comment,
+            char_literal,
+            multiline_string_literal,
+            string_literal,
+        ))(input);
+        let unterminated = if input.starts_with("{-") && block_comment(input).is_err() {
+            Some("comment")
+        } else if input.starts_with("\"\"\"") && multiline_string_literal(input).is_err() {
+            Some("multiline string")
+        } else if input.starts_with('"') && skipped.is_err() {
+            Some("string")
+        } else {
+            None
+        };
+        if let Some(kind) = unterminated {
+            problems.push((
+                format!("Unterminated {}", kind),
+                line_at(src, src.len() - input.len()),
+            ));
+            break;
+        }
+        input = match skipped {
+            Ok((rest, _)) => rest,
+            Err(_) => &input[c.len_utf8()..],
+        };
+    }
+    problems
+}
+
+/// Returns true if the module is an effect module, such as `effect module Task where { command = MyCmd } exposing (..)`.
+/// Only elm and elm-explorations packages can define those.
+pub fn is_effect_module(src: &str) -> bool {
+    let effect_header: IResult<&str, &str> = preceded(
+        tuple((
+            ignore_not_code,
+            tag("effect"),
+            space_or_comment,
+            ignore_not_code,
+        )),
+        tag("module"),
+    )(src);
+    effect_header.is_ok()
+}
+
+/// Magic comment quarantining a whole tests module, followed by the reason.
+const SKIP_MODULE_MARKER: &str = "-- elm-test-rs:skip-module";
+
+/// Returns the reason given by a `-- elm-test-rs:skip-module reason` comment
+/// at the top of the module, before its first declaration,
+/// or `None` if the module is not skipped.
+pub fn skip_module_reason(src: &str) -> Option<String> {
+    let is_header = |offset: &usize| {
+        let line = &src[*offset..];
+        [
+            "module",
+            "port module",
+            "effect module",
+            "import",
+            "--",
+            "{-",
+        ]
+        .iter()
+        .any(|start| line.starts_with(start))
+    };
+    let header_end = top_level_offsets(src)
+        .into_iter()
+        .find(|offset| !is_header(offset))
+        .unwrap_or(src.len());
+    src[..header_end].lines().find_map(|line| {
+        let rest = line.strip_prefix(SKIP_MODULE_MARKER)?;
+        if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+            Some(rest.trim().to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// Check if the module has a top-level declaration of the given name,
+/// such as `main` for the modules of an application.
+pub fn defines_value(src: &str, name: &str) -> bool {
+    !top_level_declarations(src, name).is_empty()
+}
+
+/// Returns the source following each top-level declaration of the given name,
+/// such as its type annotation or definition.
+fn top_level_declarations<'a>(src: &'a str, name: &str) -> Vec<&'a str> {
+    top_level_offsets(src)
+        .into_iter()
+        .filter_map(|offset| src[offset..].strip_prefix(name))
+        .filter(|rest| !rest.starts_with(is_allowed_in_identifier))
+        .collect()
+}
+
+/// Returns the byte offsets of the lines starting without indentation,
+/// outside of comments and string literals, where top-level declarations start.
+fn top_level_offsets(src: &str) -> Vec<usize> {
+    let mut offsets = vec![0];
+    let mut input = src;
+    while let Some(c) = input.chars().next() {
+        let skipped: IResult<&str, &str> = alt((
+            block_comment,
+            line_comment,
+            char_literal,
+            multiline_string_literal,
+            string_literal,
+        ))(input);
+        input = match skipped {
+            Ok((rest, _)) => rest,
+            Err(_) => {
+                let rest = &input[c.len_utf8()..];
+                if c == '\n' && rest.starts_with(|c: char| !c.is_whitespace()) {
+                    offsets.push(src.len() - rest.len());
+                }
+                rest
+            }
+        };
+    }
+    offsets
+}
+
+fn definition_label(input: &str) -> Option<String> {
+    let (input, _) = delimited(ignore_not_code, tag("="), ignore_not_code)(input).ok()?;
+    let (input, _) = opt(tag("Test."))(input).ok()?;
+    let (input, function) = take_identifier(input).ok()?;
+    if !LABELED_TEST_FUNCTIONS.contains(&function) {
+        return None;
+    }
+    first_label(input)
+}
+
+/// Returns the labels of all the tests and groups defined in the module,
+/// each preceded by the labels of the `describe` groups around it in the module, outermost first,
+/// with the line and column (both starting at 1) of the function call defining them.
+/// Warning: multiline string labels are not supported.
+pub fn label_positions(src: &str) -> Vec<(Vec<String>, usize, usize)> {
+    // Labels of the groups around the current call, with the byte offset where their list ends.
+    let mut groups: Vec<(String, usize)> = Vec::new();
+    let mut positions = Vec::new();
+    for (offset, function, rest) in code_identifiers(src) {
+        if !LABELED_TEST_FUNCTIONS.contains(&function) {
+            continue;
+        }
+        let label = match first_label(rest) {
+            Some(label) => label,
+            None => continue,
+        };
+        while let Some((_, end)) = groups.last() {
+            if *end > offset {
+                break;
+            }
+            groups.pop();
+        }
+        // The call starts at the Test module qualifier, if any.
+        let start = if src[..offset].ends_with("Test.") {
+            offset - "Test.".len()
+        } else {
+            offset
+        };
+        let mut path: Vec<String> = groups.iter().map(|(group, _)| group.clone()).collect();
+        path.push(label.clone());
+        positions.push((path, line_at(src, start), column_at(src, start)));
+        if function == "describe" {
+            if let Some(list_end) = group_list_end(rest) {
+                groups.push((label, src.len() - list_end.len()));
+            }
+        }
+    }
+    positions
+}
+
+/// Returns the source following the list of tests of a `describe` call,
+/// given the source following the `describe` identifier.
+/// The list is the bracketed expression right after the literal label, such as `[ test ... ]`.
+fn group_list_end(input: &str) -> Option<&str> {
+    let (input, _) = preceded(ignore_not_code, string_literal)(input).ok()?;
+    let (mut input, _) = ignore_not_code(input).ok()?;
+    if !input.starts_with(['[', '(']) {
+        return None;
+    }
+    let mut depth = 0;
+    loop {
+        let skipped: IResult<&str, &str> = alt((
+            block_comment,
+            line_comment,
+            char_literal,
+            multiline_string_literal,
+            string_literal,
+        ))(input);
+        if let Ok((rest, _)) = skipped {
+            input = rest;
+            continue;
+        }
+        let c = input.chars().next()?;
+        match c {
+            '[' | '(' | '{' => depth += 1,
+            ']' | ')' | '}' => depth -= 1,
+            _ => {}
+        }
+        input = &input[c.len_utf8()..];
+        if depth == 0 {
+            return Some(input);
+        }
+    }
+}
+
+/// Returns the string literals of the module, which include the labels of all its tests,
+/// or `None` if some labels may be computed, such as `test ("case " ++ name)`.
+/// All calls to labeled functions are considered, whatever their module qualifier,
+/// to stay on the safe side with aliases such as `import Test as T`.
+pub fn possible_labels(src: &str) -> Option<Vec<String>> {
+    let literal_labels =
+        code_identifiers(src)
+            .into_iter()
+            .all(|(_, function, rest)| match function {
+                "describe" | "test" | "todo" => literal_label_after(rest, 0),
+                "fuzz" => literal_label_after(rest, 1),
+                "fuzz2" | "fuzzWith" => literal_label_after(rest, 2),
+                "fuzz3" => literal_label_after(rest, 3),
+                _ => true,
+            });
+    if !literal_labels {
+        return None;
+    }
+    Some(string_literals(src))
+}
+
+/// Returns the unescaped string literals of the given source, outside of comments.
+fn string_literals(src: &str) -> Vec<String> {
+    let mut literals = Vec::new();
+    let mut input = src;
+    while let Some(c) = input.chars().next() {
+        if let Ok((rest, literal)) = alt((multiline_string_literal, string_literal))(input) {
+            literals.push(unescape(literal));
+            input = rest;
+            continue;
+        }
+        let skipped: IResult<&str, &str> = alt((block_comment, line_comment, char_literal))(input);
+        input = match skipped {
+            Ok((rest, _)) => rest,
+            Err(_) => &input[c.len_utf8()..],
+        };
+    }
+    literals
+}
+
+/// Returns the top-level values of the module, exposed or not,
+/// with the string literals of their declarations and the other top-level values they refer to.
+/// The labels of the tests of a value are among its literals, those of the values it refers to,
+/// and those of the imported modules, unless some labels are computed.
+pub fn declarations(src: &str) -> Vec<(String, Vec<String>, Vec<String>)> {
+    let offsets = top_level_offsets(src);
+    let mut bodies: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (i, start) in offsets.iter().enumerate() {
+        let end = offsets.get(i + 1).copied().unwrap_or(src.len());
+        let body = &src[*start..end];
+        match take_identifier(body) {
+            Ok((_, name))
+                if name.starts_with(char::is_lowercase)
+                    && !["module", "import", "port", "type", "effect", "infix"].contains(&name) =>
+            {
+                bodies.entry(name).or_default().push(body)
+            }
+            _ => {}
+        }
+    }
+    bodies
+        .iter()
+        .map(|(name, parts)| {
+            let literals = parts
+                .iter()
+                .flat_map(|body| string_literals(body))
+                .collect();
+            let mut references: Vec<String> = parts
+                .iter()
+                .flat_map(|body| code_identifiers(body))
+                .map(|(_, identifier, _)| identifier)
+                .filter(|identifier| identifier != name && bodies.contains_key(identifier))
+                .map(|identifier| identifier.to_string())
+                .collect();
+            references.sort_unstable();
+            references.dedup();
+            (name.to_string(), literals, references)
+        })
+        .collect()
+}
+
+/// Check if a string literal follows the given number of simple arguments,
+/// such as `Fuzz.int` or `(Fuzz.list Fuzz.int)` for the fuzzer of `fuzz`.
+fn literal_label_after(mut input: &str, arguments: usize) -> bool {
+    for _ in 0..arguments {
+        let argument: IResult<&str, &str> = preceded(
+            ignore_not_code,
+            alt((
+                take_while1(is_allowed_in_module_identifier),
+                |i| within_recursive("(", ")", i),
+                |i| within_recursive("{", "}", i),
+                |i| within_recursive("[", "]", i),
+            )),
+        )(input);
+        match argument {
+            Ok((rest, _)) => input = rest,
+            Err(_) => return false,
+        }
+    }
+    preceded(
+        ignore_not_code,
+        alt((multiline_string_literal, string_literal)),
+    )(input)
+    .is_ok()
+}
+
+/// Functions of the Test module changing which tests are run.
+const FOCUS_TEST_FUNCTIONS: &[&str] = &["only", "skip", "todo"];
+
+/// Returns the calls to `only`, `skip` and `todo` of the Test module in the module,
+/// with their line number (starting at 1).
+pub fn focus_calls(src: &str) -> Vec<(&str, usize)> {
+    code_identifiers(src)
+        .into_iter()
+        .filter(|(offset, function, _)| {
+            FOCUS_TEST_FUNCTIONS.contains(function) && is_test_function(src, *offset)
+        })
+        .map(|(offset, function, _)| (function, line_at(src, offset)))
+        .collect()
+}
+
+/// Label reported for a `todo` test whose label is not a string literal.
+pub const COMPUTED_LABEL: &str = "<computed label>";
+
+/// Returns the labels of the `todo` tests of the module,
+/// with the line number (starting at 1) of their call.
+/// Labels which are not string literals, such as `todo ("parse " ++ name)`,
+/// are replaced by `COMPUTED_LABEL`.
+pub fn todos(src: &str) -> Vec<(String, usize)> {
+    code_identifiers(src)
+        .into_iter()
+        .filter(|(offset, function, _)| *function == "todo" && is_test_function(src, *offset))
+        .map(|(offset, _, rest)| {
+            let label = Some(rest)
+                .filter(|rest| literal_label_after(rest, 0))
+                .and_then(first_label)
+                .unwrap_or_else(|| COMPUTED_LABEL.to_string());
+            (label, line_at(src, offset))
+        })
+        .collect()
+}
+
+/// Check if the identifier at the given offset may be a function of the Test module.
+/// Identifiers are recognized with or without the `Test.` qualifier,
+/// but those qualified by another module or record, such as `config.skip`, are not.
+fn is_test_function(src: &str, offset: usize) -> bool {
+    match src[..offset].strip_suffix('.') {
+        None => true,
+        Some(before) => {
+            before
+                .rsplit(|c| !is_allowed_in_module_identifier(c))
+                .next()
+                == Some("Test")
+        }
+    }
+}
+
+/// Returns the identifiers in the code of the module, outside of comments, literals and imports,
+/// with their byte offset and the source following them.
+fn code_identifiers(src: &str) -> Vec<(usize, &str, &str)> {
+    let mut identifiers = Vec::new();
+    let mut input = src;
+    while !input.is_empty() {
+        let offset = src.len() - input.len();
+        if let Ok((rest, identifier)) = take_identifier(input) {
+            input = match (identifier, parse_import(input)) {
+                ("import", Ok((rest, _))) => rest,
+                _ => {
+                    identifiers.push((offset, identifier, rest));
+                    rest
+                }
+            };
+            continue;
+        }
+        let skipped: IResult<&str, &str> = alt((
+            block_comment,
+            line_comment,
+            char_literal,
+            multiline_string_literal,
+            string_literal,
+        ))(input);
+        input = match skipped {
+            Ok((rest, _)) => rest,
+            Err(_) => &input[input.chars().next().map_or(1, |c| c.len_utf8())..],
+        };
+    }
+    identifiers
+}
+
+/// Line number (starting at 1) of the given byte offset.
+fn line_at(src: &str, offset: usize) -> usize {
+    src[..offset].matches('\n').count() + 1
+}
+
+/// Column number (starting at 1) of the given byte offset, counted in characters.
+fn column_at(src: &str, offset: usize) -> usize {
+    src[..offset]
+        .rsplit('\n')
+        .next()
+        .unwrap_or("")
+        .chars()
+        .count()
+        + 1
+}
+
+/// The label is the first string literal following the labeled function.
+fn first_label(mut input: &str) -> Option<String> {
+    loop {
+        if multiline_string_literal(input).is_ok() {
+            return None;
+        }
+        if let Ok((_, label)) = string_literal(input) {
+            return Some(unescape(label));
+        }
+        match body_element(input) {
+            Ok((rest, _)) if rest.len() < input.len() => input = rest,
+            _ => return None,
+        }
+    }
+}
+
+/// Unescape the content of a string literal.
+fn unescape(escaped: &str) -> String {
+    let mut unescaped = String::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
 }
 
 fn parse_file(input: &str) -> IResult<&str, Vec<&str>> {
@@ -65,12 +677,21 @@ fn is_potential_test(identifier: &str) -> bool {
 }
 
 fn take_exposed_identifier(input: &str) -> IResult<&str, &str> {
-    let (input, identifier) = take_identifier(input)?;
+    let (input, identifier) = alt((take_identifier, exposed_operator))(input)?;
     let (input, _) = ignore_not_code(input)?;
     let (input, _) = alt((double_dot_expose, success("")))(input)?;
     Ok((input, identifier))
 }
 
+/// An exposed operator, such as `(<|>)`.
+fn exposed_operator(input: &str) -> IResult<&str, &str> {
+    recognize(delimited(
+        tag("("),
+        take_while1(|c: char| "+-/*=.<>:&|^?%!".contains(c)),
+        tag(")"),
+    ))(input)
+}
+
 fn double_dot_expose(input: &str) -> IResult<&str, &str> {
     delimited(
         preceded(tag("("), ignore_not_code),
@@ -413,6 +1034,432 @@ five = something
             vec!["one", "two", "three", "four", "five"],
         );
     }
+    #[test]
+    fn test_label() {
+        let src = r#"module Tests exposing (..)
+
+import Test exposing (..)
+
+suite : Test
+suite =
+    describe "The \"main\" suite"
+        [ test "first" <| \_ -> Expect.pass ]
+
+fuzzed =
+    Test.fuzz Fuzz.int "some fuzz" <| \_ -> Expect.pass
+
+skipped = skip <|
+    describe "skipped suite" []
+
+concatenated =
+    concat [ suite, fuzzed ]
+"#;
+        let label = |name| super::test_label(src, name);
+        assert_eq!(label("suite"), Some("The \"main\" suite".to_string()));
+        assert_eq!(label("fuzzed"), Some("some fuzz".to_string()));
+        assert_eq!(label("skipped"), Some("skipped suite".to_string()));
+        assert_eq!(label("concatenated"), None);
+        assert_eq!(label("missing"), None);
+    }
+    #[test]
+    fn label_positions() {
+        let src = r#"module Tests exposing (..)
+
+{- test "commented" -}
+suite =
+    describe "suite"
+        [ test "first" <| \_ -> Expect.equal "test" "test"
+        , Test.fuzz Fuzz.int
+            "second" <| \_ -> Expect.pass
+        ]
+"#;
+        assert_eq!(
+            super::label_positions(src),
+            vec![
+                (vec!["suite".to_string()], 5, 5),
+                (vec!["suite".to_string(), "first".to_string()], 6, 11),
+                (vec!["suite".to_string(), "second".to_string()], 7, 11),
+            ]
+        );
+    }
+    #[test]
+    fn nested_label_positions() {
+        let src = r#"module Tests exposing (..)
+
+suite =
+    describe "suite"
+        [ describe "inner"
+            [ test "same" <| \_ -> Expect.equal "]" (String.fromChar ']')
+            ]
+        , test "same" <| \_ -> Expect.pass
+        ]
+
+other =
+    test "same" <| \_ -> Expect.pass
+"#;
+        let paths: Vec<Vec<String>> = super::label_positions(src)
+            .into_iter()
+            .map(|(path, _, _)| path)
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                vec!["suite"],
+                vec!["suite", "inner"],
+                vec!["suite", "inner", "same"],
+                vec!["suite", "same"],
+                vec!["same"],
+            ]
+        );
+    }
+    #[test]
+    fn focus_calls() {
+        let src = r#"module Tests exposing (..)
+
+import Test exposing (Test, describe, only, skip, test)
+
+{- only "commented" -}
+suite =
+    describe "suite"
+        [ only <| test "first" <| \_ -> Expect.pass
+        , Test.skip <| test "skip" <| \_ -> Expect.pass
+        , Test.todo "later"
+        , test "config" <| \_ -> Expect.equal config.skip MyTest.only
+        ]
+"#;
+        assert_eq!(
+            super::focus_calls(src),
+            vec![("only", 8), ("skip", 9), ("todo", 10)]
+        );
+    }
+    #[test]
+    fn defines_value() {
+        let src = r#"module Main exposing (main)
+
+{-|
+main = "in a comment"
+-}
+mainView =
+    text """
+main = in a string
+"""
+
+main :
+    Program () () ()
+main =
+    Browser.sandbox { init = (), view = \_ -> mainView, update = \_ _ -> () }
+"#;
+        assert!(super::defines_value(src, "main"));
+        assert!(!super::defines_value(
+            &src[..src.find("main :").unwrap()],
+            "main"
+        ));
+    }
+    #[test]
+    fn todos() {
+        let src = r#"module Tests exposing (..)
+
+import Test exposing (Test, describe, todo)
+
+suite =
+    describe "suite"
+        [ Test.todo "later"
+        , todo ("parse " ++ name)
+        , todo "escaped \"quotes\""
+        , config.todo "not a test"
+        ]
+"#;
+        assert_eq!(
+            super::todos(src),
+            vec![
+                ("later".to_string(), 7),
+                (super::COMPUTED_LABEL.to_string(), 8),
+                ("escaped \"quotes\"".to_string(), 9),
+            ]
+        );
+    }
+    #[test]
+    fn unexposed_tests() {
+        let src = r#"module Tests exposing (suite, Model)
+
+suite : Test
+suite =
+    describe "suite" []
+
+forgotten : Test.Test
+forgotten =
+    concat []
+
+unannotated =
+    test "unannotated" <| \_ -> Expect.pass
+
+helper : Int -> Test
+helper n =
+    concat []
+
+tested : Tested
+tested =
+    Tested
+"#;
+        assert_eq!(
+            super::unexposed_tests(src),
+            vec!["forgotten", "unannotated"]
+        );
+        assert!(
+            super::unexposed_tests("module Tests exposing (..)\n\nt : Test\nt = todo \"\"")
+                .is_empty()
+        );
+    }
+    #[test]
+    fn test_label_outside_comments_and_strings() {
+        let src = r#"module Tests exposing (..)
+
+{-
+suite = describe "in a comment" []
+-}
+message =
+    """
+suite = describe "in a string" []
+"""
+
+suite =
+    describe "real" []
+"#;
+        assert_eq!(super::potential_tests(src), vec!["message", "suite"]);
+        assert_eq!(super::test_label(src, "suite"), Some("real".to_string()));
+        assert_eq!(
+            super::top_level_declarations(src, "suit"),
+            Vec::<&str>::new()
+        );
+    }
+    #[test]
+    fn duplicate_labels() {
+        let src = r#"module Tests exposing (..)
+
+parser : Test
+parser =
+    describe "Parser" []
+
+lexer =
+    test "Lexer" <| \_ -> Expect.pass
+
+parserAgain =
+    describe "Parser" [ test "Lexer" <| \_ -> Expect.pass ]
+"#;
+        assert_eq!(
+            super::duplicate_labels(src),
+            vec![("Parser".to_string(), 4, 10)]
+        );
+    }
+    #[test]
+    fn nested_duplicate_labels() {
+        let src = r#"module Tests exposing (..)
+
+suite =
+    describe "Parser"
+        [ test "numbers" <| \_ -> Expect.pass
+        , describe "strings"
+            [ test "numbers" <| \_ -> Expect.pass
+            , test "escapes" <| \_ -> Expect.pass
+            , Test.test "escapes" <| \_ -> Expect.pass
+            ]
+        , fuzz int "numbers" <| \_ -> Expect.pass
+        ]
+
+other =
+    describe "Lexer" [ test "numbers" <| \_ -> Expect.pass ]
+"#;
+        assert_eq!(
+            super::duplicate_labels(src),
+            vec![
+                ("Parser > strings > escapes".to_string(), 8, 9),
+                ("Parser > numbers".to_string(), 5, 11),
+            ]
+        );
+    }
+    #[test]
+    fn imports() {
+        let src = r#"module Tests exposing (..)
+
+{-| Tests of the parser. -}
+
+import Expect
+import Parser.Advanced as P exposing
+    ( run
+    , Parser
+    )
+-- import Commented
+import Test exposing (..)
+
+suite =
+    describe "imports" []
+"#;
+        assert_eq!(
+            super::imports(src),
+            vec!["Expect", "Parser.Advanced", "Test"]
+        );
+    }
+    #[test]
+    fn possible_labels() {
+        let src = r#"module Tests exposing (..)
+
+suite =
+    describe "suite"
+        [ test "first" <| \_ -> Expect.equal "a" "a"
+        , fuzz (Fuzz.list Fuzz.int) "lists" <| \_ -> Expect.pass
+        , Test.fuzz2 Fuzz.int Fuzz.string "pairs" <| \_ _ -> Expect.pass
+        ]
+"#;
+        assert_eq!(
+            super::possible_labels(src),
+            Some(
+                vec!["suite", "first", "a", "a", "lists", "pairs"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect()
+            )
+        );
+        let computed = "suite = List.map (\\n -> test (\"case \" ++ n) ok) cases";
+        assert_eq!(super::possible_labels(computed), None);
+        let fuzzed = "suite = fuzz fuzzer label <| \\_ -> Expect.pass";
+        assert_eq!(super::possible_labels(fuzzed), None);
+    }
+
+    #[test]
+    fn declarations() {
+        let src = r#"module Tests exposing (suite, other)
+
+import Expect
+
+suite : Test
+suite =
+    describe "suite" [ first, test "second" <| \_ -> Expect.pass ]
+
+first =
+    test "first" <| \_ -> Expect.pass
+
+other =
+    test "other" <| \_ -> Expect.pass
+"#;
+        let to_strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            super::declarations(src),
+            vec![
+                ("first".to_string(), to_strings(&["first"]), vec![]),
+                ("other".to_string(), to_strings(&["other"]), vec![]),
+                (
+                    "suite".to_string(),
+                    to_strings(&["suite", "second"]),
+                    to_strings(&["first"])
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn skip_module_reason() {
+        let src = r#"module Tests exposing (suite)
+
+-- elm-test-rs:skip-module broken by the new parser API
+
+import Test exposing (..)
+
+suite =
+    describe "suite" []
+"#;
+        assert_eq!(
+            super::skip_module_reason(src),
+            Some("broken by the new parser API".to_string())
+        );
+        let unexplained = "-- elm-test-rs:skip-module\nmodule Tests exposing (..)\n";
+        assert_eq!(super::skip_module_reason(unexplained), Some(String::new()));
+        let after_declarations =
+            "module Tests exposing (..)\n\nsuite = 1\n\n-- elm-test-rs:skip-module later\n";
+        assert_eq!(super::skip_module_reason(after_declarations), None);
+        let other_comment = "module Tests exposing (..)\n-- elm-test-rs:skip-modules\n";
+        assert_eq!(super::skip_module_reason(other_comment), None);
+    }
+
+    #[test]
+    fn syntax_problems() {
+        let valid =
+            "module Tests exposing (suite)\n\n{- A {- nested -} comment -}\nsuite = \"\\\"\"\n";
+        assert_eq!(super::syntax_problems(valid), vec![]);
+        let no_declaration = "import Test\n\nsuite = test \"a\" ok\n";
+        assert_eq!(super::syntax_problems(no_declaration), vec![]);
+        let invalid = "-- Tests\nmodule Tests exposing suite\n\nsuite = \"\"\"never closed\n";
+        assert_eq!(
+            super::syntax_problems(invalid),
+            vec![
+                (
+                    "Invalid module declaration, its tests cannot be found".to_string(),
+                    2
+                ),
+                ("Unterminated multiline string".to_string(), 4),
+            ]
+        );
+        let comment = "module Tests exposing (..)\n\n{- {- -}\nsuite = 1\n";
+        assert_eq!(
+            super::syntax_problems(comment),
+            vec![("Unterminated comment".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    fn port_and_effect_modules() {
+        let port_module = r#"port module Tests exposing (..)
+
+port log : String -> Cmd msg
+
+suite : Test
+suite =
+    test "ports" <| \_ -> Expect.pass
+"#;
+        assert_eq!(super::potential_tests(port_module), vec!["suite"]);
+        assert!(!super::is_effect_module(port_module));
+        let effect_module =
+            "{- Helper -}\neffect module Helper where { command = MyCmd } exposing (..)\n";
+        assert!(super::is_effect_module(effect_module));
+        assert!(!super::is_effect_module("module Effects exposing (..)\n"));
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        let src = "module Tests.Élève exposing (suíte, Ωmega, ünicode)\n";
+        assert_eq!(super::potential_tests(src), vec!["suíte", "ünicode"]);
+        let imports = "module Tests exposing (..)\n\nimport Données.Élève\nimport Test\n";
+        assert_eq!(super::imports(imports), vec!["Données.Élève", "Test"]);
+    }
+
+    #[test]
+    fn exposing_lists() {
+        let definitions = "\n\nsuite = describe \"suite\" []\n\nhelper = 1\n";
+        let exposing = |declaration: &str| {
+            let src = format!("{}{}", declaration, definitions);
+            super::potential_tests(&src)
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            exposing("module Tests exposing (..)"),
+            vec!["suite", "helper"]
+        );
+        assert_eq!(exposing("module Tests exposing (suite)"), vec!["suite"]);
+        assert_eq!(
+            exposing("module Tests exposing ((<+>), Model(..), suite)"),
+            vec!["suite"]
+        );
+        assert_eq!(
+            exposing("-- Tests\nport module Tests exposing (suite)"),
+            vec!["suite"]
+        );
+        assert_eq!(
+            exposing("module Tests exposing suite"),
+            Vec::<String>::new()
+        );
+        assert_eq!(exposing(""), vec!["suite", "helper"]);
+    }
 }
 
 #[cfg(test)]
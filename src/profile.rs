@@ -0,0 +1,148 @@
+//! Profile of a tests run.
+//!
+//! After the run, the slowest tests and the slowest modules are printed
+//! with their duration and share of the total duration of tests,
+//! to know where optimization efforts are worth it.
+//! With `--heap-stats`, the tests growing the heap the most are printed too.
+
+use std::collections::HashMap;
+
+use crate::summary::{RunSummary, TestOutcome};
+
+/// Print the given number of slowest tests and modules of a run.
+pub fn report(summary: &RunSummary, count: usize) {
+    let total = summary.duration();
+    let mut tests: Vec<&TestOutcome> = summary.tests.iter().collect();
+    tests.sort_by(|a, b| {
+        b.duration
+            .partial_cmp(&a.duration)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    log::error!(
+        "\nProfile of {} tests, running for {} ms in total\n",
+        summary.tests.len(),
+        total.round()
+    );
+    log::error!("Slowest tests:\n");
+    for test in tests.iter().take(count) {
+        log::error!("{}  {}", columns(test.duration, total), test.name());
+    }
+    log::error!("\nSlowest modules:\n");
+    for (module, duration) in slowest_modules(summary).iter().take(count) {
+        log::error!("{}  {}", columns(*duration, total), module);
+    }
+    let hungry = hungriest_tests(summary);
+    if !hungry.is_empty() {
+        log::error!("\nTests growing the heap the most:\n");
+        for (test, growth) in hungry.iter().take(count) {
+            log::error!("{:>8.1} MB  {}", growth / 1024.0 / 1024.0, test.name());
+        }
+    }
+    log::error!("");
+}
+
+/// Tests with a measured heap growth, the largest first.
+pub fn hungriest_tests(summary: &RunSummary) -> Vec<(&TestOutcome, f64)> {
+    let mut tests: Vec<(&TestOutcome, f64)> = summary
+        .tests
+        .iter()
+        .filter_map(|test| test.heap_growth.map(|growth| (test, growth)))
+        .collect();
+    tests.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    tests
+}
+
+/// Total duration of tests of every module, the slowest first.
+/// Tests whose module is unknown are grouped by their top-level label.
+pub fn slowest_modules(summary: &RunSummary) -> Vec<(String, f64)> {
+    let mut durations: HashMap<String, f64> = HashMap::new();
+    for test in summary.tests.iter() {
+        let module = match &test.module {
+            Some(module) => module.clone(),
+            None => test.labels.first().cloned().unwrap_or_default(),
+        };
+        *durations.entry(module).or_insert(0.0) += test.duration;
+    }
+    let mut modules: Vec<(String, f64)> = durations.into_iter().collect();
+    modules.sort_by(|a, b| {
+        b.1.partial_cmp(&a.1)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.0.cmp(&b.0))
+    });
+    modules
+}
+
+/// Duration and percentage of the total duration, aligned in columns.
+fn columns(duration: f64, total: f64) -> String {
+    let percent = if total > 0.0 {
+        100.0 * duration / total
+    } else {
+        0.0
+    };
+    format!("{:>8} ms {:>5.1} %", duration.round(), percent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hungriest_tests, slowest_modules};
+    use crate::summary::{RunSummary, Status, TestOutcome};
+
+    #[test]
+    fn group_durations_by_module() {
+        let test = |module: Option<&str>, labels: &[&str], duration| TestOutcome {
+            labels: labels.iter().map(|s| s.to_string()).collect(),
+            module: module.map(|s| s.to_string()),
+            status: Status::Pass,
+            duration,
+            heap_growth: None,
+        };
+        let summary = RunSummary {
+            exit_code: 0,
+            seed: 0,
+            fuzz_runs: 100,
+            tests: vec![
+                test(Some("Tests.Parser"), &["Suite", "a"], 10.0),
+                test(Some("Tests.Parser"), &["Suite", "b"], 30.0),
+                test(Some("Tests.Json"), &["Suite", "c"], 20.0),
+                test(None, &["Other", "d"], 5.0),
+            ],
+        };
+        assert_eq!(
+            slowest_modules(&summary),
+            vec![
+                ("Tests.Parser".to_string(), 40.0),
+                ("Tests.Json".to_string(), 20.0),
+                ("Other".to_string(), 5.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn sort_tests_by_heap_growth() {
+        let test = |name: &str, heap_growth| TestOutcome {
+            labels: vec![name.to_string()],
+            module: None,
+            status: Status::Pass,
+            duration: 1.0,
+            heap_growth,
+        };
+        let summary = RunSummary {
+            exit_code: 0,
+            seed: 0,
+            fuzz_runs: 100,
+            tests: vec![
+                test("small", Some(1024.0)),
+                test("unmeasured", None),
+                test("large", Some(4096.0)),
+            ],
+        };
+        let names: Vec<(String, f64)> = hungriest_tests(&summary)
+            .into_iter()
+            .map(|(test, growth)| (test.name(), growth))
+            .collect();
+        assert_eq!(
+            names,
+            vec![("large".to_string(), 4096.0), ("small".to_string(), 1024.0)]
+        );
+    }
+}
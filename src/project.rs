@@ -1,12 +1,20 @@
 use anyhow::Context;
 use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
 use pubgrub_dependency_provider_elm::project_config::ProjectConfig;
-use std::collections::BTreeSet;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashSet};
 use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 use std::sync::mpsc::channel;
 use std::time::Duration;
 
+thread_local! {
+    /// Nested source directories already warned about by this process,
+    /// since projects are loaded again at every change in watch mode.
+    static WARNED_NESTED_DIRS: RefCell<HashSet<(PathBuf, PathBuf)>> = RefCell::new(HashSet::new());
+}
+
 #[derive(Debug)]
 pub struct Project {
     pub config: ProjectConfig,
@@ -21,8 +29,7 @@ impl Project {
         // Read project elm.json
         let elm_json_str = std::fs::read_to_string(root_directory.join("elm.json"))
             .context("Unable to read elm.json")?;
-        let config: ProjectConfig =
-            serde_json::from_str(&elm_json_str).context("Invalid elm.json")?;
+        let config = parse_config(&elm_json_str).context("Invalid elm.json")?;
 
         // Retrieve source directories from the project config.
         let default_src_dir = ["src".to_string()];
@@ -44,6 +51,28 @@ impl Project {
             src_and_test_dirs.insert(tests_dir);
         }
 
+        // Warn about nested source directories, whose modules could have two names, once.
+        for outer in &src_and_test_dirs {
+            for inner in &src_and_test_dirs {
+                let first_time = || {
+                    WARNED_NESTED_DIRS
+                        .with(|warned| warned.borrow_mut().insert((outer.clone(), inner.clone())))
+                };
+                if inner != outer && inner.starts_with(outer) && first_time() {
+                    let relative = |dir: &Path| {
+                        pathdiff::diff_paths(dir, &root_directory)
+                            .unwrap_or_else(|| dir.to_path_buf())
+                    };
+                    log::warn!(
+                        "The source directory {} is inside the source directory {}, modules within it are named relative to {}",
+                        relative(inner).display(),
+                        relative(outer).display(),
+                        relative(inner).display()
+                    );
+                }
+            }
+        }
+
         Ok(Project {
             config,
             src_and_test_dirs,
@@ -51,6 +80,15 @@ impl Project {
         })
     }
 
+    /// Source directories of the project, without the tests directory.
+    pub fn source_dirs(&self) -> Vec<&PathBuf> {
+        let tests_dir = self.root_directory.join("tests");
+        self.src_and_test_dirs
+            .iter()
+            .filter(|dir| **dir != tests_dir)
+            .collect()
+    }
+
     pub fn watch(&mut self, call_back: impl Fn(&Self) -> anyhow::Result<()>) -> anyhow::Result<()> {
         // Create a channel to receive the events.
         let (tx, rx) = channel();
@@ -75,6 +113,9 @@ impl Project {
         // Call the function to execute passed as argument.
         call_back(self).context("Initial run in watch mode")?;
 
+        // Parse cache and imports graph, to tell which modules are impacted by a change.
+        let mut impacts: Option<(crate::parse_cache::Cache, crate::imports::ImportGraph)> = None;
+
         // Enter the watch loop.
         loop {
             match rx.recv().context("Error watching files")? {
@@ -154,6 +195,33 @@ impl Project {
                         "=".repeat(detection_msg.len())
                     );
 
+                    // Log the modules impacted by the change.
+                    // The graph is kept between changes, and only the changed module is updated.
+                    match path {
+                        Some(p) if p.extension() == Some(OsStr::new("elm")) => {}
+                        _ => impacts = None,
+                    }
+                    if let (Some(p), true) = (path, log::log_enabled!(log::Level::Info)) {
+                        match &mut impacts {
+                            Some((parse_cache, graph)) => graph.update(self, parse_cache, p),
+                            None => {
+                                let mut parse_cache =
+                                    crate::parse_cache::Cache::load(&crate::make::tests_root(self));
+                                let graph =
+                                    crate::imports::ImportGraph::build(self, &mut parse_cache);
+                                impacts = Some((parse_cache, graph));
+                            }
+                        }
+                        let module = impacts
+                            .as_ref()
+                            .and_then(|(_, graph)| Some((graph, graph.module(p)?)));
+                        if let Some((graph, module)) = module {
+                            let dependents: Vec<String> =
+                                graph.dependents(module).into_iter().collect();
+                            log::info!("Modules impacted by the change: {}", dependents.join(", "));
+                        }
+                    }
+
                     // Call the function to execute passed as argument.
                     call_back(self).context("Subsequent run in watch mode")?;
                 }
@@ -161,3 +229,221 @@ impl Project {
         }
     }
 }
+
+/// Parse the content of an elm.json.
+/// When it is malformed, the error lists every invalid field with an example of the expected shape.
+pub fn parse_config(elm_json_str: &str) -> anyhow::Result<ProjectConfig> {
+    let value: Value = serde_json::from_str(elm_json_str).context("It is not valid JSON")?;
+    let problems = config_problems(&value);
+    if !problems.is_empty() {
+        anyhow::bail!("{}", problems.join("\n"));
+    }
+    Ok(serde_json::from_str(elm_json_str)?)
+}
+
+/// Problems of the fields of an elm.json, such as a missing "source-directories"
+/// or a malformed version constraint.
+fn config_problems(config: &Value) -> Vec<String> {
+    if !config.is_object() {
+        return vec![
+            "It should be an object, such as {\"type\": \"application\", ...}".to_string(),
+        ];
+    }
+    let mut problems = Vec::new();
+    let mut check = |value: &Value, valid: &dyn Fn(&Value) -> bool, field: &str, expected: &str| {
+        if !valid(value) {
+            let found = match value {
+                Value::Null => "but it is missing".to_string(),
+                _ => format!("not {}", value),
+            };
+            problems.push(format!("\"{}\" should be {}, {}", field, expected, found));
+        }
+    };
+    let is_string = |v: &Value| v.is_string();
+    let is_version = |v: &Value| v.as_str().map(is_valid_version).unwrap_or(false);
+    let is_constraint = |v: &Value| v.as_str().map(is_valid_constraint).unwrap_or(false);
+    let is_strings = |v: &Value| {
+        v.as_array()
+            .map(|items| items.iter().all(Value::is_string))
+            .unwrap_or(false)
+    };
+    match config["type"].as_str() {
+        Some("application") => {
+            check(
+                &config["source-directories"],
+                &is_strings,
+                "source-directories",
+                "a list of directories, such as [\"src\"]",
+            );
+            check(
+                &config["elm-version"],
+                &is_version,
+                "elm-version",
+                "a version, such as \"0.19.1\"",
+            );
+            for deps in ["dependencies", "test-dependencies"] {
+                for kind in ["direct", "indirect"] {
+                    check_dependencies(
+                        &mut check,
+                        &config[deps][kind],
+                        &format!("{}.{}", deps, kind),
+                        &is_version,
+                        "a version, such as \"1.0.5\"",
+                    );
+                }
+            }
+        }
+        Some("package") => {
+            let is_name = |v: &Value| v.as_str().and_then(crate::deps::parse_package).is_some();
+            let is_exposed = |v: &Value| {
+                is_strings(v)
+                    || v.as_object()
+                        .map(|categories| categories.values().all(is_strings))
+                        .unwrap_or(false)
+            };
+            check(
+                &config["name"],
+                &is_name,
+                "name",
+                "a package name, such as \"author/project\"",
+            );
+            check(&config["summary"], &is_string, "summary", "a short summary");
+            check(
+                &config["license"],
+                &is_string,
+                "license",
+                "a license, such as \"BSD-3-Clause\"",
+            );
+            check(
+                &config["version"],
+                &is_version,
+                "version",
+                "a version, such as \"1.0.0\"",
+            );
+            check(
+                &config["exposed-modules"],
+                &is_exposed,
+                "exposed-modules",
+                "a list of modules, such as [\"Parser\"], or lists of modules by category",
+            );
+            check(
+                &config["elm-version"],
+                &is_constraint,
+                "elm-version",
+                "a constraint, such as \"0.19.0 <= v < 0.20.0\"",
+            );
+            for deps in ["dependencies", "test-dependencies"] {
+                check_dependencies(
+                    &mut check,
+                    &config[deps],
+                    deps,
+                    &is_constraint,
+                    "a constraint, such as \"1.0.0 <= v < 2.0.0\"",
+                );
+            }
+        }
+        _ => check(
+            &config["type"],
+            &|_| false,
+            "type",
+            "either \"application\" or \"package\"",
+        ),
+    }
+    problems
+}
+
+/// Check dependencies, mapping package names to versions or constraints.
+fn check_dependencies(
+    check: &mut impl FnMut(&Value, &dyn Fn(&Value) -> bool, &str, &str),
+    deps: &Value,
+    field: &str,
+    valid: &dyn Fn(&Value) -> bool,
+    expected: &str,
+) {
+    let is_object = |v: &Value| v.is_object();
+    check(
+        deps,
+        &is_object,
+        field,
+        "an object, such as {\"elm/core\": ...}",
+    );
+    for (pkg, version) in deps.as_object().into_iter().flatten() {
+        match crate::deps::parse_package(pkg) {
+            Some(_) => check(version, valid, &format!("{}.{}", field, pkg), expected),
+            None => check(
+                &Value::String(pkg.clone()),
+                &|_| false,
+                field,
+                "an object with package names as keys, such as \"elm/core\"",
+            ),
+        }
+    }
+}
+
+/// Whether a version is valid, such as "1.0.5".
+fn is_valid_version(version: &str) -> bool {
+    let parts: Vec<&str> = version.split('.').collect();
+    parts.len() == 3
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Whether a version constraint is valid, such as "1.0.0 <= v < 2.0.0".
+fn is_valid_constraint(constraint: &str) -> bool {
+    match constraint.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [low, low_op, "v", high_op, high] => {
+            is_valid_version(low)
+                && is_valid_version(high)
+                && ["<", "<="].contains(low_op)
+                && ["<", "<="].contains(high_op)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::config_problems;
+    use serde_json::json;
+
+    #[test]
+    fn malformed_elm_json() {
+        let app = json!({
+            "type": "application",
+            "elm-version": "0.19.1",
+            "dependencies": {
+                "direct": { "elm/core": "1.0.5", "elm/json": "1.1" },
+                "indirect": {}
+            },
+            "test-dependencies": { "direct": { "json": "1.0.0" }, "indirect": {} }
+        });
+        assert_eq!(
+            config_problems(&app),
+            vec![
+                "\"source-directories\" should be a list of directories, such as [\"src\"], but it is missing",
+                "\"dependencies.direct.elm/json\" should be a version, such as \"1.0.5\", not \"1.1\"",
+                "\"test-dependencies.direct\" should be an object with package names as keys, such as \"elm/core\", not \"json\"",
+            ]
+        );
+        let pkg = json!({
+            "type": "package",
+            "name": "author/project",
+            "summary": "",
+            "license": "BSD-3-Clause",
+            "version": "1.0.0",
+            "exposed-modules": { "Parsing": ["Parser", "Parser.Advanced"] },
+            "elm-version": "0.19.0 <= v < 0.20.0",
+            "dependencies": { "elm/core": "1.0.0 <= v < 2.0.0" },
+            "test-dependencies": { "elm-explorations/test": "2.0.0" }
+        });
+        assert_eq!(
+            config_problems(&pkg),
+            vec!["\"test-dependencies.elm-explorations/test\" should be a constraint, such as \"1.0.0 <= v < 2.0.0\", not \"2.0.0\""]
+        );
+        assert_eq!(
+            config_problems(&json!({ "type": "library" })),
+            vec!["\"type\" should be either \"application\" or \"package\", not \"library\""]
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! Messages exchanged between elm-test-rs and the supervisor.
+//!
+//! Messages are JSON objects with a "type" field, one per line on the channel
+//! connecting elm-test-rs and the supervisor.
+//! The supervisor first sends a `hello` message with the version of the protocol it speaks,
+//! to detect a supervisor generated by another version of elm-test-rs,
+//! and the token given in the `ELM_TEST_RS_CHANNEL_TOKEN` environment variable,
+//! so that another local process connecting to the channel first is rejected.
+//! Each run is then started with a `runStart` message giving the runner to use,
+//! and whether a persistent supervisor can keep the workers of the previous run, which loaded the same runner,
+//! and the supervisor answers with messages of the following types:
+//!
+//!  - `report`: a part of the report to print,
+//!  - `testResult`: the outcome of a finished test,
+//!  - `log`: something printed on stdout by the tests, such as with `console.log` in ports,
+//!  - `snapshot`: a snapshot to write in `tests/__snapshots__/`,
+//!  - `fatal`: a problem stopping the run, such as a crash while setting up the tests,
+//!  - `runEnd`: the end of the run, with its exit code.
+//!
+//! The supervisor and its workers exchange messages with a "type_" field instead,
+//! such as `dispatch` asking a worker to run a test, and `testResult` for its outcome.
+
+use anyhow::Context;
+use serde_json::{json, Value};
+
+use crate::summary::{Status, TestOutcome};
+
+/// Version of the protocol, to bump on every incompatible change of the messages.
+pub const VERSION: u64 = 3;
+
+/// Environment variable with the token the supervisor sends back in its hello message.
+pub const TOKEN_VAR: &str = "ELM_TEST_RS_CHANNEL_TOKEN";
+
+/// Message sent by the supervisor.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Hello { protocol: u64, token: String },
+    Report(String),
+    TestResult(TestOutcome),
+    Log(String),
+    Snapshot { name: String, contents: String },
+    Fatal(String),
+    RunEnd(i32),
+}
+
+/// Message starting a run with the given runner.
+pub fn run_start(runner: &str, reuse_workers: bool) -> String {
+    json!({ "type": "runStart", "runner": runner, "reuseWorkers": reuse_workers }).to_string()
+}
+
+/// Decode a message sent by the supervisor.
+pub fn decode(line: &str) -> anyhow::Result<Message> {
+    let msg: Value = serde_json::from_str(line).context("Invalid message from the supervisor")?;
+    let string = |key: &str| -> anyhow::Result<String> {
+        msg[key]
+            .as_str()
+            .map(|s| s.to_string())
+            .context(format!("Missing {} in the message {}", key, msg))
+    };
+    match msg["type"].as_str() {
+        Some("hello") => Ok(Message::Hello {
+            protocol: msg["protocol"]
+                .as_u64()
+                .context("Missing protocol version in the hello message")?,
+            token: msg["token"].as_str().unwrap_or("").to_string(),
+        }),
+        Some("report") => Ok(Message::Report(string("text")?)),
+        Some("testResult") => {
+            let status = match msg["status"].as_str() {
+                Some("pass") => Status::Pass,
+                Some("fail") => Status::Fail,
+                Some("todo") => Status::Todo,
+                Some("skip") => Status::Skip,
+                _ => anyhow::bail!("Invalid status in the message {}", msg),
+            };
+            let labels = msg["labels"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|label| label.as_str().map(|s| s.to_string()))
+                .collect();
+            Ok(Message::TestResult(TestOutcome {
+                labels,
+                module: msg["module"].as_str().map(|s| s.to_string()),
+                status,
+                duration: msg["duration"].as_f64().unwrap_or(0.0),
+                heap_growth: None,
+            }))
+        }
+        Some("log") => Ok(Message::Log(string("text")?)),
+        Some("snapshot") => Ok(Message::Snapshot {
+            name: string("name")?,
+            contents: string("contents")?,
+        }),
+        Some("fatal") => Ok(Message::Fatal(string("message")?)),
+        Some("runEnd") => Ok(Message::RunEnd(
+            msg["exitCode"]
+                .as_i64()
+                .context("Missing exit code in the runEnd message")? as i32,
+        )),
+        _ => anyhow::bail!("Unknown message from the supervisor: {}", msg),
+    }
+}
+
+/// Random token for a new supervisor, which must send it back in its hello message.
+/// The hasher keys of `RandomState` come from the randomness of the operating system.
+pub fn new_token() -> String {
+    use std::hash::{BuildHasher, Hasher};
+    let random = || {
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u32(std::process::id());
+        hasher.finish()
+    };
+    format!("{:016x}{:016x}", random(), random())
+}
+
+/// Check the hello message of a supervisor, with the token it was given.
+pub fn check_hello(msg: &Message, expected_token: &str) -> anyhow::Result<()> {
+    match msg {
+        Message::Hello { protocol, token } if *protocol == VERSION => {
+            if token != expected_token {
+                anyhow::bail!("The program connected to the supervisor channel did not send the expected token")
+            }
+            Ok(())
+        }
+        Message::Hello { protocol, .. } => anyhow::bail!(
+            "The supervisor speaks version {} of the protocol, but elm-test-rs {} speaks version {}. Remove elm-stuff/tests-0.19.1 to generate it again",
+            protocol,
+            std::env!("CARGO_PKG_VERSION"),
+            VERSION
+        ),
+        _ => anyhow::bail!("The supervisor did not start with a hello message"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_hello, decode, Message, VERSION};
+    use crate::summary::{Status, TestOutcome};
+
+    #[test]
+    fn supervisor_messages() {
+        let hello = decode(r#"{"type":"hello","protocol":1,"token":"abc"}"#).unwrap();
+        let hello_with = |protocol, token: &str| Message::Hello {
+            protocol,
+            token: token.to_string(),
+        };
+        assert_eq!(hello, hello_with(1, "abc"));
+        assert!(check_hello(&hello_with(VERSION, "abc"), "abc").is_ok());
+        assert!(check_hello(&hello_with(VERSION, "abd"), "abc").is_err());
+        assert!(check_hello(&hello_with(VERSION, ""), "abc").is_err());
+        assert!(check_hello(&hello_with(VERSION + 1, "abc"), "abc").is_err());
+        assert!(check_hello(&Message::RunEnd(0), "abc").is_err());
+        assert_eq!(
+            decode(r#"{"type":"report","text":"ok\n"}"#).unwrap(),
+            Message::Report("ok\n".to_string())
+        );
+        assert_eq!(
+            decode(
+                r#"{"type":"testResult","id":3,"labels":["A","b"],"module":"Tests.A","status":"fail","duration":2.5}"#
+            )
+            .unwrap(),
+            Message::TestResult(TestOutcome {
+                labels: vec!["A".to_string(), "b".to_string()],
+                module: Some("Tests.A".to_string()),
+                status: Status::Fail,
+                duration: 2.5,
+                heap_growth: None,
+            })
+        );
+        assert_eq!(
+            decode(r#"{"type":"snapshot","name":"a.txt","contents":"A"}"#).unwrap(),
+            Message::Snapshot {
+                name: "a.txt".to_string(),
+                contents: "A".to_string()
+            }
+        );
+        assert_eq!(
+            decode(r#"{"type":"runEnd","exitCode":2}"#).unwrap(),
+            Message::RunEnd(2)
+        );
+        assert!(decode(r#"{"type":"runEnd"}"#).is_err());
+        assert!(decode(r#"{"type":"other"}"#).is_err());
+        assert!(decode("__elmTestRsFinished__:0").is_err());
+    }
+}
@@ -2,34 +2,166 @@
 
 use crate::make::Output;
 use crate::project::Project;
+use crate::protocol::Message;
+use crate::summary::RunSummary;
 use anyhow::Context;
 use regex::Regex;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::Write;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
 use std::num::NonZeroU32;
-use std::path::Path;
-use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
 
 use crate::include_template;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 /// Options passed as arguments.
 pub struct Options {
     pub seed: u32,
     pub fuzz: NonZeroU32,
     pub workers: u32,
     pub filter: Option<String>,
-    pub reporter: String,
+    /// Reports to generate, there is at least one.
+    pub reports: Vec<Report>,
     pub runtime: Runtime,
+    pub coverage: bool,
+    /// Fail the run when the coverage is below one of these thresholds.
+    pub coverage_min: Vec<crate::coverage::Threshold>,
+    /// Print the given number of slowest tests and modules after the run.
+    pub profile: Option<usize>,
+    /// Elide the middle of failure values longer than this number of characters.
+    pub max_failure_output: Option<usize>,
+    /// Abort the run when a single test runs longer than this number of seconds.
+    pub timeout: Option<NonZeroU32>,
+    /// Maximum heap size of each worker in MB, to name the test running out of memory.
+    pub worker_max_memory: Option<NonZeroU32>,
+    /// Measure the peak heap of each test.
+    pub heap_stats: bool,
+    /// Report the timers left active by tests.
+    pub detect_leaks: bool,
+    /// Run the tests of each module in a fresh worker.
+    pub isolate_modules: bool,
+    /// Unit of work dispatched to the workers.
+    pub granularity: Granularity,
+    /// Compare tests durations with a recorded baseline.
+    pub duration_baseline: Option<crate::baseline::Options>,
+    /// Url to POST the run summary to at the end of the run.
+    pub post_results: Option<String>,
+    /// Only run the tests with one of these tags in their labels, such as "[slow]".
+    pub tags: Vec<String>,
+    /// Do not run the tests with one of these tags in their labels.
+    pub skip_tags: Vec<String>,
+    /// Do not print the tests report.
+    pub quiet: bool,
+    /// Load a lightweight DOM into the runners, for tests starting programs.
+    pub dom: bool,
+    /// Fixtures answering the HTTP requests of the tests, none are answered otherwise.
+    pub http_fixtures: Option<PathBuf>,
+    /// Write all the snapshots compared by the tests, instead of only the missing ones.
+    pub update_snapshots: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+/// A tests report in a given format, printed to stdout or written to a file.
+pub struct Report {
+    pub format: String,
+    pub output: Option<PathBuf>,
+    /// Use colors in the tree report.
+    pub color: bool,
+}
+
+/// Formats of the Elm reporter.
+const ELM_REPORTERS: &[&str] = &["console", "consoleDebug", "json", "exercism"];
+
+/// Reporters implemented in templates/js_reporters.js instead of the Elm reporter.
+const JS_REPORTERS: &[&str] = &[
+    "tree",
+    "tap",
+    "azure",
+    "markdown",
+    "html",
+    "allure",
+    "xunit",
+    "dot",
+    "ndjson",
+    "json-compat",
+    "junit",
+    "junit-per-module",
+    "badge",
+];
+
+/// Reports including the version of the elm compiler in the description of the run environment.
+const COMPILER_VERSION_REPORTS: &[&str] = &["junit", "junit-per-module", "ndjson"];
+
+/// Duration after which a supervisor which did not connect to its channel is given up,
+/// such as a browser unable to load the supervisor page.
+const SUPERVISOR_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Environment variable with the bearer token sent with --post-results.
+const POST_RESULTS_TOKEN_VAR: &str = "ELM_TEST_RS_POST_TOKEN";
+
+impl Report {
+    /// Parse a report argument, either "format" or "format=path".
+    pub fn parse(arg: &str) -> anyhow::Result<Report> {
+        let (format, output) = match arg.find('=') {
+            None => (arg, None),
+            Some(i) => (&arg[..i], Some(PathBuf::from(&arg[i + 1..]))),
+        };
+        if !Report::formats().any(|f| f == format) {
+            anyhow::bail!(
+                "Unknown report format {}, possible formats are: {}",
+                format,
+                Report::formats().collect::<Vec<_>>().join(", ")
+            );
+        }
+        Ok(Report {
+            format: format.to_string(),
+            output,
+            color: false,
+        })
+    }
+
+    /// Default output of reports that are written into a directory or are not text.
+    pub fn default_output(&self) -> Option<PathBuf> {
+        match self.format.as_str() {
+            "allure" => Some(PathBuf::from("allure-results")),
+            "junit-per-module" => Some(PathBuf::from("junit")),
+            "badge" => Some(PathBuf::from("tests-badge.svg")),
+            _ => None,
+        }
+    }
+
+    /// All available report formats.
+    pub fn formats() -> impl Iterator<Item = &'static str> {
+        ELM_REPORTERS.iter().chain(JS_REPORTERS.iter()).copied()
+    }
+}
+
+#[derive(Debug, Clone)]
 /// The runtime to be used.
 pub enum Runtime {
     /// Node is the default runtime.
     Node,
     /// Deno is an alternative runtime.
     Deno,
+    /// A headless Chromium, for tests needing browser APIs.
+    Browser,
+    /// Bun runs the Node supervisor and runners, with its Node compatibility.
+    Bun,
+}
+
+#[derive(Debug, Clone, Copy)]
+/// The unit of work the supervisor dispatches to the workers.
+pub enum Granularity {
+    /// Tests are dispatched one by one to the first available worker, balancing the load best.
+    Test,
+    /// All the tests of a module are dispatched to the same worker,
+    /// keeping the state and caches of the module warm.
+    Module,
 }
 
 /// Wrapper for the main_helper function with "watch" functionality.
@@ -44,20 +176,33 @@ pub fn main(
     run_options: Options,
 ) -> anyhow::Result<i32> {
     // Prints to stderr the current version
-    let title = format!(
-        "elm-test-rs {} for elm 0.19.1",
-        std::env!("CARGO_PKG_VERSION")
-    );
-    log::warn!("\n{}\n{}\n", &title, "-".repeat(title.len()));
+    crate::utils::print_title();
 
-    let mut project = Project::from_dir(elm_project_root.to_path_buf())?;
+    let project = Project::from_dir(elm_project_root.to_path_buf())?;
+    let offline = matches!(
+        make_options.connectivity,
+        crate::deps::ConnectivityStrategy::Offline
+    );
+    let mut project = crate::init::ensure_test_dependency(
+        elm_home,
+        &make_options.network,
+        project,
+        make_options.yes,
+        offline,
+    )?;
+    // With --dependencies bounds, the tests run with both the oldest and newest dependencies.
+    let run_tests = |project: &Project| {
+        if make_options.dependency_bounds {
+            crate::bounds::main_helper(elm_home, project, &make_options, &run_options)
+        } else {
+            main_helper(elm_home, project, &make_options, &run_options)
+        }
+    };
     if make_options.watch {
-        project.watch(|project| {
-            main_helper(elm_home, project, &make_options, &run_options).map(|_| ())
-        })?;
+        project.watch(|project| run_tests(project).map(|_| ()))?;
         Ok(0)
     } else {
-        main_helper(elm_home, &project, &make_options, &run_options)
+        run_tests(&project)
     }
 }
 
@@ -70,23 +215,98 @@ pub fn main(
 ///  4. Generate and start the Node supervisor program.
 ///
 /// Returns the the last exit code.
-fn main_helper(
+pub fn main_helper(
     elm_home: &Path,
     project: &Project,
     make_options: &crate::make::Options,
     run_options: &Options,
 ) -> anyhow::Result<i32> {
+    let prepared = match prepare(elm_home, project, make_options, run_options, false)? {
+        None => return Ok(1),
+        Some(prepared) => prepared,
+    };
+
+    // Start the tests supervisor
+    log::info!("Starting the supervisor ...");
+    let mut supervisor = spawn_supervisor(&prepared, run_options)?;
+
+    // Send runner module path to supervisor to start the work
+    log::info!("Running tests ...");
+    send_runner(&mut supervisor, &prepared, false)?;
+
+    // Print the reports until the end of the run, then wait for the supervisor to exit
+    let finished = if run_options.quiet {
+        receive_reports(&mut supervisor, &prepared, &mut std::io::sink())?
+    } else {
+        receive_reports(&mut supervisor, &prepared, &mut std::io::stdout())?
+    };
+    // The browser does not exit by itself at the end of the run.
+    if let Runtime::Browser = run_options.runtime {
+        let _ = supervisor.child.kill();
+    }
+    let exit_code = wait_child(&mut supervisor.child);
+    if finished.is_none() && run_options.worker_max_memory.is_none() {
+        if let Runtime::Node | Runtime::Bun = run_options.runtime {
+            log::error!("The supervisor stopped before the end of the run. If it ran out of memory, run the tests with --worker-max-memory <MB> to name the test using too much memory.");
+        }
+    }
+    finish(&prepared, run_options, finished.or(exit_code).unwrap_or(0))
+}
+
+/// Files generated to run the tests, ready to be picked up by a supervisor.
+pub struct Prepared {
+    pub tests_root: PathBuf,
+    pub runner_path: PathBuf,
+    pub supervisor_js_file: PathBuf,
+    /// Hash of what the supervisor embeds besides the runner: the reporter, the globs,
+    /// the tests files and modules, and the reports.
+    /// A persistent supervisor must be restarted when it changes.
+    pub supervisor_key: u64,
+    summary_file: PathBuf,
+    coverage: Option<(Vec<crate::coverage::Declaration>, PathBuf)>,
+    snapshots_dir: PathBuf,
+    report_outputs: Vec<PathBuf>,
+    /// Proxy given with `--proxy`, for posting the results.
+    proxy: Option<String>,
+}
+
+impl Prepared {
+    /// Files written by the supervisor: the report outputs, the run summary and the coverage hits.
+    fn writable_files(&self) -> Vec<PathBuf> {
+        let mut files = self.report_outputs.clone();
+        files.push(self.summary_file.clone());
+        files.extend(self.coverage.iter().map(|(_, hits_file)| hits_file.clone()));
+        files
+    }
+}
+
+/// Compile the runner and generate the supervisor in charge of running the tests.
+/// A persistent supervisor does not exit after running the tests,
+/// but waits for a new runner on its channel instead.
+///
+/// Returns `None` if the compilation failed.
+pub fn prepare(
+    elm_home: &Path,
+    project: &Project,
+    make_options: &crate::make::Options,
+    run_options: &Options,
+    persistent: bool,
+) -> anyhow::Result<Option<Prepared>> {
     // let start_time = std::time::Instant::now();
 
+    // Other runs in the same project wait until the generated files are all written.
+    let _lock = crate::utils::lock_dir(&crate::make::tests_root(project))?;
+
     // Compile the Runner.elm file.
-    let (tests_root, modules_abs_paths, compiled_runner) =
+    let (tests_root, modules_abs_paths, compiled_runner, mut parse_cache) =
         match crate::make::main_helper(elm_home, project, make_options)? {
-            Output::MakeFailure => return Ok(1),
+            Output::MakeFailure => return Ok(None),
             Output::MakeSuccess {
                 tests_root,
                 modules_abs_paths,
                 compiled_runner,
-            } => (tests_root, modules_abs_paths, compiled_runner),
+                parse_cache,
+            } => (tests_root, modules_abs_paths, compiled_runner, parse_cache),
         };
 
     // Add a kernel patch to the generated code in order to be able to recognize
@@ -97,17 +317,58 @@ fn main_helper(
         compiled_runner.display()
     ))?;
     let es_module = match run_options.runtime {
-        Runtime::Node => false,
-        Runtime::Deno => true,
+        Runtime::Node | Runtime::Bun => false,
+        Runtime::Deno | Runtime::Browser => true,
     };
-    fs::write(
-        &compiled_runner,
-        &kernel_patch_tests(&compiled_runner_src, es_module).context(format!(
-            "Failed to patch the file {}",
-            compiled_runner.display()
-        ))?,
-    )
-    .context(format!(
+    let tags_filter = if run_options.tags.is_empty() && run_options.skip_tags.is_empty() {
+        None
+    } else {
+        Some(
+            include_template!("test_tags.js")
+                .replace(
+                    "{{ includedTags }}",
+                    &serde_json::to_string(&run_options.tags)?,
+                )
+                .replace(
+                    "{{ excludedTags }}",
+                    &serde_json::to_string(&run_options.skip_tags)?,
+                ),
+        )
+    };
+    let patched_runner_src =
+        kernel_patch_tests(&compiled_runner_src, es_module, tags_filter.as_deref()).context(
+            format!("Failed to patch the file {}", compiled_runner.display()),
+        )?;
+
+    // Embed the files of tests/fixtures/ read by the tests with ElmTestRs.Fixture.
+    let fixtures_dir = project.root_directory.join("tests").join("fixtures");
+    let patched_runner_src = crate::fixtures::embed_files(&patched_runner_src, &fixtures_dir)?;
+
+    // Embed the snapshots of tests/__snapshots__/ compared by the tests with ElmTestRs.Snapshot.
+    let snapshots_dir = project.root_directory.join("tests").join("__snapshots__");
+    let patched_runner_src = crate::snapshot::embed(
+        &patched_runner_src,
+        &snapshots_dir,
+        run_options.update_snapshots,
+    )?;
+
+    // Instrument the functions of source modules with counters to measure coverage.
+    let coverage_hits_file = tests_root.join("coverage-hits.json");
+    let (patched_runner_src, coverage_declarations) = if run_options.coverage {
+        log::info!("Instrumenting Runner.elm.js for coverage ...");
+        let _ = fs::remove_file(&coverage_hits_file);
+        let source_modules = crate::coverage::source_modules(project);
+        let (instrumented_src, declarations) =
+            crate::coverage::instrument(&patched_runner_src, &source_modules)
+                .context("Failed to instrument Runner.elm.js for coverage")?;
+        (instrumented_src, Some(declarations))
+    } else {
+        (patched_runner_src, None)
+    };
+
+    // Append the names of the definitions, to translate the stack traces of crashes into Elm.
+    let patched_runner_src = crate::stack::append_names(&patched_runner_src);
+    crate::utils::write_atomic(&compiled_runner, &patched_runner_src).context(format!(
         "Failed to write the patched file {}",
         compiled_runner.display()
     ))?;
@@ -115,8 +376,12 @@ fn main_helper(
     // Generate the node_runner.js node module embedding the Elm runner
 
     let (runner_name, runner_template) = match run_options.runtime {
-        Runtime::Node => ("node_runner.js", include_template!("node_runner.js")),
+        Runtime::Node | Runtime::Bun => ("node_runner.js", include_template!("node_runner.js")),
         Runtime::Deno => ("deno_runner.mjs", include_template!("deno_runner.mjs")),
+        Runtime::Browser => (
+            "browser_runner.mjs",
+            include_template!("browser_runner.mjs"),
+        ),
     };
     let polyfills = include_template!("node_polyfills.js");
     let runner_path = tests_root.join("js").join(runner_name);
@@ -124,6 +389,40 @@ fn main_helper(
         None => "null".to_string(),
         Some(s) => format!("\"{}\"", s),
     };
+    // With --dom, the DOM shims are added to the polyfills of Node runners.
+    // Deno hoists the imports of modules, so they are imported before Runner.elm.js instead.
+    let dom_shims = include_template!("dom_shims.js");
+    // With --http-fixtures, the mock of XMLHttpRequest and fetch replaces the no-op polyfill.
+    let http_fixtures = match &run_options.http_fixtures {
+        None => String::new(),
+        Some(file) => format!(
+            "\n// From templates/http_fixtures.js\n{}",
+            include_template!("http_fixtures.js").replacen(
+                "{{ httpFixtures }}",
+                &crate::fixtures::load(file)?,
+                1
+            )
+        ),
+    };
+    let runner_polyfills = match run_options.runtime {
+        Runtime::Node | Runtime::Bun if run_options.dom => format!(
+            "{}\n// From templates/dom_shims.js\n{}{}",
+            polyfills, dom_shims, http_fixtures
+        ),
+        _ => format!("{}{}", polyfills, http_fixtures),
+    };
+    let dom_import = match run_options.runtime {
+        Runtime::Deno if run_options.dom => {
+            crate::utils::write_atomic(tests_root.join("js").join("dom_shims.mjs"), dom_shims)?;
+            "import \"./dom_shims.mjs\";"
+        }
+        _ => "",
+    };
+    let timer_leaks = if run_options.detect_leaks {
+        include_template!("timer_leaks.js")
+    } else {
+        ""
+    };
     crate::make::create_templated(
         runner_template, // template
         &runner_path,    // output
@@ -131,7 +430,10 @@ fn main_helper(
             ("{{ initialSeed }}", &run_options.seed.to_string()),
             ("{{ fuzzRuns }}", &run_options.fuzz.to_string()),
             ("{{ filter }}", &filter),
-            ("{{ polyfills }}", polyfills),
+            ("{{ polyfills }}", &runner_polyfills),
+            ("{{ domImport }}", dom_import),
+            ("{{ heapStats }}", &run_options.heap_stats.to_string()),
+            ("{{ timerLeaks }}", timer_leaks),
         ],
     )
     .context(format!("Failed to write {}", runner_path.display()))?;
@@ -140,12 +442,13 @@ fn main_helper(
     log::info!("Compiling Reporter.elm.js ...");
     let reporter_template = include_template!("Reporter.elm");
     let reporter_elm_path = tests_root.join("src").join("Reporter.elm");
-    std::fs::write(&reporter_elm_path, reporter_template)
+    crate::utils::write_atomic(&reporter_elm_path, reporter_template)
         .context("Error writing Reporter.elm to test folder")?;
     let compiled_reporter = tests_root.join("js").join("Reporter.elm.js");
     // let compile_time = std::time::Instant::now();
     if !crate::make::compile(
         elm_home,
+        &make_options.network,
         &tests_root,            // current_dir
         &make_options.compiler, // compiler
         &compiled_reporter,     // output
@@ -154,20 +457,20 @@ fn main_helper(
     )?
     .success()
     {
-        return Ok(1);
+        return Ok(None);
     }
 
-    // For a Deno runtime, convert the compiled Reporter.elm.js into an ES module.
-    if let Runtime::Deno = run_options.runtime {
+    // For Deno and browser runtimes, convert the compiled Reporter.elm.js into an ES module.
+    if let Runtime::Deno | Runtime::Browser = run_options.runtime {
         let compiled_reporter_code = fs::read_to_string(&compiled_reporter)?;
-        fs::write(
+        crate::utils::write_atomic(
             &compiled_reporter,
             into_es_module(&replace_console_log(&compiled_reporter_code)),
         )?;
     }
 
     // Generate a package.json specifying that all JS files follow CommonJS.
-    std::fs::write(
+    crate::utils::write_atomic(
         tests_root.join("js").join("package.json"),
         "{type: 'commonjs'}",
     )
@@ -175,7 +478,7 @@ fn main_helper(
 
     // Generate the supervisor Node module
     let (supervisor_name, supervisor_template) = match run_options.runtime {
-        Runtime::Node => (
+        Runtime::Node | Runtime::Bun => (
             "node_supervisor.js",
             include_template!("node_supervisor.js"),
         ),
@@ -183,8 +486,118 @@ fn main_helper(
             "deno_supervisor.mjs",
             include_template!("deno_supervisor.mjs"),
         ),
+        Runtime::Browser => (
+            "browser_supervisor.mjs",
+            include_template!("browser_supervisor.mjs"),
+        ),
     };
     let supervisor_js_file = tests_root.join("js").join(supervisor_name);
+    let summary_file = RunSummary::path(&tests_root);
+    let _ = fs::remove_file(&summary_file);
+    let summary_file_json = serde_json::to_string(&summary_file.to_str().context(format!(
+        "Could not convert path into a String: {}",
+        summary_file.display()
+    ))?)
+    .context("Failed to convert the run summary path to JSON")?;
+    let coverage_hits = match &coverage_declarations {
+        None => "null".to_string(),
+        Some(_) => serde_json::to_string(&coverage_hits_file.to_str().context(format!(
+            "Could not convert path into a String: {}",
+            coverage_hits_file.display()
+        ))?)
+        .context("Failed to convert the coverage hits path to JSON")?,
+    };
+    // JS reporters use the decoded results of the json mode of the Elm reporter.
+    let reports = run_options
+        .reports
+        .iter()
+        .map(|report| {
+            let output = match &report.output {
+                None => None,
+                Some(path) => Some(path.to_str().context(format!(
+                    "Could not convert path into a String: {}",
+                    path.display()
+                ))?),
+            };
+            if JS_REPORTERS.contains(&report.format.as_str()) {
+                let options = serde_json::json!({
+                    "color": report.color,
+                    "maxFailureOutput": run_options.max_failure_output,
+                });
+                Ok(serde_json::json!({
+                    "mode": "json",
+                    "js": report.format,
+                    "options": options,
+                    "output": output,
+                }))
+            } else {
+                Ok(serde_json::json!({ "mode": report.format, "js": null, "output": output }))
+            }
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let reports =
+        serde_json::to_string(&reports).context("Failed to convert the reports to JSON")?;
+    let test_modules = test_modules(project, &mut parse_cache, &modules_abs_paths)?;
+    let test_modules_json: serde_json::Map<String, serde_json::Value> = test_modules
+        .iter()
+        .map(|m| {
+            let positions: serde_json::Map<String, serde_json::Value> = m
+                .positions
+                .iter()
+                .map(|(label, (line, column))| {
+                    let position = serde_json::json!({ "line": line, "column": column });
+                    (label.clone(), position)
+                })
+                .collect();
+            let json = serde_json::json!({
+                "module": m.module,
+                "file": m.file.display().to_string(),
+                "positions": positions,
+            });
+            (m.module.clone(), json)
+        })
+        .collect();
+    let test_modules_json = serde_json::to_string(&test_modules_json)
+        .context("Failed to convert the modules of tests to JSON")?;
+    let globs = serde_json::to_string(&make_options.files).context(
+        "Failed to convert the list of tests files passed as CLI arguments to a JSON list",
+    )?;
+    let paths = serde_json::to_string(&modules_abs_paths)
+        .context("Failed to convert the list of actual tests files to a JSON list")?;
+    let supervisor_key = {
+        let mut hasher = DefaultHasher::new();
+        for part in &[&reports, &globs, &paths, &test_modules_json] {
+            part.hash(&mut hasher);
+        }
+        fs::read(&compiled_reporter)
+            .context("Failed to read the compiled reporter")?
+            .hash(&mut hasher);
+        hasher.finish()
+    };
+    let html_template = serde_json::to_string(include_template!("report.html"))
+        .context("Failed to convert the HTML report template to JSON")?;
+    let test_timeout = match run_options.timeout {
+        Some(seconds) => (1000 * seconds.get() as u64).to_string(),
+        None => "null".to_string(),
+    };
+    let worker_max_memory = match run_options.worker_max_memory {
+        Some(megabytes) => megabytes.to_string(),
+        None => "null".to_string(),
+    };
+    // Retrieving the version of the compiler starts a process, so only do it when it is reported.
+    let mut environment = serde_json::json!({ "elmTestRs": std::env!("CARGO_PKG_VERSION") });
+    if run_options
+        .reports
+        .iter()
+        .any(|report| COMPILER_VERSION_REPORTS.contains(&report.format.as_str()))
+    {
+        environment["compiler"] = compiler_version(&make_options.compiler).into();
+    }
+    let environment = environment.to_string();
+    let granularity = match run_options.granularity {
+        Granularity::Test => "\"test\"",
+        Granularity::Module => "\"module\"",
+    };
     crate::make::create_templated(
         supervisor_template, // template
         &supervisor_js_file, // output
@@ -192,31 +605,107 @@ fn main_helper(
             ("{{ workersCount }}", &run_options.workers.to_string()),
             ("{{ initialSeed }}", &run_options.seed.to_string()),
             ("{{ fuzzRuns }}", &run_options.fuzz.to_string()),
-            ("{{ reporter }}", &run_options.reporter),
+            ("{{ reports }}", &reports),
+            ("{{ htmlTemplate }}", &html_template),
+            ("{{ environment }}", &environment),
+            ("{{ testModules }}", &test_modules_json),
+            ("{{ testTimeout }}", &test_timeout),
+            ("{{ workerMaxMemory }}", &worker_max_memory),
+            (
+                "{{ isolateModules }}",
+                &run_options.isolate_modules.to_string(),
+            ),
+            ("{{ granularity }}", granularity),
+            (
+                "{{ testDurations }}",
+                &crate::history::durations(&tests_root).to_string(),
+            ),
+            (
+                "{{ protocolVersion }}",
+                &crate::protocol::VERSION.to_string(),
+            ),
             ("{{ verbosity }}", &make_options.verbosity.to_string()),
-            ("{{ globs }}", &serde_json::to_string(&make_options.files).context("Failed to convert the list of tests files passed as CLI arguments to a JSON list")?),
-            ("{{ paths }}", &serde_json::to_string(&modules_abs_paths).context("Failed to convert the list of actual tests files to a JSON list")?),
+            ("{{ globs }}", &globs),
+            ("{{ paths }}", &paths),
             ("{{ polyfills }}", polyfills),
+            ("{{ testResults }}", include_template!("test_results.js")),
+            ("{{ jsReporters }}", include_template!("js_reporters.js")),
+            ("{{ summaryFile }}", &summary_file_json),
+            ("{{ coverageHits }}", &coverage_hits),
+            ("{{ persistent }}", &persistent.to_string()),
         ],
     )
-    .context(format!(
-        "Failed to write {}",
-        supervisor_js_file.display()
-    ))?;
+    .context(format!("Failed to write {}", supervisor_js_file.display()))?;
 
     // For a Deno runtime, make deno_linereader.mjs and deno_logger.mjs available.
     if let Runtime::Deno = run_options.runtime {
         let linereader_template = include_template!("deno_linereader.mjs");
         let linereader_path = tests_root.join("js").join("deno_linereader.mjs");
-        std::fs::write(linereader_path, linereader_template)?;
+        crate::utils::write_atomic(linereader_path, linereader_template)?;
         let logger_template = include_template!("deno_logger.mjs");
         let logger_path = tests_root.join("js").join("deno_logger.mjs");
-        std::fs::write(logger_path, logger_template)?;
+        crate::utils::write_atomic(logger_path, logger_template)?;
     }
 
-    // Start the tests supervisor
-    log::info!("Starting the supervisor ...");
-    let mut supervisor = match run_options.runtime {
+    // For a browser runtime, generate the pages of the supervisor and runner,
+    // and make deno_logger.mjs available to the runner.
+    if let Runtime::Browser = run_options.runtime {
+        let js_dir = tests_root.join("js");
+        let logger_template = include_template!("deno_logger.mjs");
+        crate::utils::write_atomic(js_dir.join("deno_logger.mjs"), logger_template)?;
+        for (page, script) in &[
+            ("index.html", "browser_supervisor.mjs"),
+            ("browser_runner.html", "browser_runner.mjs"),
+        ] {
+            crate::make::create_templated(
+                include_template!("browser_page.html"),
+                js_dir.join(page),
+                &[("{{ script }}", script)],
+            )
+            .context(format!("Failed to write {}", page))?;
+        }
+    }
+
+    Ok(Some(Prepared {
+        tests_root,
+        runner_path,
+        supervisor_js_file,
+        supervisor_key,
+        summary_file,
+        coverage: coverage_declarations.map(|declarations| (declarations, coverage_hits_file)),
+        snapshots_dir,
+        report_outputs: run_options
+            .reports
+            .iter()
+            .filter_map(|report| report.output.clone())
+            .collect(),
+        proxy: make_options.network.proxy.clone(),
+    }))
+}
+
+/// A running supervisor, and the local socket connected to it.
+///
+/// The socket carries the messages of the protocol defined in `protocol.rs`,
+/// such as the paths of the runners to start, the reports, and the end of each run.
+/// This keeps them apart from what the tests print on stdout,
+/// which could otherwise corrupt the reports.
+pub struct Supervisor {
+    pub child: Child,
+    channel: BufReader<TcpStream>,
+}
+
+/// Start the supervisor program, and wait for it to connect to its channel.
+pub fn spawn_supervisor(prepared: &Prepared, run_options: &Options) -> anyhow::Result<Supervisor> {
+    let listener = TcpListener::bind(("127.0.0.1", 0))
+        .context("Failed to open a local socket for the supervisor")?;
+    let port = listener
+        .local_addr()
+        .context("Failed to retrieve the address of the supervisor socket")?
+        .port();
+    // Only the supervisor knows the token, to tell it apart from another local process
+    // connecting to the port first.
+    let token = crate::protocol::new_token();
+    let mut child = match run_options.runtime {
         Runtime::Node => {
             let node_version = Command::new("node")
                 .arg("--version")
@@ -234,47 +723,276 @@ fn main_helper(
 
             Command::new("node")
                 .args(experimental_arg)
-                .arg(supervisor_js_file)
-                .current_dir(tests_root)
-                .stdin(Stdio::piped())
+                .arg(&prepared.supervisor_js_file)
+                .arg(port.to_string())
+                .env(crate::protocol::TOKEN_VAR, &token)
+                .current_dir(&prepared.tests_root)
+                .stdin(Stdio::null())
                 .spawn()
                 .context("Node supervisor failed to start")?
         }
         Runtime::Deno => Command::new("deno")
-            .args(["run", "--allow-read", "--allow-hrtime"])
-            .arg(supervisor_js_file)
-            .current_dir(tests_root)
-            .stdin(Stdio::piped())
+            .args([
+                "run",
+                "--allow-read",
+                "--allow-write",
+                "--allow-hrtime",
+                "--allow-net=127.0.0.1",
+            ])
+            .arg(format!("--allow-env={}", crate::protocol::TOKEN_VAR))
+            .arg(&prepared.supervisor_js_file)
+            .arg(port.to_string())
+            .env(crate::protocol::TOKEN_VAR, &token)
+            .current_dir(&prepared.tests_root)
+            .stdin(Stdio::null())
             .spawn()
             .context("Deno supervisor failed to start")?,
+        Runtime::Browser => crate::browser::start(
+            &prepared.tests_root,
+            port,
+            &token,
+            prepared.writable_files(),
+        )?,
+        Runtime::Bun => Command::new("bun")
+            .arg(&prepared.supervisor_js_file)
+            .arg(port.to_string())
+            .env(crate::protocol::TOKEN_VAR, &token)
+            .current_dir(&prepared.tests_root)
+            .stdin(Stdio::null())
+            .spawn()
+            .context("Bun supervisor failed to start")?,
     };
-
-    // Helper closure to write to supervisor
-    let stdin = supervisor
-        .stdin
-        .as_mut()
-        .context("Failed to open supervisor stdin")?;
-    let mut writeln = |msg| -> anyhow::Result<()> {
-        stdin.write_all(msg)?;
-        stdin.write_all(b"\n")?;
-        Ok(())
+    let channel = accept_supervisor(&listener, &mut child)?;
+    let mut supervisor = Supervisor {
+        child,
+        channel: BufReader::new(channel),
     };
+    match receive(&mut supervisor)? {
+        Some(hello) => crate::protocol::check_hello(&hello, &token)?,
+        None => anyhow::bail!("The supervisor closed its channel before saying hello"),
+    }
+    Ok(supervisor)
+}
 
-    // Send runner module path to supervisor to start the work
-    log::info!("Running tests ...");
-    let runner_path_string = runner_path
-        .to_str()
-        .context(format!(
-            "Could not convert path into a String: {}",
-            runner_path.display()
-        ))?
-        .to_string();
-    writeln(runner_path_string.as_bytes())
-        .context("Failed to write runner path to supervisor stdin")?;
-
-    // Wait for supervisor child process to end and terminate with same exit code
-    let exit_code = wait_child(&mut supervisor);
-    Ok(exit_code.unwrap_or(0))
+/// Wait for the supervisor to connect, failing if it exits before or takes too long.
+fn accept_supervisor(listener: &TcpListener, child: &mut Child) -> anyhow::Result<TcpStream> {
+    listener
+        .set_nonblocking(true)
+        .context("Failed to configure the supervisor socket")?;
+    let start = std::time::Instant::now();
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream
+                    .set_nonblocking(false)
+                    .context("Failed to configure the supervisor socket")?;
+                return Ok(stream);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                if let Ok(Some(status)) = child.try_wait() {
+                    anyhow::bail!(
+                        "The supervisor exited before starting the tests: {}",
+                        status
+                    );
+                }
+                if start.elapsed() > SUPERVISOR_CONNECT_TIMEOUT {
+                    let _ = child.kill();
+                    anyhow::bail!(
+                        "The supervisor did not connect within {} seconds",
+                        SUPERVISOR_CONNECT_TIMEOUT.as_secs()
+                    );
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            Err(e) => return Err(e).context("Failed to connect with the supervisor"),
+        }
+    }
+}
+
+/// Send the runner module path to the supervisor to start the work.
+/// A persistent supervisor keeps the workers of its previous run with `reuse_workers`,
+/// which must only be set when the runner did not change since.
+pub fn send_runner(
+    supervisor: &mut Supervisor,
+    prepared: &Prepared,
+    reuse_workers: bool,
+) -> anyhow::Result<()> {
+    let runner_path_string = prepared.runner_path.to_str().context(format!(
+        "Could not convert path into a String: {}",
+        prepared.runner_path.display()
+    ))?;
+    let channel = supervisor.channel.get_mut();
+    channel
+        .write_all(crate::protocol::run_start(runner_path_string, reuse_workers).as_bytes())
+        .and_then(|_| channel.write_all(b"\n"))
+        .context("Failed to send the runner path to the supervisor")
+}
+
+/// Read the next message of the supervisor, or `None` if it closed the channel.
+fn receive(supervisor: &mut Supervisor) -> anyhow::Result<Option<Message>> {
+    let mut line = String::new();
+    let read = supervisor
+        .channel
+        .read_line(&mut line)
+        .context("Failed to read the messages of the supervisor")?;
+    if read == 0 {
+        return Ok(None);
+    }
+    crate::protocol::decode(&line).map(Some)
+}
+
+/// Write the reports of a run to `out` until the supervisor signals the end of the run.
+/// What the tests print on stdout is written to stderr.
+/// Errors writing the reports are ignored to keep reading until the end of the run.
+///
+/// Returns the exit code of the run, or `None` if the supervisor stopped before its end.
+pub fn receive_reports(
+    supervisor: &mut Supervisor,
+    prepared: &Prepared,
+    out: &mut dyn Write,
+) -> anyhow::Result<Option<i32>> {
+    loop {
+        match receive(supervisor)? {
+            None => {
+                let _ = out.flush();
+                return Ok(None);
+            }
+            Some(Message::Report(text)) => {
+                let _ = out.write_all(text.as_bytes());
+            }
+            Some(Message::TestResult(outcome)) => {
+                log::debug!("{}: {}", outcome.status.as_str(), outcome.name());
+            }
+            Some(Message::Log(text)) => {
+                let _ = std::io::stderr().write_all(text.as_bytes());
+            }
+            Some(Message::Snapshot { name, contents }) => {
+                if let Err(e) = crate::snapshot::write(&prepared.snapshots_dir, &name, &contents) {
+                    log::error!("{:#}", e);
+                }
+            }
+            Some(Message::Fatal(message)) => log::error!("\n{}\n", message),
+            Some(Message::RunEnd(exit_code)) => {
+                let _ = out.flush();
+                return Ok(Some(exit_code));
+            }
+            Some(Message::Hello { .. }) => {
+                anyhow::bail!("Unexpected hello message from the supervisor during a run")
+            }
+        }
+    }
+}
+
+/// Module and file defining exposed tests.
+#[derive(Debug, Clone)]
+pub struct TestModule {
+    pub module: String,
+    /// Path of the module file, relative to the project root.
+    pub file: PathBuf,
+    /// Line and column of the tests and groups labels in the module file,
+    /// keyed by their labels paths within the module, such as `["suite","first"]` in JSON.
+    pub positions: HashMap<String, (usize, usize)>,
+}
+
+/// Version of the elm compiler, such as "0.19.1", or "unknown" if it cannot be retrieved.
+fn compiler_version(compiler: &str) -> String {
+    Command::new(compiler)
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|version| !version.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Files of the tests modules and the positions of their labels.
+/// Runners send the module of each test with its id,
+/// so that reporters know where a test comes from, even when modules share labels.
+/// The modules were already parsed when generating the runner.
+fn test_modules(
+    project: &Project,
+    parse_cache: &mut crate::parse_cache::Cache,
+    modules_abs_paths: &HashSet<PathBuf>,
+) -> anyhow::Result<Vec<TestModule>> {
+    let mut modules = Vec::new();
+    for path in modules_abs_paths {
+        let parsed = parse_cache.parse(path);
+        let module = crate::make::get_module_name(&project.src_and_test_dirs, path)?;
+        let file = pathdiff::diff_paths(path, &project.root_directory)
+            .unwrap_or_else(|| path.to_path_buf());
+        // Keep the first definition of each labels path.
+        let mut positions = HashMap::new();
+        for (path, line, column) in parsed.label_positions.into_iter().rev() {
+            positions.insert(serde_json::to_string(&path)?, (line, column));
+        }
+        modules.push(TestModule {
+            module,
+            file,
+            positions,
+        });
+    }
+    Ok(modules)
+}
+
+/// Process the outputs of the supervisor once all tests are done.
+/// Returns the exit code of the run, which fails if the tests passed
+/// but the coverage is below its thresholds or the durations regressed.
+pub fn finish(prepared: &Prepared, run_options: &Options, exit_code: i32) -> anyhow::Result<i32> {
+    // Record the results of this run in the history.
+    if !run_options.quiet {
+        match RunSummary::read(&prepared.summary_file) {
+            Ok(summary) => crate::history::record(&prepared.tests_root, &summary)?,
+            Err(e) => log::info!("No run summary to record in the history: {:#}", e),
+        }
+    }
+
+    // Print the slowest tests and modules.
+    if let Some(count) = run_options.profile {
+        let summary = RunSummary::read(&prepared.summary_file)?;
+        crate::profile::report(&summary, count);
+    }
+
+    // Compare durations with the baseline.
+    let mut durations_ok = true;
+    if let Some(baseline) = &run_options.duration_baseline {
+        let summary = RunSummary::read(&prepared.summary_file)?;
+        durations_ok = crate::baseline::check(&summary, baseline)?;
+    }
+
+    // Report the coverage collected by the supervisor.
+    let mut coverage_reached = true;
+    if let Some((declarations, hits_file)) = &prepared.coverage {
+        if hits_file.exists() {
+            let modules = crate::coverage::collect(declarations, hits_file)?;
+            crate::coverage::report(&modules, &prepared.tests_root.join("coverage.json"))?;
+            coverage_reached = crate::coverage::check(&modules, &run_options.coverage_min);
+        } else {
+            log::error!("No coverage data was collected");
+            coverage_reached = run_options.coverage_min.is_empty();
+        }
+    }
+    // Send the run summary to the results webhook.
+    // Failing to post does not fail the run since tests themselves went fine.
+    // Quiet runs, such as mutation runs, are not posted.
+    if let (Some(url), false) = (&run_options.post_results, run_options.quiet) {
+        let token = std::env::var(POST_RESULTS_TOKEN_VAR).ok();
+        let posted = fs::read_to_string(&prepared.summary_file)
+            .context("Failed to read the run summary")
+            .and_then(|summary| {
+                let proxy = prepared.proxy.as_deref();
+                crate::utils::http_post_json(url, proxy, &summary, token.as_deref())
+            });
+        if let Err(e) = posted {
+            log::error!("Failed to post the results: {:#}", e);
+        }
+    }
+
+    if exit_code == 0 && !(coverage_reached && durations_ok) {
+        Ok(1)
+    } else {
+        Ok(exit_code)
+    }
 }
 
 /// Wait for child process to end
@@ -298,8 +1016,14 @@ fn wait_child(child: &mut std::process::Child) -> Option<i32> {
 /// Also replace the unique call to console.log in Debug.log
 /// by a call to the "yet-to-be-defined" console.elmlog
 ///
+/// With a tags filter, tests are selected by their tags when found by the `check` function.
+///
 /// Transformation to an esmodule is also possible.
-fn kernel_patch_tests(elm_js: &str, esmodule: bool) -> anyhow::Result<String> {
+fn kernel_patch_tests(
+    elm_js: &str,
+    esmodule: bool,
+    tags_filter: Option<&str>,
+) -> anyhow::Result<String> {
     // For older versions of elm-explorations/test we need to list every single
     // variant of the `Test` type. To avoid having to update this regex if a new
     // variant is added, newer versions of elm-explorations/test have prefixed all
@@ -321,9 +1045,21 @@ fn kernel_patch_tests(elm_js: &str, esmodule: bool) -> anyhow::Result<String> {
 
     let elm_js =
         test_variant_definition.replace_all(elm_js, "$0 __elmTestSymbol: __elmTestSymbol,");
-    let elm_js = check_definition.replace(&elm_js, "$1 = value => value && value.__elmTestSymbol === __elmTestSymbol ? $$elm$$core$$Maybe$$Just(value) : $$elm$$core$$Maybe$$Nothing;");
+    let selected = match tags_filter {
+        None => "value",
+        Some(_) => "__elmTestFilterTags(value, false)",
+    };
+    // Selected tests are also recorded with their module to know the labels and modules of tests by their id,
+    // and checked modules to warn about those exposing no tests.
+    let elm_js = check_definition.replace(&elm_js, format!("$1 = module => value => (__elmTestCheckedModules.add(module), value && value.__elmTestSymbol === __elmTestSymbol ? $$elm$$core$$Maybe$$Just(__elmTestTopLevel[__elmTestTopLevel.push({{ module: module, test: {} }}) - 1].test) : $$elm$$core$$Maybe$$Nothing);", selected).as_str());
 
-    let elm_js = ["const __elmTestSymbol = Symbol('elmTestSymbol');", &elm_js].join("\n");
+    let elm_js = [
+        "const __elmTestSymbol = Symbol('elmTestSymbol');",
+        include_template!("test_labels.js"),
+        tags_filter.unwrap_or(""),
+        &elm_js,
+    ]
+    .join("\n");
 
     // If an ES module is asked, the following transformation is applied.
     if esmodule {
@@ -358,3 +1094,42 @@ fn into_es_module(elm_js: &str) -> String {
     ]
     .join("\n")
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::include_template;
+    use std::process::Command;
+
+    /// Order in which the tests are dispatched, from the shared supervisor code run by Node.
+    fn dispatch_order(isolate_modules: bool) -> String {
+        let script = format!(
+            "{}\nconst labels = [['A', 'a1'], ['A', 'a2'], ['B', 'b1'], ['B', 'b2']];\n\
+             const durations = {{ 'A > a1': 10, 'A > a2': 1, 'B > b1': 9, 'B > b2': 8 }};\n\
+             const order = dispatchOrder(4, labels, ['A', 'A', 'B', 'B'], durations, {});\n\
+             process.stdout.write(JSON.stringify(order.reverse()));",
+            include_template!("test_results.js"),
+            isolate_modules
+        );
+        let output = Command::new("node")
+            .arg("-e")
+            .arg(script)
+            .output()
+            .expect("Node is needed to run the supervisor code");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn dispatch_slowest_first() {
+        assert_eq!(dispatch_order(false), "[0,2,3,1]");
+    }
+
+    #[test]
+    fn dispatch_modules_together_when_isolated() {
+        assert_eq!(dispatch_order(true), "[2,3,0,1]");
+    }
+}
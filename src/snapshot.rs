@@ -0,0 +1,85 @@
+//! Snapshot testing.
+//!
+//! Tests compare strings with snapshots using `ElmTestRs.Snapshot.expect`,
+//! generated from `templates/Snapshot.elm`.
+//! The snapshots of the `tests/__snapshots__/` directory are embedded in the compiled tests,
+//! with `templates/test_snapshots.js` comparing them, and the Elm function is kernel-patched to call it.
+//! Missing snapshots, or all of them with `--update-snapshots`, are sent by the supervisor
+//! with `snapshot` messages, and written by elm-test-rs.
+
+use anyhow::Context;
+use regex::Regex;
+use serde_json::Value;
+use std::path::{Component, Path, PathBuf};
+
+use crate::include_template;
+
+/// Embed the snapshots in the compiled tests, if they use `ElmTestRs.Snapshot.expect`.
+pub fn embed(elm_js: &str, snapshots_dir: &Path, update: bool) -> anyhow::Result<String> {
+    if !elm_js.contains("$author$project$ElmTestRs$Snapshot$checkReplaceMe___") {
+        return Ok(elm_js.to_string());
+    }
+    let snapshots =
+        crate::fixtures::read_files(snapshots_dir).context("Failed to read the snapshots")?;
+    let check_definition = Regex::new(
+        r#"(?m)^var\s+\$author\$project\$ElmTestRs\$Snapshot\$checkReplaceMe___\s*=\s*F2\(\s*function\s*\((\w+),\s*(\w+)\)\s*\{"#,
+    )?;
+    let elm_js = check_definition.replace(
+        elm_js,
+        "$0 var failure = __elmTestCheckSnapshot(${1}, ${2}); return failure === null ? $$elm$$core$$Maybe$$Nothing : $$elm$$core$$Maybe$$Just(failure);",
+    );
+    let preamble = include_template!("test_snapshots.js")
+        .replace("{{ snapshots }}", &Value::Object(snapshots).to_string())
+        .replace("{{ updateSnapshots }}", &update.to_string());
+    Ok(format!("{}\n{}", preamble, elm_js))
+}
+
+/// Write a snapshot sent by the supervisor.
+pub fn write(snapshots_dir: &Path, name: &str, contents: &str) -> anyhow::Result<()> {
+    let path = snapshot_path(snapshots_dir, name)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).context(format!(
+            "Could not create the directory {}",
+            parent.display()
+        ))?;
+    }
+    crate::utils::write_atomic(&path, contents)
+        .context(format!("Failed to write the snapshot {}", path.display()))?;
+    log::error!("Wrote the snapshot {}", path.display());
+    Ok(())
+}
+
+/// Path of the snapshot of the given name, which must stay within the snapshots directory.
+fn snapshot_path(snapshots_dir: &Path, name: &str) -> anyhow::Result<PathBuf> {
+    let relative = Path::new(name);
+    let inside = relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if name.is_empty() || !inside {
+        anyhow::bail!(
+            "Invalid snapshot name \"{}\", it must be a relative path such as \"invoices/simple.txt\"",
+            name
+        );
+    }
+    Ok(snapshots_dir.join(relative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::snapshot_path;
+    use std::path::Path;
+
+    #[test]
+    fn snapshot_paths() {
+        let dir = Path::new("tests/__snapshots__");
+        assert_eq!(
+            snapshot_path(dir, "invoices/simple.txt").unwrap(),
+            dir.join("invoices").join("simple.txt")
+        );
+        assert!(snapshot_path(dir, "").is_err());
+        assert!(snapshot_path(dir, "../elm.json").is_err());
+        assert!(snapshot_path(dir, "a/../../b").is_err());
+        assert!(snapshot_path(dir, "/etc/passwd").is_err());
+        assert!(snapshot_path(dir, "./a").is_err());
+    }
+}
@@ -0,0 +1,90 @@
+//! Elm-aware stack traces.
+//!
+//! A name table of the top-level definitions of the compiled `Runner.elm.js`,
+//! by the line where they start, is appended to it with `templates/elm_stack.js`.
+//! When a test throws a JS exception, the runner translates the frames of its stack
+//! located in `Runner.elm.js` into the Elm values they are in, such as `Parser.run`,
+//! instead of `$author$project$Parser$run` symbols and JS line numbers.
+//! Appending the table at the end keeps the line numbers of the compiled code unchanged.
+
+use serde_json::{json, Value};
+
+use crate::include_template;
+
+/// Append the name table of its definitions to the compiled runner.
+pub fn append_names(elm_js: &str) -> String {
+    let names =
+        include_template!("elm_stack.js").replace("{{ names }}", &names(elm_js).to_string());
+    format!("{}\n{}", elm_js, names)
+}
+
+/// Lines (starting at 1) and names of the top-level definitions.
+fn names(elm_js: &str) -> Value {
+    let definitions: Vec<Value> = elm_js
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let rest = line
+                .strip_prefix("var ")
+                .or_else(|| line.strip_prefix("function "))?;
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$'))
+                .unwrap_or(rest.len());
+            let js_name = &rest[..end];
+            if js_name.is_empty() {
+                None
+            } else {
+                Some(json!([index + 1, elm_name(js_name)]))
+            }
+        })
+        .collect();
+    Value::Array(definitions)
+}
+
+/// Elm name of a compiled value, with its package if it is not from the project.
+/// Such as "Parser.run" for `$author$project$Parser$run`
+/// and "List.map (elm/core)" for `$elm$core$List$map`.
+/// Kernel functions such as `_List_map` and helpers such as `A2` are kept as they are.
+fn elm_name(js_name: &str) -> String {
+    let parts: Vec<&str> = js_name.split('$').collect();
+    match parts.as_slice() {
+        ["", "author", "project", path @ ..] if !path.is_empty() => path.join("."),
+        ["", author, package, path @ ..] if !path.is_empty() => format!(
+            "{} ({}/{})",
+            path.join("."),
+            author.replace('_', "-"),
+            package.replace('_', "-")
+        ),
+        _ => js_name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{elm_name, names};
+    use serde_json::json;
+
+    #[test]
+    fn elm_names() {
+        assert_eq!(
+            elm_name("$author$project$Parser$Json$run"),
+            "Parser.Json.run"
+        );
+        assert_eq!(elm_name("$elm$core$List$map"), "List.map (elm/core)");
+        assert_eq!(
+            elm_name("$elm_explorations$test$Expect$equal"),
+            "Expect.equal (elm-explorations/test)"
+        );
+        assert_eq!(elm_name("_List_map"), "_List_map");
+        assert_eq!(elm_name("A2"), "A2");
+    }
+
+    #[test]
+    fn definitions_lines() {
+        let elm_js = "(function(scope){\n'use strict';\nfunction F2(fun) {\n  return fun;\n}\nvar $author$project$Main$view = function (model) {\n\treturn model;\n};\nvar $author$project$Main$update = F2(\n\tfunction (msg, model) {\n\t\treturn model;\n\t});";
+        assert_eq!(
+            names(elm_js),
+            json!([[3, "F2"], [6, "Main.view"], [9, "Main.update"]])
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! Summary of a tests run.
+//!
+//! When all tests are done, the supervisor writes the outcome of each test
+//! in the file `elm-stuff/tests-0.19.1/run-summary.json`,
+//! read back by elm-test-rs after the supervisor exits.
+
+use anyhow::Context;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+/// Status of a test.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Pass,
+    Fail,
+    Todo,
+    Skip,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Pass => "pass",
+            Status::Fail => "fail",
+            Status::Todo => "todo",
+            Status::Skip => "skip",
+        }
+    }
+}
+
+/// Outcome of one test.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestOutcome {
+    /// Labels of the test, with the outermost label first.
+    pub labels: Vec<String>,
+    /// Name of the module defining the test, if it is known.
+    pub module: Option<String>,
+    pub status: Status,
+    /// Duration in milliseconds.
+    pub duration: f64,
+    /// Peak of the heap during the test over the heap used when it started,
+    /// in bytes, measured with --heap-stats.
+    pub heap_growth: Option<f64>,
+}
+
+impl TestOutcome {
+    /// Full name of the test, joining all its labels.
+    pub fn name(&self) -> String {
+        self.labels.join(" > ")
+    }
+}
+
+/// Outcome of all tests of a run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunSummary {
+    pub exit_code: i32,
+    pub seed: u32,
+    pub fuzz_runs: u32,
+    pub tests: Vec<TestOutcome>,
+}
+
+impl RunSummary {
+    /// Path of the summary file written by the supervisor.
+    pub fn path(tests_root: &Path) -> PathBuf {
+        tests_root.join("run-summary.json")
+    }
+
+    /// Read the summary file written by the supervisor.
+    pub fn read(path: &Path) -> anyhow::Result<RunSummary> {
+        let summary_str =
+            std::fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+        let value: Value = serde_json::from_str(&summary_str)
+            .context(format!("Invalid JSON in {}", path.display()))?;
+        RunSummary::from_json(&value)
+    }
+
+    pub fn from_json(value: &Value) -> anyhow::Result<RunSummary> {
+        let tests = value["tests"]
+            .as_array()
+            .context("Missing tests in the run summary")?
+            .iter()
+            .map(|test| {
+                let labels = test["labels"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|l| l.as_str().map(|s| s.to_string()))
+                    .collect();
+                let status = match test["status"].as_str() {
+                    Some("pass") => Status::Pass,
+                    Some("fail") => Status::Fail,
+                    Some("todo") => Status::Todo,
+                    Some("skip") => Status::Skip,
+                    s => anyhow::bail!("Invalid test status in the run summary: {:?}", s),
+                };
+                Ok(TestOutcome {
+                    labels,
+                    module: test["module"].as_str().map(|s| s.to_string()),
+                    status,
+                    duration: test["duration"].as_f64().unwrap_or(0.0),
+                    heap_growth: test["heap"]["growth"].as_f64(),
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(RunSummary {
+            exit_code: value["exitCode"].as_i64().unwrap_or(0) as i32,
+            seed: value["seed"].as_u64().unwrap_or(0) as u32,
+            fuzz_runs: value["fuzzRuns"].as_u64().unwrap_or(0) as u32,
+            tests,
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        let tests: Vec<Value> = self
+            .tests
+            .iter()
+            .map(|test| {
+                let mut value = json!({
+                    "labels": test.labels.clone(),
+                    "status": test.status.as_str(),
+                    "duration": test.duration,
+                });
+                if let Some(module) = &test.module {
+                    value["module"] = json!(module);
+                }
+                if let Some(growth) = test.heap_growth {
+                    value["heap"] = json!({ "growth": growth });
+                }
+                value
+            })
+            .collect();
+        json!({
+            "exitCode": self.exit_code as i64,
+            "seed": self.seed,
+            "fuzzRuns": self.fuzz_runs,
+            "tests": tests,
+        })
+    }
+
+    /// Total duration of all tests, in milliseconds.
+    pub fn duration(&self) -> f64 {
+        self.tests.iter().map(|t| t.duration).sum()
+    }
+
+    /// Number of tests with the given status.
+    pub fn count(&self, status: Status) -> usize {
+        self.tests.iter().filter(|t| t.status == status).count()
+    }
+}
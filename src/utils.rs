@@ -1,7 +1,9 @@
 //! Utility functions for the other modules.
 
 use anyhow::Context;
+use fs2::FileExt;
 use path_absolutize::Absolutize;
+use std::cell::RefCell;
 use std::error::Error;
 use std::io::Write;
 use std::path::{Path, PathBuf};
@@ -70,10 +72,91 @@ fn default_elm_home() -> anyhow::Result<PathBuf> {
         .map(|p| p.join("elm"))
 }
 
+/// Print to stderr the versions of elm-test-rs and of the compiler, as the title of a run.
+pub fn print_title() {
+    let title = format!(
+        "elm-test-rs {} for elm 0.19.1",
+        std::env!("CARGO_PKG_VERSION")
+    );
+    log::warn!("\n{}\n{}\n", &title, "-".repeat(title.len()));
+}
+
+/// Duration after which posting to a url is given up, including reading the response,
+/// so that an unresponsive server does not hang the end of a run.
+const POST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+thread_local! {
+    /// Proxy given with `--proxy` to the fetcher of the dependency solver,
+    /// which is a plain function and cannot capture it.
+    static FETCH_PROXY: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with the proxy used by `http_fetch` on this thread set to the given one, if any.
+pub fn with_proxy<T>(proxy: Option<&str>, f: impl FnOnce() -> T) -> T {
+    let previous = FETCH_PROXY.with(|p| p.replace(proxy.map(|s| s.to_string())));
+    let result = f();
+    FETCH_PROXY.with(|p| p.replace(previous));
+    result
+}
+
+/// HTTP agent for the given url, going through the proxy configured for it, if any.
+/// A given proxy replaces the ones of the environment, but NO_PROXY still applies.
+/// Requests are given up after the timeout, if one is given.
+fn http_agent(
+    url: &str,
+    proxy: Option<&str>,
+    timeout: Option<std::time::Duration>,
+) -> anyhow::Result<ureq::Agent> {
+    let mut builder = ureq::builder().timeout_connect(std::time::Duration::from_secs(10));
+    if let Some(timeout) = timeout {
+        builder = builder.timeout(timeout);
+    }
+    let env_var = |var: &str| match proxy {
+        Some(proxy)
+            if var.eq_ignore_ascii_case("HTTPS_PROXY")
+                || var.eq_ignore_ascii_case("HTTP_PROXY") =>
+        {
+            Some(proxy.to_string())
+        }
+        _ => std::env::var(var).ok(),
+    };
+    if let Some(proxy) = proxy_for(url, env_var) {
+        log::debug!("Using the proxy {} for {}", proxy, url);
+        let proxy = ureq::Proxy::new(&proxy).context(format!("Invalid proxy {}", proxy))?;
+        builder = builder.proxy(proxy);
+    }
+    Ok(builder.build())
+}
+
+/// Proxy to use for the given url, from the HTTPS_PROXY or HTTP_PROXY environment variables
+/// depending on its scheme, unless its host is excluded by NO_PROXY.
+/// Lowercase variables are also accepted, such as `https_proxy`.
+pub fn proxy_for(url: &str, env_var: impl Fn(&str) -> Option<String>) -> Option<String> {
+    let var = |name: &str| {
+        env_var(name)
+            .or_else(|| env_var(&name.to_lowercase()))
+            .filter(|value| !value.trim().is_empty())
+    };
+    let (scheme, rest) = url.split_once("://")?;
+    let host = rest.split(['/', ':']).next().unwrap_or_default();
+    let no_proxy = var("NO_PROXY").unwrap_or_default();
+    let excluded = no_proxy
+        .split(',')
+        .map(|entry| entry.trim().trim_start_matches('.'))
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| entry == "*" || host == entry || host.ends_with(&format!(".{}", entry)));
+    if excluded {
+        return None;
+    }
+    match scheme {
+        "https" => var("HTTPS_PROXY").or_else(|| var("HTTP_PROXY")),
+        _ => var("HTTP_PROXY"),
+    }
+}
+
 pub fn http_fetch(url: &str) -> Result<String, Box<dyn Error>> {
-    let agent = ureq::builder()
-        .timeout_connect(std::time::Duration::from_secs(10))
-        .build();
+    let proxy = FETCH_PROXY.with(|p| p.borrow().clone());
+    let agent = http_agent(url, proxy.as_deref(), None)?;
     let response = agent
         .get(url)
         .call()
@@ -83,6 +166,24 @@ pub fn http_fetch(url: &str) -> Result<String, Box<dyn Error>> {
     Ok(response)
 }
 
+/// POST a JSON body to the given url, with a bearer token if one is given.
+pub fn http_post_json(
+    url: &str,
+    proxy: Option<&str>,
+    body: &str,
+    token: Option<&str>,
+) -> anyhow::Result<()> {
+    let agent = http_agent(url, proxy, Some(POST_TIMEOUT))?;
+    let mut request = agent.post(url).set("Content-Type", "application/json");
+    if let Some(token) = token {
+        request = request.set("Authorization", &format!("Bearer {}", token));
+    }
+    request
+        .send_string(body)
+        .context(format!("Error posting to {}", url))?;
+    Ok(())
+}
+
 // pub fn write_elm_json(project: &Project, matches: &ArgMatches) -> Result<()> {
 pub fn json_write<P: AsRef<Path>, T: ?Sized + serde::Serialize>(
     path: P,
@@ -99,6 +200,68 @@ pub fn json_write<P: AsRef<Path>, T: ?Sized + serde::Serialize>(
     writer.flush().map_err(|e| e.into())
 }
 
+/// Write a file atomically, by writing to a temporary file next to it and renaming it,
+/// so that an interrupted or concurrent write never leaves a half-written file.
+pub fn write_atomic<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> std::io::Result<()> {
+    let path = path.as_ref();
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+    let result = std::fs::write(&tmp_path, contents).and_then(|_| std::fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Lock on a directory, released when dropped or when the process exits.
+pub struct DirLock {
+    _file: std::fs::File,
+}
+
+/// Take the lock on a directory, with an advisory lock on its `.lock` file,
+/// waiting for another process to release it if needed.
+/// The operating system releases the lock of a process which was killed.
+pub fn lock_dir(dir: &Path) -> anyhow::Result<DirLock> {
+    std::fs::create_dir_all(dir).context(format!("Could not create {}", dir.display()))?;
+    let path = dir.join(".lock");
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .context(format!("Could not create {}", path.display()))?;
+    if file.try_lock_exclusive().is_err() {
+        log::warn!(
+            "Waiting for another elm-test-rs run to release {}",
+            path.display()
+        );
+        file.lock_exclusive()
+            .context(format!("Could not lock {}", path.display()))?;
+    }
+    Ok(DirLock { _file: file })
+}
+
+/// Path of a source directory in a generated elm.json, relative to the directory of that elm.json.
+/// It stays absolute when there is no relative path to it, such as on another Windows drive.
+pub fn relative_source_dir(path: &Path, base: &Path) -> PathBuf {
+    let same_root = path.components().next() == base.components().next();
+    match pathdiff::diff_paths(path, base) {
+        Some(relative) if same_root => relative,
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Source directory as written in an elm.json.
+/// Separators are forward slashes on Windows too, which the compiler always accepts.
+pub fn elm_json_path(path: &Path) -> Option<String> {
+    let path = path.to_str()?;
+    if cfg!(windows) {
+        Some(path.replace('\\', "/"))
+    } else {
+        Some(path.to_string())
+    }
+}
+
 /// Returns the absolute path with a useful error message if not possible.
 pub fn absolute_path<P: AsRef<Path>>(path: P) -> anyhow::Result<PathBuf> {
     let path = path.as_ref();
@@ -107,3 +270,52 @@ pub fn absolute_path<P: AsRef<Path>>(path: P) -> anyhow::Result<PathBuf> {
         path.display()
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{proxy_for, relative_source_dir};
+    use std::path::Path;
+
+    #[test]
+    fn source_dirs_relative_to_generated_elm_json() {
+        let tests_root = Path::new("/project/elm-stuff/tests-0.19.1");
+        assert_eq!(
+            relative_source_dir(Path::new("/project/src"), tests_root),
+            Path::new("../../src")
+        );
+        assert_eq!(
+            relative_source_dir(Path::new("/vendor/parser/src"), tests_root),
+            Path::new("../../../vendor/parser/src")
+        );
+        assert_eq!(
+            relative_source_dir(Path::new("src"), tests_root),
+            Path::new("src")
+        );
+    }
+
+    #[test]
+    fn proxy_from_environment() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        let proxy = env(&[
+            ("HTTPS_PROXY", "http://secure:3128"),
+            ("http_proxy", "http://plain:3128"),
+            ("NO_PROXY", "localhost, .internal.example.com"),
+        ]);
+        let https = Some("http://secure:3128".to_string());
+        let http = Some("http://plain:3128".to_string());
+        assert_eq!(proxy_for("https://package.elm-lang.org/all", proxy), https);
+        assert_eq!(proxy_for("http://package.elm-lang.org/all", proxy), http);
+        assert_eq!(proxy_for("http://localhost:8000/all", proxy), None);
+        assert_eq!(proxy_for("https://internal.example.com", proxy), None);
+        assert_eq!(proxy_for("https://pkg.internal.example.com", proxy), None);
+        assert_eq!(proxy_for("https://notinternal.example.com", proxy), https);
+        assert_eq!(proxy_for("https://x.org", env(&[("NO_PROXY", "*")])), None);
+        assert_eq!(proxy_for("https://x.org", env(&[])), None);
+    }
+}
@@ -0,0 +1,12 @@
+module {{ module_name }} exposing (suite)
+
+{{ imports }}
+import Expect
+import Test exposing (Test)
+
+
+suite : Test
+suite =
+    Test.describe "{{ source_module }}"
+        [ {{ tests }}
+        ]
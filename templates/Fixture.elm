@@ -0,0 +1,30 @@
+module ElmTestRs.Fixture exposing (read)
+
+{-| Fixture files of the tests, from the `tests/fixtures/` directory of the project.
+
+Only available when running the tests with elm-test-rs,
+which generates this module and embeds the fixtures in the compiled tests.
+
+-}
+
+
+{-| Contents of a fixture file, given by its path relative to `tests/fixtures/`,
+such as `read "users/alice.json"`.
+-}
+read : String -> Result String String
+read path =
+    case contentsReplaceMe___ path of
+        Just contents ->
+            Ok contents
+
+        Nothing ->
+            Err ("There is no fixture file tests/fixtures/" ++ path)
+
+
+{-| The implementation of this function will be replaced in the generated JS
+with a version that returns the contents of the fixtures embedded by elm-test-rs.
+If you rename or change this function you also need to update the regex that looks for it.
+-}
+contentsReplaceMe___ : String -> Maybe String
+contentsReplaceMe___ _ =
+    Nothing
@@ -3,6 +3,7 @@ port module Runner exposing (main)
 {{ imports }}
 
 import ElmTestRunner.Runner exposing (Flags, Model, Msg)
+import Expect
 import Json.Encode exposing (Value)
 import Test exposing (Test)
 
@@ -20,10 +21,11 @@ port sendResult : { id : Int, result : Value } -> Cmd msg
 
 
 {-| The implementation of this function will be replaced in the generated JS
-with a version that returns `Just value` if `value` is a `Test`, otherwise `Nothing`.
+with a version that returns `Just value` if `value` is a `Test`, otherwise `Nothing`,
+recording the name of the module defining it.
 If you rename or change this function you also need to update the regex that looks for it.
 -}
-check : a -> Maybe Test
+check : String -> a -> Maybe Test
 check =
     checkHelperReplaceMe___
 
@@ -0,0 +1,33 @@
+module ElmTestRs.Snapshot exposing (expect)
+
+{-| Snapshot assertions, comparing strings with the snapshots of the `tests/__snapshots__/` directory.
+
+Only available when running the tests with elm-test-rs,
+which generates this module and embeds the snapshots in the compiled tests.
+
+-}
+
+import Expect exposing (Expectation)
+
+
+{-| Expect a string to be the same as the snapshot of the given name,
+a path relative to `tests/__snapshots__/` such as `expect "invoices/simple.txt"`.
+A missing snapshot is written with the string, and all of them are with `--update-snapshots`.
+-}
+expect : String -> String -> Expectation
+expect name actual =
+    case checkReplaceMe___ name actual of
+        Nothing ->
+            Expect.pass
+
+        Just failure ->
+            Expect.fail failure
+
+
+{-| The implementation of this function will be replaced in the generated JS
+with a version comparing the string with the embedded snapshot, or recording it to write it.
+If you rename or change this function you also need to update the regex that looks for it.
+-}
+checkReplaceMe___ : String -> String -> Maybe String
+checkReplaceMe___ _ _ =
+    Nothing
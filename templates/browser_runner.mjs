@@ -0,0 +1,153 @@
+// Runner loaded in an iframe of the browser supervisor page,
+// so that tests have access to a real DOM and browser APIs.
+
+// Capture Debug.log from elm code
+// which has been kernel-switched to "console.elmlog"
+import { logs } from "./deno_logger.mjs";
+
+// Compiled by elm-test-rs from templates/Runner.elm
+import { Elm } from "./Runner.elm.js";
+
+// Timers leaked by tests, from templates/timer_leaks.js with --detect-leaks
+{{ timerLeaks }}
+
+// Messages to the supervisor page
+function send(msg) {
+  parent.postMessage(msg, "*");
+}
+
+// What the tests print with console.log, such as in ports,
+// is sent to the supervisor with log messages, apart from the reports.
+console.log = (...args) => send({ type_: "log", text: args.join(" ") + "\n" });
+console.info = console.log;
+
+// Start the Elm app
+const flags = { initialSeed: {{ initialSeed }}, fuzzRuns: {{ fuzzRuns }}, filter: {{ filter }} };
+const app = Elm.Runner.init({ flags: flags });
+
+// Record the timing at which we received the last "dispatch" message
+let startTime;
+
+// Id of the test being run, until its result is sent
+let runningTest;
+
+// Communication from Supervisor to Elm runner via port
+window.addEventListener("message", (event) => {
+  if (event.source !== parent) {
+    return;
+  }
+  const msg = event.data;
+  if (msg.type_ == "askTestsCount") {
+    app.ports.askTestsCount.send();
+  } else if (msg.type_ == "dispatch") {
+    send({ type_: "testStarted", id: msg.id });
+    startTime = performance.now();
+    if (globalThis.__elmTestLeaks) {
+      globalThis.__elmTestLeaks.start();
+    }
+    runningTest = msg.id;
+    try {
+      app.ports.receiveRunTest.send(msg.id);
+    } catch (error) {
+      reportError(error);
+    }
+  } else {
+    console.error("Invalid supervisor msg.type_:", msg.type_);
+  }
+});
+
+// Communication from Elm runner to Supervisor via port
+// Subscribe to outgoing Elm ports defined in templates/Runner.elm
+app.ports.sendResult.subscribe((msg) => {
+  runningTest = undefined;
+  msg.type_ = "testResult";
+  msg.duration = performance.now() - startTime;
+  if (globalThis.__elmTestLeaks) {
+    msg.leaks = globalThis.__elmTestLeaks.take();
+  }
+  msg.logs = logs;
+  if (globalThis.__elmTestCoverage) {
+    msg.coverage = takeCoverageHits();
+  }
+  if (globalThis.__elmTestSnapshotWrites) {
+    msg.snapshots = takeSnapshotWrites();
+  }
+  send(msg);
+  logs.length = 0;
+});
+app.ports.sendTestsCount.subscribe((msg) => {
+  msg.type_ = "testsCount";
+  msg.logs = logs;
+  // Labels and modules of the tests, by id, to name a test if it hangs or crashes the runner,
+  // and to know the module of each test.
+  msg.labels = globalThis.__elmTestLabels ? globalThis.__elmTestLabels() : [];
+  msg.modules = globalThis.__elmTestModules ? globalThis.__elmTestModules() : [];
+  // Modules exposing values, none of which is a test, such as misplaced helper modules.
+  msg.modulesWithoutTests = globalThis.__elmTestModulesWithoutTests
+    ? globalThis.__elmTestModulesWithoutTests()
+    : [];
+  send(msg);
+  logs.length = 0;
+});
+
+// Errors thrown by kernel code or ports during a test, even asynchronously,
+// are reported as a failure of that test, and the runner is kept for the next ones.
+// Errors outside of a test are reported as a crash of the runner, which replaces it.
+window.addEventListener("error", (event) => {
+  event.preventDefault();
+  reportError(event.error || event.message);
+});
+window.addEventListener("unhandledrejection", (event) => {
+  event.preventDefault();
+  reportError(event.reason);
+});
+
+function reportError(error) {
+  const details = errorDetails(error);
+  if (runningTest == undefined) {
+    send({ type_: "crash", error: details });
+    return;
+  }
+  const msg = {
+    type_: "testError",
+    id: runningTest,
+    error: details,
+    duration: performance.now() - startTime,
+    logs: logs,
+  };
+  runningTest = undefined;
+  if (globalThis.__elmTestCoverage) {
+    msg.coverage = takeCoverageHits();
+  }
+  send(msg);
+  logs.length = 0;
+}
+
+// Stack of an error, with the frames in Runner.elm.js translated into the Elm values they are in.
+function errorDetails(error) {
+  const stack = error && error.stack ? error.stack : String(error);
+  return globalThis.__elmTestStack ? globalThis.__elmTestStack(stack) : stack;
+}
+
+// Collect the snapshots to write since the last test result
+// (only present when the tests use ElmTestRs.Snapshot).
+function takeSnapshotWrites() {
+  const writes = globalThis.__elmTestSnapshotWrites;
+  globalThis.__elmTestSnapshotWrites = {};
+  return writes;
+}
+
+// Collect the coverage counters hit since the last test result
+// (only present when instrumented by the coverage subcommand).
+function takeCoverageHits() {
+  const counters = globalThis.__elmTestCoverage;
+  const hits = {};
+  counters.forEach((count, id) => {
+    if (count > 0) hits[id] = count;
+  });
+  counters.fill(0);
+  return hits;
+}
+
+// Tell the supervisor the runner is ready to receive messages
+send({ type_: "ready" });
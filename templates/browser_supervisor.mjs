@@ -0,0 +1,416 @@
+// Supervisor of the browser runtime, loaded by js/index.html in a headless browser.
+// Files are served and messages relayed to elm-test-rs by the server in src/browser.rs.
+
+// From templates/test_results.js
+{{ testResults }}
+
+// From templates/js_reporters.js
+{{ jsReporters }}
+
+import { Elm } from "./Reporter.elm.js";
+
+// Global variables
+let testsCount, todoTests, testsLabels, testsModules;
+let reporters = [];
+let runner = null;
+let working = false;
+// Same-origin iframes share the main thread of the page,
+// so tests run one after the other in a single runner.
+const workersCount = 1;
+let startWorkCallback = function(){};
+const persistent = {{ persistent }};
+const verbosity = {{ verbosity }};
+const summaryFile = {{ summaryFile }};
+const coverageHitsFile = {{ coverageHits }};
+let coverageHits = {};
+const htmlTemplate = {{ htmlTemplate }};
+const testModules = {{ testModules }};
+// Version of the protocol with elm-test-rs, defined in src/protocol.rs
+const protocolVersion = {{ protocolVersion }};
+// Maximum duration of a test in milliseconds, or null
+const testTimeout = {{ testTimeout }};
+// Run the tests of each module in a fresh worker, with --isolate-modules
+const isolateModules = {{ isolateModules }};
+// Dispatch tests to runners one by one ("test"), or by whole modules ("module")
+const granularity = {{ granularity }};
+// Average durations of the tests in the recorded runs, by name, to dispatch the slowest first
+const testDurations = {{ testDurations }};
+const hostname = location.hostname;
+// Versions and platform of the run, included in reports to make them self-describing
+const environment = {
+  ...{{ environment }},
+  runtime: navigator.userAgent,
+  platform: navigator.platform,
+  workers: workersCount,
+};
+const reports = {{ reports }};
+let finishedReporters = 0;
+
+// Messages to elm-test-rs, sent one after the other, in order,
+// with POST requests relayed to its channel.
+let channelWrites = Promise.resolve();
+function sendMessage(msg) {
+  const body = JSON.stringify(msg) + "\n";
+  channelWrites = channelWrites.then(() =>
+    fetch("/messages", { method: "POST", body: body })
+  );
+  return channelWrites;
+}
+
+// The page cannot write files, so they are written by the server.
+function writeFile(path, contents, append) {
+  return sendMessage({ type: "writeFile", path: path, contents: contents, append: append });
+}
+
+// Signal the end of the run to elm-test-rs, which closes the browser unless persistent.
+async function signalFinished(exitCode) {
+  await sendMessage({ type: "runEnd", exitCode: exitCode });
+}
+
+// Create long lived reporters, one per report
+const flags = {
+  initialSeed: {{ initialSeed }},
+  fuzzRuns: {{ fuzzRuns }},
+  globs: {{ globs }},
+  paths: {{ paths }},
+};
+reporters = reports.map(({ mode, js, options, output }) => {
+  const elm = Elm.Reporter.init({ flags: { ...flags, mode: mode } });
+  const reporter = { elm: elm, js: js ? jsReporters[js](options) : null, output: output };
+  reporter.toDirectory = Boolean(reporter.js && reporter.js.files);
+
+  // Pipe the Elm stdout port to the report, unless a JS reporter replaces it
+  elm.ports.stdout.subscribe(
+    (str) => reporter.js || writeReport(reporter, mode == "json" ? completeJsonEvent(str) : str)
+  );
+
+  // The run is finished when all reporters have finished
+  elm.ports.signalFinished.subscribe(({ exitCode }) => {
+    finishedReporters += 1;
+    if (finishedReporters == reporters.length) {
+      finishRun(exitCode);
+    }
+  });
+  return reporter;
+});
+
+// Send a report to elm-test-rs to print it, or append it to its output file
+function writeReport(reporter, str) {
+  if (reporter.toDirectory) {
+    return;
+  } else if (reporter.output) {
+    writeFile(reporter.output, str, true);
+  } else {
+    sendMessage({ type: "report", text: str });
+  }
+}
+
+// There is no terminal to show the progress of the run.
+function updateProgress() {}
+
+// Write the files of a directory reporter into its output directory
+function writeReportFiles(reporter, files) {
+  files.forEach(([name, content]) =>
+    writeFile(`${reporter.output}/${name}`, content, false)
+  );
+}
+
+// When all reporters have finished clean the runner.
+// A persistent supervisor keeps it for the next run, in case it uses the same runner.
+async function finishRun(exitCode) {
+  if (!persistent) {
+    terminateRunner();
+  }
+  working = false;
+  reporters.forEach((reporter) => {
+    if (reporter.toDirectory) {
+      writeReportFiles(reporter, reporter.js.files(exitCode));
+    } else if (reporter.js) {
+      writeReport(reporter, reporter.js.end(exitCode));
+    }
+  });
+  writeFile(summaryFile, runSummary(exitCode), false);
+  if (coverageHitsFile) {
+    writeFile(coverageHitsFile, JSON.stringify(coverageHits), false);
+  }
+  await signalFinished(exitCode);
+  startWorkCallback();
+}
+
+// When receiving a runStart message from elm-test-rs, start the test runner
+// The message contains the runner url, such as "/js/browser_runner.mjs"
+async function receiveMessages() {
+  for (;;) {
+    const response = await fetch("/next");
+    if (response.status == 204) {
+      continue;
+    } else if (!response.ok) {
+      return;
+    }
+    const msg = await response.json();
+    if (msg.type != "runStart") {
+      console.error("Invalid elm-test-rs msg.type:", msg.type);
+      continue;
+    }
+    const runnerPage = msg.runner.replace(/\.mjs$/, ".html");
+    working
+      ? registerWork(runnerPage, msg.reuseWorkers)
+      : startWork(runnerPage, msg.reuseWorkers);
+  }
+}
+await sendMessage({ type: "hello", protocol: protocolVersion });
+receiveMessages();
+
+function registerWork(runnerPage, reuseWorkers) {
+  startWorkCallback = () => startWork(runnerPage, reuseWorkers);
+}
+
+function startWork(runnerPage, reuseWorkers) {
+  startWorkCallback = function(){};
+  working = true;
+  // Keep the runner of the previous run if it loaded the same runner page
+  if (!reuseWorkers || !runner) {
+    terminateRunner();
+    newRunner(runnerPage);
+  }
+  runner.postMessage({ type_: "askTestsCount" });
+}
+
+// Start the runner in an iframe, for the tests to have a real DOM.
+// Messages to the runner are queued until it is ready.
+function newRunner(runnerPage) {
+  const frame = document.createElement("iframe");
+  frame.src = runnerPage;
+  const queue = [];
+  runner = {
+    frame: frame,
+    page: runnerPage,
+    ready: false,
+    postMessage: (msg) =>
+      runner.ready ? frame.contentWindow.postMessage(msg, "*") : queue.push(msg),
+    flush: () => queue.splice(0).forEach((msg) => frame.contentWindow.postMessage(msg, "*")),
+  };
+  document.body.appendChild(frame);
+}
+
+function terminateRunner() {
+  if (runner) {
+    clearTimeout(runner.timeout);
+    runner.frame.remove();
+    runner = null;
+  }
+}
+
+// Messages of the runner
+window.addEventListener("message", (event) => {
+  if (!runner || event.source !== runner.frame.contentWindow) {
+    return;
+  }
+  if (event.data.type_ == "ready") {
+    runner.ready = true;
+    runner.flush();
+  } else {
+    handleRunnerMsg(runner, event.data);
+  }
+});
+
+// Handle a test result
+function handleRunnerMsg(runner, msg) {
+  if (msg.type_ == "testsCount") {
+    msg.logs.forEach((str) => sendMessage({ type: "log", text: str }));
+    setupWithTestsCount(msg);
+  } else if (msg.type_ == "testStarted") {
+    startTest(runner);
+  } else if (msg.type_ == "testResult") {
+    clearTimeout(runner.timeout);
+    if (msg.leaks && msg.leaks.length > 0) {
+      const test = runner.running || { id: msg.id, labels: [] };
+      sendMessage({ type: "log", text: leaksWarning(test, msg.leaks) });
+    }
+    delete msg.leaks;
+    runner.running = null;
+    dispatchWork(runner, nextTest(runner));
+    reportResult(msg);
+  } else if (msg.type_ == "log") {
+    sendMessage({ type: "log", text: msg.text });
+  } else if (msg.type_ == "testError") {
+    clearTimeout(runner.timeout);
+    const test = runner.running || { id: msg.id, labels: [] };
+    runner.running = null;
+    dispatchWork(runner, nextTest(runner));
+    const problem = `threw an uncaught error:\n\n${msg.error}`;
+    const result = crashedResult(test, problem, msg.duration);
+    result.logs = msg.logs;
+    result.coverage = msg.coverage;
+    reportResult(result);
+  } else if (msg.type_ == "crash") {
+    replaceRunner(runner, `crashed the runner:\n\n${msg.error}`);
+  } else {
+    console.error("Invalid runner msg.type_:", msg.type_);
+  }
+}
+
+// Send a test result to all reporters
+function reportResult(msg) {
+  if (msg.coverage) {
+    mergeCoverage(msg.coverage);
+    delete msg.coverage;
+  }
+  if (msg.snapshots) {
+    for (const name in msg.snapshots) {
+      sendMessage({ type: "snapshot", name: name, contents: msg.snapshots[name] });
+    }
+    delete msg.snapshots;
+  }
+  const result = recordResult(msg);
+  sendMessage({
+    type: "testResult",
+    id: result.id,
+    labels: result.labels,
+    module: moduleOf(result.labels),
+    status: result.status,
+    duration: result.duration,
+  });
+  reporters.forEach((reporter) => {
+    if (reporter.js) {
+      writeReport(reporter, reporter.js.result(result));
+    }
+    reporter.elm.ports.incomingResult.send(msg);
+  });
+  updateProgress();
+}
+
+// Reset supervisor tests count and reporter
+// Start work on the runner
+function setupWithTestsCount(msg) {
+  // Reset supervisor tests
+  testsCount = msg.testsCount;
+  testsLabels = msg.labels || [];
+  testsModules = msg.modules || [];
+  recordTestsModules(testsLabels, testsModules);
+  const modulesWithoutTests = msg.modulesWithoutTests || [];
+  if (verbosity >= 1 && modulesWithoutTests.length > 0) {
+    sendMessage({ type: "log", text: modulesWithoutTestsWarning(modulesWithoutTests.sort()) });
+  }
+  todoTests = dispatchOrder(testsCount, testsLabels, testsModules, testDurations, isolateModules);
+
+  // Reset reports outputs, reporters, results and coverage
+  reporters.forEach((reporter) => {
+    if (reporter.output && !reporter.toDirectory) {
+      writeFile(reporter.output, "", false);
+    }
+    reporter.elm.ports.restart.send(msg);
+  });
+  testResults = [];
+  testResultsByLabels = new Map();
+  coverageHits = {};
+  finishedReporters = 0;
+  runner.assigned = [];
+  reporters.forEach((reporter) => {
+    if (reporter.js) {
+      writeReport(reporter, reporter.js.begin(testsCount));
+    }
+  });
+
+  // Send first runner job
+  if (testsCount == 0) {
+    abortRun(runner, "found no exposed values of type Test. Did you forget to expose them?");
+  } else {
+    dispatchWork(runner, nextTest(runner));
+  }
+}
+
+// Time the test started in the runner, and stop the run if it exceeds the timeout.
+function startTest(runner) {
+  runner.startTime = performance.now();
+  if (testTimeout) {
+    runner.timeout = setTimeout(
+      () => abortRun(runner, `did not finish within ${testTimeout / 1000}s`),
+      testTimeout
+    );
+  }
+}
+
+// With --isolate-modules, replace a runner by a fresh one before it runs a test of another module,
+// so that the global state of kernel code or ports does not leak between modules.
+function recycleRunner(recycled, testId) {
+  terminateRunner();
+  newRunner(recycled.page);
+  runner.assigned = recycled.assigned;
+  dispatchWork(runner, testId);
+}
+
+// Record a failure for the test running in a crashed runner,
+// and continue with the remaining tests in a new runner.
+// A crash while setting up the tests stops the run, since every test would crash the same way.
+function replaceRunner(crashed, problem) {
+  if (!working) {
+    return;
+  }
+  const test = crashed.running;
+  if (!test) {
+    abortRun(crashed, problem);
+    return;
+  }
+  terminateRunner();
+  newRunner(crashed.page);
+  runner.assigned = crashed.assigned;
+  dispatchWork(runner, nextTest(runner));
+  reportResult(crashedResult(test, problem, performance.now() - crashed.startTime));
+}
+
+// Stop the run because of a problem in the runner, such as a hanging test.
+async function abortRun(stopped, problem) {
+  if (!working) {
+    return;
+  }
+  const test = stopped && stopped.running;
+  terminateRunner();
+  working = false;
+  const name = test ? testName(test) : "The setup of the tests";
+  sendMessage({ type: "fatal", message: `${name} ${problem}` });
+  await signalFinished(1);
+  startWorkCallback();
+}
+
+// Next test to run in a runner.
+// With --granularity module, a runner is assigned all the remaining tests of a module at once,
+// and runs them before taking another module.
+function nextTest(runner) {
+  if (granularity != "module") {
+    return todoTests.pop();
+  }
+  if (!runner.assigned || runner.assigned.length == 0) {
+    const first = todoTests.pop();
+    if (first == undefined) {
+      return undefined;
+    }
+    const module = testsModules[first];
+    const sameModule = (id) => testsModules[id] == module;
+    runner.assigned = [first, ...todoTests.filter(sameModule).reverse()];
+    todoTests = todoTests.filter((id) => !sameModule(id));
+  }
+  return runner.assigned.shift();
+}
+
+// Ask runner to run some test, and record it to name it if it hangs or crashes the runner.
+function dispatchWork(runner, testId) {
+  if (testId != undefined) {
+    const module = testsModules[testId];
+    if (isolateModules && runner.module != undefined && runner.module != module) {
+      recycleRunner(runner, testId);
+      return;
+    }
+    runner.module = module;
+    runner.running = { id: testId, labels: testsLabels[testId] || [] };
+    runner.startTime = performance.now();
+    runner.postMessage({ type_: "dispatch", id: testId });
+  }
+}
+
+// Accumulate the coverage counters hit by one test.
+function mergeCoverage(hits) {
+  for (const id in hits) {
+    coverageHits[id] = (coverageHits[id] || 0) + hits[id];
+  }
+}
@@ -1,10 +1,21 @@
 // From templates/polyfills.js
 {{ polyfills }}
 
+// Lightweight DOM from templates/dom_shims.js, with --dom
+{{ domImport }}
+
+// Timers leaked by tests, from templates/timer_leaks.js with --detect-leaks
+{{ timerLeaks }}
+
 // Capture Debug.log from elm code
 // which has been kernel-switched to "console.elmlog"
 import { logs } from "./deno_logger.mjs";
 
+// What the tests print on stdout, such as with console.log in ports,
+// is sent to the supervisor with log messages, apart from the reports.
+console.log = (...args) => self.postMessage({ type_: "log", text: args.join(" ") + "\n" });
+console.info = console.log;
+
 // Compiled by elm-test-rs from templates/Runner.elm
 import { Elm } from "./Runner.elm.js";
 
@@ -12,33 +23,129 @@ import { Elm } from "./Runner.elm.js";
 const flags = { initialSeed: {{ initialSeed }}, fuzzRuns: {{ fuzzRuns }}, filter: {{ filter }} };
 const app = Elm.Runner.init({ flags: flags });
 
-// Record the timing at which we received the last "runTest" message
+// Record the timing at which we received the last "dispatch" message
 let startTime;
 
+// Id of the test being run, until its result is sent
+let runningTest;
+
 // Communication from Supervisor to Elm runner via port
 self.onmessage = (msg) => {
   if (msg.data.type_ == "askTestsCount") {
     app.ports.askTestsCount.send();
-  } else if (msg.data.type_ == "runTest") {
+  } else if (msg.data.type_ == "dispatch") {
+    reportTestStarted(msg.data.id);
     startTime = performance.now();
-    app.ports.receiveRunTest.send(msg.data.id);
+    if (globalThis.__elmTestLeaks) {
+      globalThis.__elmTestLeaks.start();
+    }
+    runningTest = msg.data.id;
+    try {
+      app.ports.receiveRunTest.send(msg.data.id);
+    } catch (error) {
+      reportTestError(error);
+    }
   } else {
     console.error("Invalid supervisor msg.type_:", msg.data.type_);
   }
 };
 
+// Tell the supervisor when a test starts, to time it.
+function reportTestStarted(id) {
+  self.postMessage({ type_: "testStarted", id: id });
+}
+
 // Communication from Elm runner to Supervisor via port
 // Subscribe to outgoing Elm ports defined in templates/Runner.elm
 app.ports.sendResult.subscribe((msg) => {
+  runningTest = undefined;
   msg.type_ = "testResult";
   msg.duration = performance.now() - startTime;
+  if (globalThis.__elmTestLeaks) {
+    msg.leaks = globalThis.__elmTestLeaks.take();
+  }
   msg.logs = logs;
+  if (globalThis.__elmTestCoverage) {
+    msg.coverage = takeCoverageHits();
+  }
+  if (globalThis.__elmTestSnapshotWrites) {
+    msg.snapshots = takeSnapshotWrites();
+  }
   self.postMessage(msg);
   logs.length = 0;
 });
 app.ports.sendTestsCount.subscribe((msg) => {
   msg.type_ = "testsCount";
   msg.logs = logs;
+  // Labels and modules of the tests, by id, to name a test if it hangs or crashes the worker,
+  // and to know the module of each test.
+  msg.labels = globalThis.__elmTestLabels ? globalThis.__elmTestLabels() : [];
+  msg.modules = globalThis.__elmTestModules ? globalThis.__elmTestModules() : [];
+  // Modules exposing values, none of which is a test, such as misplaced helper modules.
+  msg.modulesWithoutTests = globalThis.__elmTestModulesWithoutTests
+    ? globalThis.__elmTestModulesWithoutTests()
+    : [];
   self.postMessage(msg);
   logs.length = 0;
 });
+
+// Errors thrown by kernel code or ports during a test, even asynchronously,
+// are reported as a failure of that test, and the worker is kept alive for the next ones.
+// Errors outside of a test still crash the worker, which stops the run.
+self.addEventListener("error", (event) => {
+  if (runningTest != undefined) {
+    event.preventDefault();
+    reportTestError(event.error || event.message);
+  }
+});
+self.addEventListener("unhandledrejection", (event) => {
+  if (runningTest != undefined) {
+    event.preventDefault();
+    reportTestError(event.reason);
+  }
+});
+
+function reportTestError(error) {
+  if (runningTest == undefined) {
+    throw error;
+  }
+  const msg = {
+    type_: "testError",
+    id: runningTest,
+    error: errorDetails(error),
+    duration: performance.now() - startTime,
+    logs: logs,
+  };
+  runningTest = undefined;
+  if (globalThis.__elmTestCoverage) {
+    msg.coverage = takeCoverageHits();
+  }
+  self.postMessage(msg);
+  logs.length = 0;
+}
+
+// Stack of an error, with the frames in Runner.elm.js translated into the Elm values they are in.
+function errorDetails(error) {
+  const stack = error && error.stack ? error.stack : String(error);
+  return globalThis.__elmTestStack ? globalThis.__elmTestStack(stack) : stack;
+}
+
+// Collect the snapshots to write since the last test result
+// (only present when the tests use ElmTestRs.Snapshot).
+function takeSnapshotWrites() {
+  const writes = globalThis.__elmTestSnapshotWrites;
+  globalThis.__elmTestSnapshotWrites = {};
+  return writes;
+}
+
+// Collect the coverage counters hit since the last test result
+// (only present when instrumented by the coverage subcommand).
+function takeCoverageHits() {
+  const counters = globalThis.__elmTestCoverage;
+  const hits = {};
+  counters.forEach((count, id) => {
+    if (count > 0) hits[id] = count;
+  });
+  counters.fill(0);
+  return hits;
+}
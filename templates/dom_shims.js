@@ -0,0 +1,288 @@
+// Lightweight DOM for the runners, enabled with --dom.
+// It implements what elm/virtual-dom and elm/browser use to render views
+// and listen to events, so that programs can be started in tests without a real browser.
+// Nothing is laid out or painted, sizes and positions are always 0.
+(function () {
+  if (typeof globalThis.document != "undefined" && globalThis.document.createElement) {
+    return;
+  }
+
+  class Event {
+    constructor(type, options) {
+      this.type = type;
+      this.bubbles = Boolean(options && options.bubbles);
+      this.cancelable = Boolean(options && options.cancelable);
+      this.defaultPrevented = false;
+      this.target = null;
+      this.currentTarget = null;
+      this._stopped = false;
+      Object.assign(this, options);
+    }
+    preventDefault() {
+      if (this.cancelable) this.defaultPrevented = true;
+    }
+    stopPropagation() {
+      this._stopped = true;
+    }
+    stopImmediatePropagation() {
+      this._stopped = true;
+    }
+  }
+
+  class EventTarget {
+    constructor() {
+      this._listeners = {};
+    }
+    addEventListener(type, listener) {
+      const listeners = (this._listeners[type] = this._listeners[type] || []);
+      if (!listeners.includes(listener)) listeners.push(listener);
+    }
+    removeEventListener(type, listener) {
+      const listeners = this._listeners[type] || [];
+      const index = listeners.indexOf(listener);
+      if (index >= 0) listeners.splice(index, 1);
+    }
+    dispatchEvent(event) {
+      event.target = event.target || this;
+      for (let node = this; node && !event._stopped; node = event.bubbles ? node.parentNode : null) {
+        event.currentTarget = node;
+        (node._listeners[event.type] || []).slice().forEach((listener) =>
+          typeof listener == "function" ? listener.call(node, event) : listener.handleEvent(event)
+        );
+      }
+      return !event.defaultPrevented;
+    }
+  }
+
+  class Node extends EventTarget {
+    constructor(nodeType, nodeName) {
+      super();
+      this.nodeType = nodeType;
+      this.nodeName = nodeName;
+      this.parentNode = null;
+      this.childNodes = [];
+    }
+    get firstChild() {
+      return this.childNodes[0] || null;
+    }
+    get lastChild() {
+      return this.childNodes[this.childNodes.length - 1] || null;
+    }
+    get nextSibling() {
+      const siblings = this.parentNode ? this.parentNode.childNodes : [];
+      return siblings[siblings.indexOf(this) + 1] || null;
+    }
+    get previousSibling() {
+      const siblings = this.parentNode ? this.parentNode.childNodes : [];
+      return siblings[siblings.indexOf(this) - 1] || null;
+    }
+    get textContent() {
+      return this.childNodes.map((child) => child.textContent).join("");
+    }
+    set textContent(text) {
+      this.childNodes.forEach((child) => (child.parentNode = null));
+      this.childNodes = [];
+      if (text) this.appendChild(new Text(String(text)));
+    }
+    insertBefore(node, reference) {
+      if (node.nodeType == 11) {
+        node.childNodes.slice().forEach((child) => this.insertBefore(child, reference));
+        return node;
+      }
+      if (node.parentNode) node.parentNode.removeChild(node);
+      const index = reference ? this.childNodes.indexOf(reference) : -1;
+      index < 0 ? this.childNodes.push(node) : this.childNodes.splice(index, 0, node);
+      node.parentNode = this;
+      return node;
+    }
+    appendChild(node) {
+      return this.insertBefore(node, null);
+    }
+    removeChild(node) {
+      const index = this.childNodes.indexOf(node);
+      if (index >= 0) this.childNodes.splice(index, 1);
+      node.parentNode = null;
+      return node;
+    }
+    replaceChild(node, old) {
+      this.insertBefore(node, old);
+      return this.removeChild(old);
+    }
+    remove() {
+      if (this.parentNode) this.parentNode.removeChild(this);
+    }
+    contains(node) {
+      for (; node; node = node.parentNode) if (node === this) return true;
+      return false;
+    }
+  }
+
+  class Text extends Node {
+    constructor(data) {
+      super(3, "#text");
+      this.data = data;
+    }
+    get textContent() {
+      return this.data;
+    }
+    set textContent(text) {
+      this.data = String(text);
+    }
+    get nodeValue() {
+      return this.data;
+    }
+    replaceData(offset, count, data) {
+      this.data = this.data.slice(0, offset) + data + this.data.slice(offset + count);
+    }
+  }
+
+  class Element extends Node {
+    constructor(tagName, namespaceURI) {
+      super(1, tagName.toUpperCase());
+      this.tagName = tagName.toUpperCase();
+      this.localName = tagName;
+      this.namespaceURI = namespaceURI || "http://www.w3.org/1999/xhtml";
+      this.attributes = [];
+      this.style = {};
+      this.value = "";
+      this.checked = false;
+    }
+    get children() {
+      return this.childNodes.filter((child) => child.nodeType == 1);
+    }
+    get id() {
+      return this.getAttribute("id") || "";
+    }
+    set id(id) {
+      this.setAttribute("id", id);
+    }
+    get className() {
+      return this.getAttribute("class") || "";
+    }
+    set className(name) {
+      this.setAttribute("class", name);
+    }
+    getAttribute(name) {
+      const attribute = this.attributes.find((attr) => attr.name == name);
+      return attribute ? attribute.value : null;
+    }
+    hasAttribute(name) {
+      return this.getAttribute(name) !== null;
+    }
+    setAttribute(name, value) {
+      const attribute = this.attributes.find((attr) => attr.name == name);
+      attribute ? (attribute.value = String(value)) : this.attributes.push({ name: name, value: String(value) });
+    }
+    removeAttribute(name) {
+      this.attributes = this.attributes.filter((attr) => attr.name != name);
+    }
+    getAttributeNS(namespace, name) {
+      return this.getAttribute(name);
+    }
+    setAttributeNS(namespace, name, value) {
+      this.setAttribute(name, value);
+    }
+    removeAttributeNS(namespace, name) {
+      this.removeAttribute(name.replace(/^.*:/, ""));
+    }
+    getElementsByTagName(tag) {
+      const name = tag.toUpperCase();
+      return descendants(this).filter((node) => name == "*" || node.tagName == name);
+    }
+    querySelector(selector) {
+      return this.querySelectorAll(selector)[0] || null;
+    }
+    // Only simple selectors are supported: a tag, #id or .class.
+    querySelectorAll(selector) {
+      return descendants(this).filter((node) => matches(node, selector.trim()));
+    }
+    getBoundingClientRect() {
+      return { x: 0, y: 0, top: 0, left: 0, right: 0, bottom: 0, width: 0, height: 0 };
+    }
+    focus() {
+      globalThis.document.activeElement = this;
+    }
+    blur() {
+      globalThis.document.activeElement = globalThis.document.body;
+    }
+    click() {
+      this.dispatchEvent(new Event("click", { bubbles: true, cancelable: true }));
+    }
+  }
+
+  class DocumentFragment extends Node {
+    constructor() {
+      super(11, "#document-fragment");
+    }
+  }
+
+  function descendants(node) {
+    return node.childNodes
+      .filter((child) => child.nodeType == 1)
+      .flatMap((child) => [child, ...descendants(child)]);
+  }
+
+  function matches(node, selector) {
+    if (selector.startsWith("#")) return node.id == selector.slice(1);
+    if (selector.startsWith(".")) return node.className.split(/\s+/).includes(selector.slice(1));
+    return node.tagName == selector.toUpperCase();
+  }
+
+  const document = new Node(9, "#document");
+  Object.assign(document, {
+    documentElement: new Element("html"),
+    head: new Element("head"),
+    body: new Element("body"),
+    title: "",
+    createElement: (tag) => new Element(tag),
+    createElementNS: (namespace, tag) => new Element(tag, namespace),
+    createTextNode: (data) => new Text(String(data)),
+    createDocumentFragment: () => new DocumentFragment(),
+    createEvent: () => new Event(""),
+    getElementById: (id) => descendants(document).find((node) => node.id == id) || null,
+    getElementsByTagName: (tag) => document.documentElement.getElementsByTagName(tag),
+    querySelector: (selector) => document.documentElement.querySelector(selector),
+    querySelectorAll: (selector) => document.documentElement.querySelectorAll(selector),
+  });
+  document.appendChild(document.documentElement);
+  document.documentElement.appendChild(document.head);
+  document.documentElement.appendChild(document.body);
+  document.activeElement = document.body;
+  document.visibilityState = "visible";
+  document.hidden = false;
+
+  const location = globalThis.location || { href: "http://localhost/", protocol: "http:", host: "localhost", hostname: "localhost", origin: "http://localhost", port: "", pathname: "/", search: "", hash: "" };
+  document.location = location;
+  const window = new EventTarget();
+  Object.assign(window, {
+    document: document,
+    location: location,
+    navigator: globalThis.navigator || { userAgent: "elm-test-rs" },
+    history: {
+      pushState: function () {},
+      replaceState: function () {},
+      go: function () {},
+    },
+    innerWidth: 1024,
+    innerHeight: 768,
+    pageXOffset: 0,
+    pageYOffset: 0,
+    scroll: function () {},
+    scrollTo: function () {},
+    getComputedStyle: (element) => element.style,
+    requestAnimationFrame: (callback) => setTimeout(() => callback(Date.now()), 1000 / 60),
+    cancelAnimationFrame: (id) => clearTimeout(id),
+  });
+
+  Object.assign(globalThis, {
+    window: window,
+    document: document,
+    Node: Node,
+    Text: Text,
+    Element: Element,
+    HTMLElement: Element,
+    Event: Event,
+    requestAnimationFrame: window.requestAnimationFrame,
+    cancelAnimationFrame: window.cancelAnimationFrame,
+  });
+})();
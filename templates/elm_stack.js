@@ -0,0 +1,29 @@
+// Top-level definitions of Runner.elm.js by the line where they start, generated by elm-test-rs,
+// to translate the frames of JS stack traces into the Elm values they are in.
+globalThis.__elmTestNames = {{ names }};
+
+globalThis.__elmTestStack = function (stack) {
+  const names = globalThis.__elmTestNames;
+  return stack
+    .split("\n")
+    .map((frame) => {
+      const location = frame.match(/Runner\.elm\.js:(\d+)/);
+      if (!location) return frame;
+      // Last definition starting before the line of the frame
+      const line = Number(location[1]);
+      let low = 0;
+      let high = names.length - 1;
+      let found = -1;
+      while (low <= high) {
+        const middle = (low + high) >> 1;
+        if (names[middle][0] <= line) {
+          found = middle;
+          low = middle + 1;
+        } else {
+          high = middle - 1;
+        }
+      }
+      return found < 0 ? frame : `    at ${names[found][1]} (Runner.elm.js:${line})`;
+    })
+    .join("\n");
+};
@@ -0,0 +1,125 @@
+// HTTP requests are answered by the fixtures given with --http-fixtures,
+// so that tests never reach the network and behave the same offline.
+// Other requests fail like a network error, with a message telling which request it was.
+var httpFixtures = {{ httpFixtures }};
+
+function findHttpFixture(method, url) {
+  var fixture = httpFixtures.find(function (fixture) {
+    var sameMethod = !fixture.method || fixture.method.toUpperCase() === method.toUpperCase();
+    var sameUrl = fixture.url.endsWith("*")
+      ? url.startsWith(fixture.url.slice(0, -1))
+      : url === fixture.url;
+    return sameMethod && sameUrl;
+  });
+  if (!fixture) {
+    console.error("elm-test-rs: no HTTP fixture for " + method + " " + url + ", failing like a network error");
+  }
+  return fixture;
+}
+
+function httpFixtureBody(fixture) {
+  if (fixture.body === undefined) return "";
+  return typeof fixture.body === "string" ? fixture.body : JSON.stringify(fixture.body);
+}
+
+var XMLHttpRequest = function () {
+  this._listeners = {};
+  this._headers = {};
+  this.upload = { addEventListener: function () {} };
+  this.readyState = 0;
+  this.status = 0;
+  this.statusText = "";
+  this.response = null;
+  this.responseText = "";
+  this.responseType = "";
+  this.responseURL = "";
+  this.timeout = 0;
+  this.withCredentials = false;
+};
+XMLHttpRequest.prototype.addEventListener = function (type, listener) {
+  (this._listeners[type] = this._listeners[type] || []).push(listener);
+};
+XMLHttpRequest.prototype.removeEventListener = function (type, listener) {
+  this._listeners[type] = (this._listeners[type] || []).filter(function (l) {
+    return l !== listener;
+  });
+};
+XMLHttpRequest.prototype._emit = function (type) {
+  var event = { type: type, target: this };
+  if (this["on" + type]) this["on" + type](event);
+  (this._listeners[type] || []).forEach(function (listener) {
+    listener(event);
+  });
+};
+XMLHttpRequest.prototype.open = function (method, url) {
+  this._method = method;
+  this._url = url;
+  this.readyState = 1;
+};
+XMLHttpRequest.prototype.setRequestHeader = function () {};
+XMLHttpRequest.prototype.overrideMimeType = function () {};
+XMLHttpRequest.prototype.getResponseHeader = function (name) {
+  var value = this._headers[name.toLowerCase()];
+  return value === undefined ? null : value;
+};
+XMLHttpRequest.prototype.getAllResponseHeaders = function () {
+  var headers = this._headers;
+  return Object.keys(headers)
+    .map(function (name) {
+      return name + ": " + headers[name] + "\r\n";
+    })
+    .join("");
+};
+XMLHttpRequest.prototype.abort = function () {
+  this._aborted = true;
+  this._emit("abort");
+};
+XMLHttpRequest.prototype.send = function () {
+  var xhr = this;
+  var fixture = findHttpFixture(xhr._method, xhr._url);
+  setTimeout(function () {
+    if (xhr._aborted) return;
+    xhr.readyState = 4;
+    if (!fixture) {
+      xhr._emit("error");
+      return;
+    }
+    var body = httpFixtureBody(fixture);
+    var headers = fixture.headers || {};
+    Object.keys(headers).forEach(function (name) {
+      xhr._headers[name.toLowerCase()] = String(headers[name]);
+    });
+    xhr.status = fixture.status || 200;
+    xhr.statusText = fixture.statusText || "";
+    xhr.responseURL = xhr._url;
+    xhr.responseText = body;
+    if (xhr.responseType === "arraybuffer") {
+      xhr.response = new TextEncoder().encode(body).buffer;
+    } else if (xhr.responseType === "blob" && typeof Blob !== "undefined") {
+      xhr.response = new Blob([body]);
+    } else {
+      xhr.response = body;
+    }
+    xhr._emit("load");
+    xhr._emit("loadend");
+  }, 0);
+};
+globalThis.XMLHttpRequest = XMLHttpRequest;
+
+if (typeof Response !== "undefined") {
+  globalThis.fetch = function (input, init) {
+    var url = typeof input === "string" ? input : String(input.url || input);
+    var method = (init && init.method) || (input && input.method) || "GET";
+    var fixture = findHttpFixture(method, url);
+    if (!fixture) {
+      return Promise.reject(new TypeError("fetch failed: no HTTP fixture for " + method + " " + url));
+    }
+    return Promise.resolve(
+      new Response(httpFixtureBody(fixture), {
+        status: fixture.status || 200,
+        statusText: fixture.statusText || "",
+        headers: fixture.headers || {},
+      })
+    );
+  };
+}
@@ -0,0 +1,924 @@
+// Reporters implemented directly in the supervisor.
+// When one of them is selected, the Elm reporter runs in json mode
+// and its output is discarded.
+// Each entry creates a reporter from its options,
+// with its own state since there may be several reports.
+// A reporter returns the text to print at the beginning of a run,
+// for each decoded test result, and at the end of the run.
+// Reporters with a files function instead write files into their output directory.
+const jsReporters = {
+  tree: consoleReporter,
+  tap: tapReporter,
+  azure: azureReporter,
+  markdown: markdownReporter,
+  html: htmlReporter,
+  allure: allureReporter,
+  xunit: xunitReporter,
+  dot: dotReporter,
+  ndjson: ndjsonReporter,
+  "json-compat": jsonCompatReporter,
+  junit: junitReporter,
+  "junit-per-module": junitPerModuleReporter,
+  badge: badgeReporter,
+};
+
+// Tree console report, with results grouped under their describe hierarchy.
+// Failures are printed as soon as they are received, and the tree of results at the end,
+// where groups are collapsed into a single line with their counts,
+// unless they have todo or skipped tests. Failed tests are not repeated in the tree.
+function consoleReporter(options) {
+  const color = Boolean(options && options.color);
+  const paint = (code) => (str) => (color ? `\x1b[${code}m${str}\x1b[0m` : str);
+  const style = {
+    color,
+    maxFailureOutput: options && options.maxFailureOutput,
+    green: paint(32),
+    red: paint(31),
+    yellow: paint(33),
+    dim: paint(2),
+  };
+  let startTime = 0;
+  return {
+    begin(testsCount) {
+      startTime = Date.now();
+      return [
+        "",
+        style.dim(environmentDescription()),
+        `Running ${testsCount} tests. To reproduce these results later,`,
+        `run elm-test-rs with --seed ${flags.initialSeed} and --fuzz ${flags.fuzzRuns}`,
+        "",
+        "",
+      ].join("\n");
+    },
+    result(result) {
+      if (result.status != "fail") {
+        return "";
+      }
+      const location = testLocation(result.labels);
+      const header = style.red("✗ " + result.labels.join(" > ")) + (location ? "  " + style.dim(location) : "");
+      return [header, "", ...consoleFailureLines(result, style), ""].join("\n");
+    },
+    end(exitCode) {
+      const counts = statusCounts();
+      const lines = [];
+      consoleTreeLines(testsTree(testResults), 0, style, lines);
+      lines.push("");
+      if (counts.fail > 0) {
+        lines.push(style.red("TEST RUN FAILED"));
+      } else if (exitCode != 0 || counts.todo + counts.skip > 0) {
+        lines.push(style.yellow("TEST RUN INCOMPLETE"));
+      } else {
+        lines.push(style.green("TEST RUN PASSED"));
+      }
+      lines.push(
+        "",
+        `Duration: ${Date.now() - startTime} ms`,
+        `Passed:   ${counts.pass}`,
+        `Failed:   ${counts.fail}`
+      );
+      if (counts.todo > 0) {
+        lines.push(`Todo:     ${counts.todo}`);
+      }
+      if (counts.skip > 0) {
+        lines.push(`Skipped:  ${counts.skip}`);
+      }
+      if (counts.todo > 0) {
+        lines.push("", "Todo tests:", ...todoSummaries().map((todo) => `  ${style.yellow("◦")} ${todo}`));
+      }
+      lines.push("", "");
+      return lines.join("\n");
+    },
+  };
+}
+
+// Event printed by the json report of elm-test-runner,
+// with the duration of its test added if it is a testCompleted event without one,
+// and its heap measured with --heap-stats.
+// Events may be separated by commas, which are kept around the completed event.
+function completeJsonEvent(str) {
+  const [, before, json, after] = str.match(/^([\s,]*)([\s\S]*?)([\s,]*)$/);
+  let event;
+  try {
+    event = JSON.parse(json);
+  } catch (error) {
+    return str;
+  }
+  if (!event || event.event != "testCompleted" || !Array.isArray(event.labels)) {
+    return str;
+  }
+  const result =
+    testResultsByLabels.get(JSON.stringify(event.labels)) ||
+    testResultsByLabels.get(JSON.stringify([...event.labels].reverse()));
+  if (!result) {
+    return str;
+  }
+  if (event.duration === undefined) {
+    event.duration = String(Math.round(result.duration || 0));
+  }
+  if (result.heap) {
+    event.heap = result.heap;
+  }
+  return before + JSON.stringify(event) + after;
+}
+
+// Whether a report prints to the console for a person, the Elm console report or the tree one.
+function isConsoleReport(mode, js) {
+  return js ? js == "tree" : mode == "consoleColor" || mode == "consoleNoColor";
+}
+
+// Tree of tests results, following their describe hierarchy.
+// Each node keeps all the results under it, and its own tests.
+function testsTree(results) {
+  const newNode = (label) => ({ label: label, groups: new Map(), tests: [], results: [] });
+  const root = newNode("");
+  results.forEach((r) => {
+    let node = root;
+    root.results.push(r);
+    r.labels.slice(0, -1).forEach((label) => {
+      if (!node.groups.has(label)) {
+        node.groups.set(label, newNode(label));
+      }
+      node = node.groups.get(label);
+      node.results.push(r);
+    });
+    node.tests.push(r);
+  });
+  return root;
+}
+
+function consoleTreeLines(node, depth, style, lines) {
+  const indent = "  ".repeat(depth);
+  node.groups.forEach((group) => {
+    const passed = group.results.filter((r) => r.status == "pass").length;
+    const failed = group.results.some((r) => r.status == "fail");
+    const incomplete = group.results.some((r) => r.status == "todo" || r.status == "skip");
+    let mark = style.green("✓");
+    if (failed) {
+      mark = style.red("✗");
+    } else if (incomplete) {
+      mark = style.yellow("◦");
+    }
+    lines.push(`${indent}${mark} ${group.label} ${style.dim(`(${passed}/${group.results.length} passed)`)}`);
+    if (incomplete) {
+      consoleTreeLines(group, depth + 1, style, lines);
+    }
+  });
+  node.tests.forEach((r) => {
+    const label = r.labels[r.labels.length - 1] || "";
+    if (r.status == "fail") {
+      // Failures were already printed when their result was received.
+      return;
+    } else if (r.status == "todo") {
+      lines.push(`${indent}${style.yellow("◦ TODO: " + label)} ${r.todos.join(", ")}`);
+    } else if (r.status == "skip") {
+      // The reason is already visible if it is in the test label.
+      const reason = r.skipReason && !label.includes(" -- ") ? ` (${r.skipReason})` : "";
+      lines.push(`${indent}${style.yellow("◦ SKIPPED: " + label)}${reason}`);
+    } else if (depth == 0) {
+      lines.push(`${indent}${style.green("✓")} ${label}`);
+    }
+  });
+}
+
+// Details of a failed test: its distinct failures, the command to rerun it, and its logs.
+function consoleFailureLines(r, style) {
+  const lines = [];
+  const indented = (str) => str.split("\n").forEach((line) => lines.push(line ? "    " + line : ""));
+  const options = {
+    maxLength: style.maxFailureOutput,
+    pretty: true,
+    diff: true,
+    color: style.color,
+  };
+  distinctFailures(r.failures).forEach(({ failure, count }) => {
+    indented(failureMessage(failure, options) + occurrencesNote(count));
+    lines.push("");
+  });
+  lines.push("    " + style.dim("Rerun with: " + rerunCommand(r.labels)), "");
+  if (r.logs.length > 0) {
+    lines.push("    " + style.dim("with debug logs:"), "");
+    indented(r.logs.join("").trimEnd());
+    lines.push("");
+  }
+  return lines;
+}
+
+// One line description of the environment of the run, for the console.
+// The version of the compiler is left out, it is only retrieved for the reports including it.
+function environmentDescription() {
+  const { elmTestRs, runtime, platform, workers } = environment;
+  return `elm-test-rs ${elmTestRs}, ${runtime}, ${platform}, ${workers} workers`;
+}
+
+// Count tests of the current run by status.
+function statusCounts() {
+  const counts = { pass: 0, fail: 0, todo: 0, skip: 0 };
+  testResults.forEach((r) => (counts[r.status] += 1));
+  return counts;
+}
+
+// Message of a todo or skipped test, such as the reason of the skip.
+function skippedMessage(r) {
+  return r.status == "skip" ? r.skipReason : r.todos.join(", ");
+}
+
+// Todo tests of the current run, such as "Tests.Parser: lists > nested lists",
+// with their todo message when it is not already their label.
+function todoSummaries() {
+  return testResults
+    .filter((r) => r.status == "todo")
+    .map((r) => {
+      const label = r.labels[r.labels.length - 1];
+      const messages = r.todos.filter((todo) => todo != label);
+      const name = `${testModule(r.labels).module}: ${r.labels.slice(1).join(" > ") || label}`;
+      return messages.length > 0 ? `${name} (${messages.join(", ")})` : name;
+    });
+}
+
+// Test Anything Protocol, version 13.
+// https://testanything.org/tap-version-13-specification.html
+function tapReporter() {
+  let count = 0;
+  return {
+    begin(testsCount) {
+      count = 0;
+      return `TAP version 13\n1..${testsCount}\n`;
+    },
+    result(result) {
+      count += 1;
+      const name = tapEscape(result.labels.join(" > "));
+      if (result.status == "pass") {
+        return `ok ${count} - ${name} # time=${Math.round(result.duration || 0)}ms\n`;
+      } else if (result.status == "todo") {
+        return `not ok ${count} - ${name} # TODO ${tapEscape(result.todos.join(", "))}\n`;
+      } else if (result.status == "skip") {
+        return `ok ${count} - ${name} # SKIP ${tapEscape(result.skipReason)}\n`;
+      }
+      const message = failuresMessage(result.failures);
+      return [
+        `not ok ${count} - ${name}`,
+        "  ---",
+        "  message: |",
+        ...message.split("\n").map((line) => "    " + line),
+        `  seed: ${flags.initialSeed}`,
+        `  fuzzRuns: ${flags.fuzzRuns}`,
+        `  rerun: '${rerunCommand(result.labels).replace(/'/g, "''")}'`,
+        `  duration_ms: ${Math.round(result.duration || 0)}`,
+        "  ...",
+        "",
+      ].join("\n");
+    },
+    end(exitCode) {
+      const counts = statusCounts();
+      return [
+        `# seed ${flags.initialSeed}`,
+        `# pass ${counts.pass}`,
+        `# fail ${counts.fail}`,
+        `# todo ${counts.todo}`,
+        ...todoSummaries().map((todo) => `#   ${tapEscape(todo)}`),
+        "",
+      ].join("\n");
+    },
+  };
+}
+
+// Test names must not contain "#" or new lines, which have a meaning in TAP.
+function tapEscape(str) {
+  return str.replace(/\\/g, "\\\\").replace(/#/g, "\\#").replace(/\n/g, " ");
+}
+
+// Azure Pipelines logging commands.
+// https://docs.microsoft.com/en-us/azure/devops/pipelines/scripts/logging-commands
+function azureReporter() {
+  return {
+    begin(testsCount) {
+      return `Running ${testsCount} tests with --seed ${flags.initialSeed} and --fuzz ${flags.fuzzRuns}\n`;
+    },
+    result(result) {
+      const name = result.labels.join(" > ");
+      if (result.status == "pass") {
+        return `✓ ${name} (${Math.round(result.duration || 0)} ms)\n`;
+      } else if (result.status == "todo") {
+        return `##vso[task.logissue type=warning]${azureEscape(`TODO ${name}: ${result.todos.join(", ")}`)}\n`;
+      } else if (result.status == "skip") {
+        return `##vso[task.logissue type=warning]${azureEscape(`SKIPPED ${name}: ${result.skipReason}`)}\n`;
+      }
+      const message = failuresMessage(result.failures);
+      const position = testPosition(result.labels);
+      let properties = "";
+      if (position) {
+        properties += `;sourcepath=${position.file}`;
+      }
+      if (position && position.line) {
+        properties += `;linenumber=${position.line};columnnumber=${position.column}`;
+      }
+      return `##vso[task.logissue type=error${properties}]${azureEscape(`${name}\n${message}`)}\n`;
+    },
+    end(exitCode) {
+      const counts = statusCounts();
+      let status = "Succeeded";
+      if (counts.fail > 0) {
+        status = "Failed";
+      } else if (exitCode != 0) {
+        status = "SucceededWithIssues";
+      }
+      const message = `Passed: ${counts.pass}, failed: ${counts.fail}, todo: ${counts.todo}, seed: ${flags.initialSeed}`;
+      const todos = todoSummaries().map((todo) => `  ◦ ${todo}\n`);
+      const todoList = todos.length > 0 ? "Todo tests:\n" + todos.join("") : "";
+      return `${todoList}##vso[task.complete result=${status};]${azureEscape(message)}\n`;
+    },
+  };
+}
+
+// Logging commands messages must fit on one line.
+function azureEscape(str) {
+  return str.replace(/%/g, "%AZP25").replace(/\r/g, "%0D").replace(/\n/g, "%0A");
+}
+
+// Markdown summary, printed at the end of the run.
+// It is suitable for GitHub step summaries, with `>> $GITHUB_STEP_SUMMARY`.
+function markdownReporter() {
+  return {
+    begin(testsCount) {
+      return "";
+    },
+    result(result) {
+      return "";
+    },
+    end(exitCode) {
+      const counts = statusCounts();
+      const verdict = exitCode == 0 ? "✅ Tests passed" : "❌ Tests failed";
+      const lines = [
+        `### ${verdict}`,
+        "",
+        "| Total | Passed | Failed | Skipped | Todo |",
+        "| ----: | -----: | -----: | ------: | ---: |",
+        `| ${testResults.length} | ${counts.pass} | ${counts.fail} | ${counts.skip} | ${counts.todo} |`,
+        "",
+        `Seed: \`${flags.initialSeed}\`, fuzz runs: \`${flags.fuzzRuns}\``,
+        "",
+      ];
+      const slowest = testResults
+        .filter((r) => typeof r.duration == "number")
+        .sort((a, b) => b.duration - a.duration)
+        .slice(0, 5);
+      if (slowest.length > 0) {
+        lines.push("#### Slowest tests", "", "| Test | Duration |", "| :--- | -------: |");
+        slowest.forEach((r) => {
+          lines.push(`| ${markdownEscape(r.labels.join(" > "))} | ${Math.round(r.duration)} ms |`);
+        });
+        lines.push("");
+      }
+      const todos = todoSummaries();
+      if (todos.length > 0) {
+        lines.push("#### Todo tests", "", ...todos.map((todo) => `- ${markdownEscape(todo)}`), "");
+      }
+      const failed = testResults.filter((r) => r.status == "fail");
+      if (failed.length > 0) {
+        lines.push("#### Failures", "");
+        failed.forEach((r) => {
+          lines.push(
+            "<details>",
+            `<summary>${htmlEscape(r.labels.join(" > "))}</summary>`,
+            "",
+            "```",
+            failuresMessage(r.failures),
+            "```",
+            "",
+            "```sh",
+            rerunCommand(r.labels),
+            "```",
+            "",
+            "</details>",
+            ""
+          );
+        });
+      }
+      return lines.join("\n");
+    },
+  };
+}
+
+// Pipes would break markdown table cells.
+function markdownEscape(str) {
+  return htmlEscape(str).replace(/\|/g, "\\|").replace(/\n/g, " ");
+}
+
+function htmlEscape(str) {
+  return str
+    .replace(/&/g, "&amp;")
+    .replace(/</g, "&lt;")
+    .replace(/>/g, "&gt;")
+    .replace(/"/g, "&quot;");
+}
+
+// Self-contained HTML report, from templates/report.html, printed at the end of the run.
+function htmlReporter() {
+  return {
+    begin(testsCount) {
+      return "";
+    },
+    result(result) {
+      return "";
+    },
+    end(exitCode) {
+      const tests = testResults.map((r) => ({
+        labels: r.labels,
+        status: r.status,
+        duration: r.duration,
+        failures: r.failures.map((failure) => {
+          const data = failure.reason && failure.reason.data;
+          const values = data && typeof data === "object" && "expected" in data && "actual" in data;
+          return {
+            message: failureMessage(failure),
+            expected: values ? String(data.expected) : undefined,
+            actual: values ? String(data.actual) : undefined,
+          };
+        }),
+      }));
+      const report = { exitCode, seed: flags.initialSeed, fuzzRuns: flags.fuzzRuns, tests };
+      // Escape "</" so that the results cannot close the script tag.
+      const reportJson = JSON.stringify(report).replace(/<\//g, "<\\/");
+      return htmlTemplate.replace("{{ report }}", () => reportJson);
+    },
+  };
+}
+
+// Allure result files, one per test.
+// https://docs.qameta.io/allure/
+function allureReporter() {
+  let stopTimes = {};
+  return {
+    begin(testsCount) {
+      stopTimes = {};
+      return "";
+    },
+    result(result) {
+      stopTimes[result.id] = Date.now();
+      return "";
+    },
+    end(exitCode) {
+      return "";
+    },
+    files(exitCode) {
+      return testResults.map((r) => {
+        const fullName = r.labels.join(" > ");
+        const stop = stopTimes[r.id] || Date.now();
+        const duration = typeof r.duration == "number" ? Math.round(r.duration) : 0;
+        const labels = [
+          { name: "framework", value: "elm-test" },
+          { name: "language", value: "elm" },
+        ];
+        if (r.labels.length > 1) {
+          labels.push({ name: "suite", value: r.labels[0] });
+        }
+        if (r.labels.length > 2) {
+          labels.push({ name: "subSuite", value: r.labels.slice(1, -1).join(" > ") });
+        }
+        const status = { pass: "passed", fail: "failed", todo: "skipped", skip: "skipped" }[r.status];
+        const uuid = randomUuid();
+        const content = {
+          uuid,
+          historyId: stringHash(fullName),
+          name: r.labels[r.labels.length - 1] || "",
+          fullName,
+          status,
+          statusDetails: {
+            message: r.status == "fail" ? failuresMessage(r.failures) : skippedMessage(r),
+          },
+          stage: "finished",
+          start: stop - duration,
+          stop,
+          labels,
+          parameters: [
+            { name: "seed", value: String(flags.initialSeed) },
+            { name: "fuzz", value: String(flags.fuzzRuns) },
+          ],
+        };
+        return [`${uuid}-result.json`, JSON.stringify(content)];
+      });
+    },
+  };
+}
+
+// Random UUID (version 4), available in both Node and Deno.
+function randomUuid() {
+  return "xxxxxxxx-xxxx-4xxx-yxxx-xxxxxxxxxxxx".replace(/[xy]/g, (c) => {
+    const r = (Math.random() * 16) | 0;
+    return (c == "x" ? r : (r & 0x3) | 0x8).toString(16);
+  });
+}
+
+// Stable hexadecimal hash of a string (32 bits FNV-1a).
+function stringHash(str) {
+  let hash = 0x811c9dc5;
+  for (let i = 0; i < str.length; i++) {
+    hash ^= str.charCodeAt(i);
+    hash = Math.imul(hash, 0x01000193);
+  }
+  return (hash >>> 0).toString(16).padStart(8, "0");
+}
+
+// xUnit.net v2 XML, printed at the end of the run.
+// https://xunit.net/docs/format-xml-v2
+function xunitReporter() {
+  return {
+    begin(testsCount) {
+      return "";
+    },
+    result(result) {
+      return "";
+    },
+    end(exitCode) {
+      const seconds = (tests) =>
+        (tests.reduce((total, r) => total + (r.duration || 0), 0) / 1000).toFixed(3);
+      const countsAttributes = (tests) => {
+        const failed = tests.filter((r) => r.status == "fail").length;
+        const passed = tests.filter((r) => r.status == "pass").length;
+        return `total="${tests.length}" passed="${passed}" failed="${failed}" skipped="${tests.length - passed - failed}" time="${seconds(tests)}"`;
+      };
+
+      // One collection per test module.
+      const collections = new Map();
+      testResults.forEach((r) => {
+        const name = testModule(r.labels).module;
+        if (!collections.has(name)) {
+          collections.set(name, []);
+        }
+        collections.get(name).push(r);
+      });
+
+      const now = new Date().toISOString();
+      const lines = [
+        `<?xml version="1.0" encoding="utf-8"?>`,
+        `<assemblies timestamp="${now}">`,
+        `  <assembly name="elm-test-rs" test-framework="elm-test" environment="seed ${flags.initialSeed}, fuzz ${flags.fuzzRuns}" run-date="${now.slice(0, 10)}" run-time="${now.slice(11, 19)}" ${countsAttributes(testResults)} errors="0">`,
+      ];
+      collections.forEach((tests, name) => {
+        lines.push(`    <collection name="${xmlEscape(name)}" ${countsAttributes(tests)}>`);
+        tests.forEach((r) => {
+          const result = { pass: "Pass", fail: "Fail", todo: "Skip", skip: "Skip" }[r.status];
+          const time = ((r.duration || 0) / 1000).toFixed(3);
+          lines.push(
+            `      <test name="${xmlEscape(r.labels.join(" > "))}" type="${xmlEscape(name)}" method="${xmlEscape(r.labels.slice(1).join(" > "))}" time="${time}" result="${result}">`,
+            `        <traits><trait name="seed" value="${flags.initialSeed}" /></traits>`
+          );
+          if (r.status == "fail") {
+            const message = failuresMessage(r.failures);
+            lines.push(
+              `        <failure exception-type="elm-test"><message>${xmlEscape(message)}</message></failure>`
+            );
+          } else if (result == "Skip") {
+            lines.push(`        <reason>${xmlEscape(skippedMessage(r))}</reason>`);
+          }
+          lines.push(`      </test>`);
+        });
+        lines.push(`    </collection>`);
+      });
+      lines.push(`  </assembly>`, `</assemblies>`, "");
+      return lines.join("\n");
+    },
+  };
+}
+
+function xmlEscape(str) {
+  return htmlEscape(str).replace(/'/g, "&apos;");
+}
+
+// One character per test as results stream in, and failure details at the end.
+function dotReporter() {
+  let count = 0;
+  return {
+    begin(testsCount) {
+      count = 0;
+      return `Running ${testsCount} tests. To reproduce these results later,\nrun elm-test-rs with --seed ${flags.initialSeed} and --fuzz ${flags.fuzzRuns}\n\n`;
+    },
+    result(result) {
+      count += 1;
+      const char = { pass: ".", fail: "F", skip: "S", todo: "T" }[result.status];
+      // Wrap lines at 80 characters.
+      return count % 80 == 0 ? char + "\n" : char;
+    },
+    end(exitCode) {
+      const counts = statusCounts();
+      const lines = ["", ""];
+      testResults
+        .filter((r) => r.status == "fail")
+        .forEach((r, i) => {
+          lines.push(`${i + 1}) ${r.labels.join(" > ")}`, "");
+          lines.push(...failuresMessage(r.failures).split("\n").map((line) => "    " + line), "");
+          lines.push(`    Rerun with: ${rerunCommand(r.labels)}`, "");
+        });
+      const todos = todoSummaries();
+      if (todos.length > 0) {
+        lines.push("Todo tests:", ...todos.map((todo) => `  ◦ ${todo}`), "");
+      }
+      lines.push(
+        `Passed: ${counts.pass}, failed: ${counts.fail}, skipped: ${counts.skip}, todo: ${counts.todo}`,
+        ""
+      );
+      return lines.join("\n");
+    },
+  };
+}
+
+// One JSON object per line for each event of the run, printed as they happen.
+function ndjsonReporter() {
+  let startTime = 0;
+  const event = (obj) => JSON.stringify(obj) + "\n";
+  return {
+    begin(testsCount) {
+      startTime = Date.now();
+      return event({
+        event: "runStart",
+        testsCount,
+        initialSeed: flags.initialSeed,
+        fuzzRuns: flags.fuzzRuns,
+        environment,
+      });
+    },
+    result(result) {
+      return event({
+        event: "testCompleted",
+        labels: result.labels,
+        status: result.status,
+        duration: result.duration,
+        heap: result.heap,
+        failures: result.failures.map((failure) => ({
+          message: failureMessage(failure),
+          reason: failure.reason,
+        })),
+        todos: result.todos,
+        skipReason: result.skipReason,
+        logs: result.logs,
+      });
+    },
+    end(exitCode) {
+      const counts = statusCounts();
+      return event({
+        event: "runComplete",
+        exitCode,
+        passed: counts.pass,
+        failed: counts.fail,
+        skipped: counts.skip,
+        todo: counts.todo,
+        duration: Date.now() - startTime,
+      });
+    },
+  };
+}
+
+// Same events as the json report of the official node-test-runner (elm-test --report json),
+// including its numbers encoded as strings, so that existing tooling works unchanged.
+function jsonCompatReporter() {
+  let startTime = 0;
+  const event = (obj) => JSON.stringify(obj) + "\n";
+  return {
+    begin(testsCount) {
+      startTime = Date.now();
+      return event({
+        event: "runStart",
+        testCount: String(testsCount),
+        fuzzRuns: String(flags.fuzzRuns),
+        globs: flags.globs,
+        paths: flags.paths,
+        initialSeed: String(flags.initialSeed),
+      });
+    },
+    result(result) {
+      const failures =
+        result.status == "todo"
+          ? result.todos
+          : result.failures.map((failure) =>
+              typeof failure === "string"
+                ? failure
+                : {
+                    given: failure.given === undefined ? null : failure.given,
+                    message: failure.description || failure.message || "",
+                    reason: failure.reason || null,
+                  }
+            );
+      return event({
+        event: "testCompleted",
+        status: result.status == "skip" ? "todo" : result.status,
+        labels: result.labels,
+        failures,
+        duration: String(Math.round(result.duration || 0)),
+        heap: result.heap,
+      });
+    },
+    end(exitCode) {
+      const counts = statusCounts();
+      let autoFail = null;
+      if (exitCode != 0 && counts.fail == 0) {
+        autoFail = counts.todo > 0 ? "Test.todo was used" : "The test run is incomplete";
+      }
+      return event({
+        event: "runComplete",
+        passed: String(counts.pass),
+        failed: String(counts.fail),
+        duration: String(Date.now() - startTime),
+        autoFail,
+      });
+    },
+  };
+}
+
+// Module and file of a test, found from all its labels.
+// Tests of unknown modules are named by their top-level label.
+function testModule(labels) {
+  const module = moduleOf(labels);
+  return testModules[module] || { module: module || labels[0] || "", file: undefined, positions: {} };
+}
+
+// Command to rerun only the given test, with the same seed and fuzz runs.
+// The test is selected by its full label path, within the file defining it,
+// since the filter is matched against the labels of a test and its groups joined by spaces.
+function rerunCommand(labels) {
+  const quote = (str) => `"${str.replace(/["\\$`]/g, (c) => "\\" + c)}"`;
+  const { file } = testModule(labels);
+  const filter = labels.join(" ");
+  const command = `elm-test-rs --seed ${flags.initialSeed} --fuzz ${flags.fuzzRuns} --filter ${quote(filter)}`;
+  if (!file) {
+    return command;
+  }
+  return `${command} ${/^[\w./-]+$/.test(file) ? file : quote(file)}`;
+}
+
+// Position of a test in its file, such as { file: "tests/Tests.elm", line: 42, column: 7 }.
+// Positions are keyed by the labels paths of the file, such as ["suite","first"],
+// which are the innermost labels of the test when its outer groups come from other modules.
+// Tests with computed labels get the position of their closest group found in the file.
+function testPosition(labels) {
+  const { file, positions } = testModule(labels);
+  if (!file) {
+    return undefined;
+  }
+  let position;
+  for (let end = labels.length; end > 0 && !position; end--) {
+    for (let start = 0; start < end && !position; start++) {
+      position = positions[JSON.stringify(labels.slice(start, end))];
+    }
+  }
+  return { file: file, line: position && position.line, column: position && position.column };
+}
+
+// Location of a test, such as "tests/Tests.elm:42:7", in a format terminals hyperlink.
+function testLocation(labels) {
+  const position = testPosition(labels);
+  if (!position) {
+    return undefined;
+  }
+  return position.line ? `${position.file}:${position.line}:${position.column}` : position.file;
+}
+
+// JUnit XML, printed at the end of the run.
+// Test cases have their module as class name, the file and line defining them,
+// and their captured Debug.log calls as system-out.
+function junitReporter() {
+  let timestamp = "";
+  return {
+    begin(testsCount) {
+      timestamp = new Date().toISOString().slice(0, 19);
+      return "";
+    },
+    result(result) {
+      return "";
+    },
+    end(exitCode) {
+      return junitDocument("elm-test-rs", testResults, timestamp);
+    },
+  };
+}
+
+// JUnit XML files, one per test module.
+function junitPerModuleReporter() {
+  let timestamp = "";
+  return {
+    begin(testsCount) {
+      timestamp = new Date().toISOString().slice(0, 19);
+      return "";
+    },
+    result(result) {
+      return "";
+    },
+    end(exitCode) {
+      return "";
+    },
+    files(exitCode) {
+      const modules = new Map();
+      testResults.forEach((r) => {
+        const { module } = testModule(r.labels);
+        if (!modules.has(module)) {
+          modules.set(module, []);
+        }
+        modules.get(module).push(r);
+      });
+      return [...modules].map(([module, tests]) => [
+        `${module.replace(/[^\w.-]/g, "_")}.xml`,
+        junitDocument(module, tests, timestamp),
+      ]);
+    },
+  };
+}
+
+// JUnit XML document of some tests results.
+function junitDocument(name, tests, timestamp) {
+  const failed = tests.filter((r) => r.status == "fail").length;
+  const skipped = tests.filter((r) => r.status == "todo" || r.status == "skip").length;
+  const seconds = (ms) => ((ms || 0) / 1000).toFixed(3);
+  const time = seconds(tests.reduce((total, r) => total + (r.duration || 0), 0));
+  const counts = `tests="${tests.length}" failures="${failed}" errors="0" skipped="${skipped}" time="${time}"`;
+  const lines = [
+    `<?xml version="1.0" encoding="UTF-8"?>`,
+    `<testsuites name="${xmlEscape(name)}" ${counts}>`,
+    `  <testsuite name="${xmlEscape(name)}" package="${xmlEscape(name)}" timestamp="${timestamp}" hostname="${xmlEscape(hostname)}" ${counts}>`,
+    `    <properties>`,
+    `      <property name="seed" value="${flags.initialSeed}" />`,
+    `      <property name="fuzz" value="${flags.fuzzRuns}" />`,
+    ...Object.entries(environment).map(
+      ([key, value]) => `      <property name="${key}" value="${xmlEscape(String(value))}" />`
+    ),
+    `    </properties>`,
+  ];
+  tests.forEach((r) => {
+    const { module } = testModule(r.labels);
+    const position = testPosition(r.labels);
+    let fileAttribute = position ? ` file="${xmlEscape(position.file)}"` : "";
+    if (position && position.line) {
+      fileAttribute += ` line="${position.line}"`;
+    }
+    const testName = r.labels.length > 1 ? r.labels.slice(1).join(" > ") : r.labels.join("");
+    lines.push(
+      `    <testcase name="${xmlEscape(testName)}" classname="${xmlEscape(module)}"${fileAttribute} time="${seconds(r.duration)}">`
+    );
+    if (r.status == "fail") {
+      const message = failuresMessage(r.failures);
+      const summary = message.split("\n").find((line) => line.trim() != "") || "";
+      lines.push(
+        `      <failure message="${xmlEscape(summary)}" type="elm-test">${xmlEscape(message)}</failure>`
+      );
+    } else if (r.status != "pass") {
+      lines.push(`      <skipped message="${xmlEscape(skippedMessage(r))}" />`);
+    }
+    if (r.logs.length > 0) {
+      lines.push(`      <system-out>${xmlEscape(r.logs.join(""))}</system-out>`);
+    }
+    lines.push(`    </testcase>`);
+  });
+  lines.push(`  </testsuite>`, `</testsuites>`, "");
+  return lines.join("\n");
+}
+
+// SVG status badge with the tests counts, written at the end of the run.
+// Its color is green if all tests passed, red on failures and yellow otherwise.
+function badgeReporter() {
+  return {
+    begin(testsCount) {
+      return "";
+    },
+    result(result) {
+      return "";
+    },
+    end(exitCode) {
+      const counts = statusCounts();
+      let color = "#4c1";
+      let message = `${counts.pass} passed`;
+      if (counts.fail > 0) {
+        color = "#e05d44";
+        message += `, ${counts.fail} failed`;
+      } else if (exitCode != 0 || counts.todo + counts.skip > 0) {
+        color = "#dfb317";
+      }
+      if (counts.todo + counts.skip > 0) {
+        message += `, ${counts.todo + counts.skip} skipped`;
+      }
+      return badgeSvg("tests", message, color);
+    },
+  };
+}
+
+// Badge in the style of shields.io, with widths approximated from the number of characters.
+function badgeSvg(label, message, color) {
+  const width = (text) => text.length * 7 + 10;
+  const labelWidth = width(label);
+  const messageWidth = width(message);
+  const total = labelWidth + messageWidth;
+  return [
+    `<svg xmlns="http://www.w3.org/2000/svg" width="${total}" height="20" role="img" aria-label="${xmlEscape(`${label}: ${message}`)}">`,
+    `  <title>${xmlEscape(`${label}: ${message}`)}</title>`,
+    `  <linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient>`,
+    `  <clipPath id="r"><rect width="${total}" height="20" rx="3" fill="#fff"/></clipPath>`,
+    `  <g clip-path="url(#r)">`,
+    `    <rect width="${labelWidth}" height="20" fill="#555"/>`,
+    `    <rect x="${labelWidth}" width="${messageWidth}" height="20" fill="${color}"/>`,
+    `    <rect width="${total}" height="20" fill="url(#s)"/>`,
+    `  </g>`,
+    `  <g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">`,
+    `    <text x="${labelWidth / 2}" y="14">${xmlEscape(label)}</text>`,
+    `    <text x="${labelWidth + messageWidth / 2}" y="14">${xmlEscape(message)}</text>`,
+    `  </g>`,
+    `</svg>`,
+    "",
+  ].join("\n");
+}
@@ -1,13 +1,24 @@
 const { parentPort } = require("worker_threads");
 const { performance } = require("perf_hooks");
+const v8 = require("v8");
+const { format } = require("util");
 
 // From templates/polyfills.js
 {{ polyfills }}
 
+// Timers leaked by tests, from templates/timer_leaks.js with --detect-leaks
+{{ timerLeaks }}
+
 // Capture Debug.log from elm code
 let logs = [];
 console.elmlog = (str) => logs.push(str + "\n");
 
+// What the tests print on stdout, such as with console.log in ports,
+// is sent to the supervisor with log messages, apart from the reports.
+// Bun does not pipe the stdout of workers to the supervisor.
+console.log = (...args) => parentPort.postMessage({ type_: "log", text: format(...args) + "\n" });
+console.info = console.log;
+
 // Compiled by elm-test-rs from templates/Runner.elm
 const { Elm } = require("./Runner.elm.js");
 
@@ -15,33 +26,156 @@ const { Elm } = require("./Runner.elm.js");
 const flags = { initialSeed: {{ initialSeed }}, fuzzRuns: {{ fuzzRuns }}, filter: {{ filter }} };
 const app = Elm.Runner.init({ flags: flags });
 
-// Record the timing at which we received the last "runTest" message
+// Record the timing at which we received the last "dispatch" message
 let startTime;
 
+// With --heap-stats, the garbage collections of each test are recorded,
+// since the heap is at its peak just before them, to report the peak of the test.
+const heapStats = {{ heapStats }} && typeof v8.GCProfiler == "function";
+let heapProfiler;
+let heapBefore = 0;
+
+// Id of the test being run, until its result is sent
+let runningTest;
+
 // Communication from Supervisor to Elm runner via port
 parentPort.on("message", (msg) => {
   if (msg.type_ == "askTestsCount") {
     app.ports.askTestsCount.send();
-  } else if (msg.type_ == "runTest") {
+  } else if (msg.type_ == "dispatch") {
+    reportTestStarted(msg.id);
     startTime = performance.now();
-    app.ports.receiveRunTest.send(msg.id);
+    if (heapStats) {
+      heapBefore = heapUsed();
+      heapProfiler = new v8.GCProfiler();
+      heapProfiler.start();
+    }
+    if (globalThis.__elmTestLeaks) {
+      globalThis.__elmTestLeaks.start();
+    }
+    runningTest = msg.id;
+    try {
+      app.ports.receiveRunTest.send(msg.id);
+    } catch (error) {
+      reportTestError(error);
+    }
   } else {
     console.error("Invalid supervisor msg.type_:", msg.type_);
   }
 });
 
+// Tell the supervisor when a test starts, to time it,
+// with the heap used at that time, to report it if the test runs out of memory.
+function reportTestStarted(id) {
+  parentPort.postMessage({ type_: "testStarted", id: id, heapUsed: heapUsed() });
+}
+
 // Communication from Elm runner to Supervisor via port
 // Subscribe to outgoing Elm ports defined in templates/Runner.elm
 app.ports.sendResult.subscribe((msg) => {
+  runningTest = undefined;
   msg.type_ = "testResult";
   msg.duration = performance.now() - startTime;
+  if (heapStats) {
+    msg.heap = takeHeapPeak();
+  }
+  if (globalThis.__elmTestLeaks) {
+    msg.leaks = globalThis.__elmTestLeaks.take();
+  }
   msg.logs = logs;
+  if (globalThis.__elmTestCoverage) {
+    msg.coverage = takeCoverageHits();
+  }
+  if (globalThis.__elmTestSnapshotWrites) {
+    msg.snapshots = takeSnapshotWrites();
+  }
   parentPort.postMessage(msg);
   logs.length = 0;
 });
 app.ports.sendTestsCount.subscribe((msg) => {
   msg.type_ = "testsCount";
   msg.logs = logs;
+  // Labels and modules of the tests, by id, to name a test if it hangs or crashes the worker,
+  // and to know the module of each test.
+  msg.labels = globalThis.__elmTestLabels ? globalThis.__elmTestLabels() : [];
+  msg.modules = globalThis.__elmTestModules ? globalThis.__elmTestModules() : [];
+  // Modules exposing values, none of which is a test, such as misplaced helper modules.
+  msg.modulesWithoutTests = globalThis.__elmTestModulesWithoutTests
+    ? globalThis.__elmTestModulesWithoutTests()
+    : [];
   parentPort.postMessage(msg);
   logs.length = 0;
 });
+
+// Errors thrown by kernel code or ports during a test, even asynchronously,
+// are reported as a failure of that test, and the worker is kept alive for the next ones.
+// Errors outside of a test still crash the worker, which stops the run.
+process.on("uncaughtException", reportTestError);
+process.on("unhandledRejection", reportTestError);
+
+function reportTestError(error) {
+  if (runningTest == undefined) {
+    console.error(errorDetails(error));
+    process.exit(1);
+  }
+  const msg = {
+    type_: "testError",
+    id: runningTest,
+    error: errorDetails(error),
+    duration: performance.now() - startTime,
+    logs: logs,
+  };
+  runningTest = undefined;
+  if (heapProfiler) {
+    msg.heap = takeHeapPeak();
+  }
+  if (globalThis.__elmTestCoverage) {
+    msg.coverage = takeCoverageHits();
+  }
+  parentPort.postMessage(msg);
+  logs.length = 0;
+}
+
+// Heap used by the worker, reported when a test starts and for the heap peak of tests with --heap-stats.
+function heapUsed() {
+  return v8.getHeapStatistics ? v8.getHeapStatistics().used_heap_size : 0;
+}
+
+// Peak of the heap during the test, the largest of the heap used
+// when the test started, when it ended, and before each garbage collection.
+// The growth is the peak minus the heap used when the test started.
+function takeHeapPeak() {
+  const { statistics } = heapProfiler.stop();
+  heapProfiler = undefined;
+  const peak = statistics.reduce(
+    (peak, gc) => Math.max(peak, gc.beforeGC.heapStatistics.usedHeapSize),
+    Math.max(heapBefore, heapUsed())
+  );
+  return { peak: peak, growth: peak - heapBefore };
+}
+
+// Stack of an error, with the frames in Runner.elm.js translated into the Elm values they are in.
+function errorDetails(error) {
+  const stack = error && error.stack ? error.stack : String(error);
+  return globalThis.__elmTestStack ? globalThis.__elmTestStack(stack) : stack;
+}
+
+// Collect the snapshots to write since the last test result
+// (only present when the tests use ElmTestRs.Snapshot).
+function takeSnapshotWrites() {
+  const writes = globalThis.__elmTestSnapshotWrites;
+  globalThis.__elmTestSnapshotWrites = {};
+  return writes;
+}
+
+// Collect the coverage counters hit since the last test result
+// (only present when instrumented by the coverage subcommand).
+function takeCoverageHits() {
+  const counters = globalThis.__elmTestCoverage;
+  const hits = {};
+  counters.forEach((count, id) => {
+    if (count > 0) hits[id] = count;
+  });
+  counters.fill(0);
+  return hits;
+}
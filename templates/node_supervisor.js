@@ -3,68 +3,243 @@ process.chdir(__dirname);
 // From templates/polyfills.js
 {{ polyfills }}
 
+// From templates/test_results.js
+{{ testResults }}
+
+// From templates/js_reporters.js
+{{ jsReporters }}
+
 const { Worker } = require("worker_threads");
 const readline = require("readline");
+const net = require("net");
 const { performance } = require("perf_hooks");
 
 // Global variables
-let testsCount, todoTests;
-let reporter;
+let testsCount, todoTests, testsLabels, testsModules;
+let reporters = [];
 let runners = [];
 let working = false;
 let workersCount = {{ workersCount }};
 let startWorkCallback = function(){};
 const verbosity = {{ verbosity }};
+const persistent = {{ persistent }};
+const summaryFile = {{ summaryFile }};
+const coverageHitsFile = {{ coverageHits }};
+let coverageHits = {};
+const htmlTemplate = {{ htmlTemplate }};
+const testModules = {{ testModules }};
+// Version of the protocol with elm-test-rs, defined in src/protocol.rs
+const protocolVersion = {{ protocolVersion }};
+// Maximum duration of a test in milliseconds, or null
+const testTimeout = {{ testTimeout }};
+// Run the tests of each module in a fresh worker, with --isolate-modules
+const isolateModules = {{ isolateModules }};
+// Dispatch tests to runners one by one ("test"), or by whole modules ("module")
+const granularity = {{ granularity }};
+// Average durations of the tests in the recorded runs, by name, to dispatch the slowest first
+const testDurations = {{ testDurations }};
+// Maximum heap size of each worker in MB, or null
+const workerMaxMemory = {{ workerMaxMemory }};
+const hostname = require("os").hostname();
+// Versions and platform of the run, included in reports to make them self-describing
+const environment = {
+  ...{{ environment }},
+  runtime: typeof Bun == "undefined" ? `node ${process.version}` : `bun ${Bun.version}`,
+  platform: `${process.platform} ${process.arch}`,
+  workers: workersCount,
+};
+const reports = {{ reports }};
+let finishedReporters = 0;
+
+// Channel with elm-test-rs, a local socket whose port is the first argument.
+// It carries the messages of the protocol defined in src/protocol.rs, one JSON object per line,
+// so that what the tests print on stdout cannot be mixed up with them.
+const channel = net.connect(Number(process.argv[2]), "127.0.0.1");
+sendMessage({ type: "hello", protocol: protocolVersion, token: process.env.ELM_TEST_RS_CHANNEL_TOKEN });
+function sendMessage(msg) {
+  channel.write(JSON.stringify(msg) + "\n");
+}
+
+// Signal the end of the run to elm-test-rs, and exit unless persistent.
+function signalFinished(exitCode) {
+  if (persistent) {
+    sendMessage({ type: "runEnd", exitCode: exitCode });
+  } else {
+    channel.end(JSON.stringify({ type: "runEnd", exitCode: exitCode }) + "\n", () =>
+      process.exit(exitCode)
+    );
+  }
+}
 
-// Create a long lived reporter worker
+// Create long lived reporters, one per report
 const { Elm } = require("./Reporter.elm.js");
 const flags = {
   initialSeed: {{ initialSeed }},
   fuzzRuns: {{ fuzzRuns }},
-  mode: "{{ reporter }}",
   globs: {{ globs }},
   paths: {{ paths }},
 };
-reporter = Elm.Reporter.init({ flags: flags });
+reporters = reports.map(({ mode, js, options, output }) => {
+  const elm = Elm.Reporter.init({ flags: { ...flags, mode: mode } });
+  const reporter = { elm: elm, js: js ? jsReporters[js](options) : null, output: output };
+  reporter.toDirectory = Boolean(reporter.js && reporter.js.files);
+
+  // Pipe the Elm stdout port to the report, unless a JS reporter replaces it
+  elm.ports.stdout.subscribe(
+    (str) => reporter.js || writeReport(reporter, mode == "json" ? completeJsonEvent(str) : str)
+  );
+
+  // The run is finished when all reporters have finished
+  elm.ports.signalFinished.subscribe(({ exitCode }) => {
+    finishedReporters += 1;
+    if (finishedReporters == reporters.length) {
+      finishRun(exitCode);
+    }
+  });
+  return reporter;
+});
+
+// Send a report to elm-test-rs to print it, or append it to its output file
+function writeReport(reporter, str) {
+  if (reporter.toDirectory) {
+    return;
+  } else if (reporter.output) {
+    require("fs").appendFileSync(reporter.output, str);
+  } else {
+    clearProgress();
+    sendMessage({ type: "report", text: str });
+  }
+}
+
+// Live progress of the run on stderr, such as "423/1982 · 3 failed · 12s",
+// only when stderr is a terminal and no other report than the console one is printed to stdout,
+// since they might be mixed up. Updates are throttled to every 100 ms.
+const showProgress =
+  reports.every(({ mode, js, output }) => output || isConsoleReport(mode, js)) && process.stderr.isTTY;
+let progressStart = 0;
+let progressUpdate = 0;
+let progressShown = false;
+function updateProgress() {
+  const now = Date.now();
+  if (!showProgress || now - progressUpdate < 100) {
+    return;
+  }
+  progressUpdate = now;
+  progressShown = true;
+  writeProgress("\r\x1b[K" + progressLine(testsCount, progressStart));
+}
+function clearProgress() {
+  if (progressShown) {
+    progressShown = false;
+    writeProgress("\r\x1b[K");
+  }
+}
+function writeProgress(str) {
+  process.stderr.write(str);
+}
 
-// Pipe the Elm stdout port to stdout
-reporter.ports.stdout.subscribe((str) => process.stdout.write(str));
+// Write the files of a directory reporter into its output directory
+function writeReportFiles(reporter, files) {
+  const fs = require("fs");
+  fs.mkdirSync(reporter.output, { recursive: true });
+  files.forEach(([name, content]) =>
+    fs.writeFileSync(require("path").join(reporter.output, name), content)
+  );
+}
 
-// When the reporter has finished clean runners
-reporter.ports.signalFinished.subscribe(async ({ exitCode, testsCount }) => {
-  await Promise.all(runners.map((runner) => runner.terminate()));
+// When all reporters have finished clean runners.
+// A persistent supervisor keeps them for the next run, in case it uses the same runner.
+async function finishRun(exitCode) {
+  if (!persistent) {
+    await stopRunners();
+  }
   working = false;
+  clearProgress();
+  reporters.forEach((reporter) => {
+    if (reporter.toDirectory) {
+      writeReportFiles(reporter, reporter.js.files(exitCode));
+    } else if (reporter.js) {
+      writeReport(reporter, reporter.js.end(exitCode));
+    }
+  });
+  require("fs").writeFileSync(summaryFile, runSummary(exitCode));
   startWorkCallback();
+  if (coverageHitsFile) {
+    require("fs").writeFileSync(coverageHitsFile, JSON.stringify(coverageHits));
+  }
   if (verbosity >= 1) {
     console.warn("Running duration (since Node.js start):", Math.round(performance.now()), "ms\n");
   }
-  process.exit(exitCode);
-});
+  signalFinished(exitCode);
+}
 
-// When receiving a CLI message, start test workers
-// The message is a string containing "/path/to/node_runner.js"
-const rl = readline.createInterface({ input: process.stdin });
-rl.on("line", (runnerFile) => {
-  working ? registerWork(runnerFile) : startWork(runnerFile);
+// When receiving a runStart message from elm-test-rs, start test workers
+// The message contains the runner path, such as "/path/to/node_runner.js"
+const rl = readline.createInterface({ input: channel });
+rl.on("line", (line) => {
+  const msg = JSON.parse(line);
+  if (msg.type != "runStart") {
+    console.error("Invalid elm-test-rs msg.type:", msg.type);
+    return;
+  }
+  working
+    ? registerWork(msg.runner, msg.reuseWorkers)
+    : startWork(msg.runner, msg.reuseWorkers);
 });
 
-function registerWork(runnerFile) {
-  startWorkCallback = () => startWork(runnerFile);
+function registerWork(runnerFile, reuseWorkers) {
+  startWorkCallback = () => startWork(runnerFile, reuseWorkers);
 }
 
-function startWork(runnerFile) {
+function startWork(runnerFile, reuseWorkers) {
   startWorkCallback = function(){};
   working = true;
-  // Start first runner worker and prevent piped stdout and sdterr
-  runners[0] = new Worker(runnerFile, { stdout: true }); //, stderr: true });
+  // Keep the workers of the previous run if they loaded the same runner
+  if (reuseWorkers && runners.length > 0 && runners.every((runner) => !runner.finished)) {
+    runners[0].postMessage({ type_: "askTestsCount" });
+    return;
+  }
+  stopRunners();
+  // Start first runner worker
+  runners[0] = newRunner(runnerFile);
   runners[0].on("message", (msg) =>
     handleRunnerMsg(runners[0], runnerFile, msg)
   );
+  watchRunner(runners[0], runnerFile);
   runners[0].on("online", () =>
     runners[0].postMessage({ type_: "askTestsCount" })
   );
 }
 
+// Terminate all runners.
+async function stopRunners() {
+  const stopped = runners;
+  runners = [];
+  stopped.forEach((runner) => (runner.finished = true));
+  await Promise.all(stopped.map((runner) => runner.terminate()));
+}
+
+// Start a runner worker.
+// What the tests print on stdout, such as with console.log in ports,
+// is sent to elm-test-rs with log messages, apart from the reports.
+// The runner sends what it prints with console.log itself, since Bun does not pipe the stdout of workers,
+// and other writes to the stdout of the worker are piped here when supported.
+// With --worker-max-memory, the heap of the worker is limited,
+// and a worker exceeding it is stopped instead of the whole supervisor.
+function newRunner(runnerFile) {
+  const options = { stdout: true };
+  if (workerMaxMemory) {
+    options.resourceLimits = { maxOldGenerationSizeMb: workerMaxMemory };
+  }
+  const runner = new Worker(runnerFile, options);
+  runner.file = runnerFile;
+  runner.heapAtStart = 0;
+  if (runner.stdout) {
+    runner.stdout.on("data", (chunk) => sendMessage({ type: "log", text: String(chunk) }));
+  }
+  return runner;
+}
+
 // Handle a test result
 function handleRunnerMsg(runner, runnerFile, msg) {
   if (msg.type_ == "testsCount") {
@@ -74,50 +249,281 @@ function handleRunnerMsg(runner, runnerFile, msg) {
       console.warn("\n------------------------------------------------------\n");
     }
     setupWithTestsCount(runnerFile, msg);
+  } else if (msg.type_ == "testStarted") {
+    runner.heapAtStart = msg.heapUsed || 0;
+    startTest(runner);
   } else if (msg.type_ == "testResult") {
-    dispatchWork(runner, todoTests.pop());
-    reporter.ports.incomingResult.send(msg);
+    clearTimeout(runner.timeout);
+    if (msg.leaks && msg.leaks.length > 0) {
+      const test = runner.running || { id: msg.id, labels: [] };
+      sendMessage({ type: "log", text: leaksWarning(test, msg.leaks) });
+    }
+    delete msg.leaks;
+    runner.running = null;
+    dispatchWork(runner, nextTest(runner));
+    reportResult(msg);
+  } else if (msg.type_ == "log") {
+    sendMessage({ type: "log", text: msg.text });
+  } else if (msg.type_ == "testError") {
+    clearTimeout(runner.timeout);
+    const test = runner.running || { id: msg.id, labels: [] };
+    runner.running = null;
+    dispatchWork(runner, nextTest(runner));
+    const problem = `threw an uncaught error:\n\n${msg.error}`;
+    const result = crashedResult(test, problem, msg.duration);
+    result.logs = msg.logs;
+    result.heap = msg.heap;
+    result.coverage = msg.coverage;
+    reportResult(result);
   } else {
     console.error("Invalid runner msg.type_:", msg.type_);
   }
 }
 
+// Send a test result to all reporters
+function reportResult(msg) {
+  if (msg.coverage) {
+    mergeCoverage(msg.coverage);
+    delete msg.coverage;
+  }
+  if (msg.snapshots) {
+    for (const name in msg.snapshots) {
+      sendMessage({ type: "snapshot", name: name, contents: msg.snapshots[name] });
+    }
+    delete msg.snapshots;
+  }
+  const result = recordResult(msg);
+  sendMessage({
+    type: "testResult",
+    id: result.id,
+    labels: result.labels,
+    module: moduleOf(result.labels),
+    status: result.status,
+    duration: result.duration,
+  });
+  reporters.forEach((reporter) => {
+    if (reporter.js) {
+      writeReport(reporter, reporter.js.result(result));
+    }
+    reporter.elm.ports.incomingResult.send(msg);
+  });
+  updateProgress();
+}
+
 // Reset supervisor tests count and reporter
 // Start work on all runners
 function setupWithTestsCount(runnerFile, msg) {
   // Reset supervisor tests
   testsCount = msg.testsCount;
-  todoTests = Array(testsCount)
-    .fill(0)
-    .map((_, id) => id)
-    .reverse();
+  testsLabels = msg.labels || [];
+  testsModules = msg.modules || [];
+  recordTestsModules(testsLabels, testsModules);
+  const modulesWithoutTests = msg.modulesWithoutTests || [];
+  if (verbosity >= 1 && modulesWithoutTests.length > 0) {
+    sendMessage({ type: "log", text: modulesWithoutTestsWarning(modulesWithoutTests.sort()) });
+  }
+  todoTests = dispatchOrder(testsCount, testsLabels, testsModules, testDurations, isolateModules);
+
+  progressStart = Date.now();
+  progressUpdate = progressStart;
 
-  // Reset reporter
-  reporter.ports.restart.send(msg);
+  // Reset reports outputs, reporters, results and coverage
+  reporters.forEach((reporter) => {
+    if (reporter.output && !reporter.toDirectory) {
+      require("fs").writeFileSync(reporter.output, "");
+    }
+    reporter.elm.ports.restart.send(msg);
+  });
+  testResults = [];
+  testResultsByLabels = new Map();
+  coverageHits = {};
+  finishedReporters = 0;
+  runners.forEach((runner) => (runner.assigned = []));
+  reporters.forEach((reporter) => {
+    if (reporter.js) {
+      writeReport(reporter, reporter.js.begin(testsCount));
+    }
+  });
 
   // Send first runner job
   if (testsCount == 0) {
     console.error("No exposed values of type Test was found. Did you forget to expose them?");
     return;
   } else {
-    runners[0].postMessage({ type_: "runTest", id: todoTests.pop() });
+    dispatchWork(runners[0], nextTest(runners[0]));
   }
 
-  // Create and send work to all other workers.
+  // Create and send work to all other workers, or to the ones kept from the previous run.
   let max_workers = Math.min(workersCount, testsCount);
   for (let i = 1; i < max_workers; i++) {
-    let runner = new Worker(runnerFile); //, { stdout: true, stderr: true });
+    if (runners[i]) {
+      dispatchWork(runners[i], nextTest(runners[i]));
+      continue;
+    }
+    let runner = newRunner(runnerFile);
     runners[i] = runner;
     runner.on("message", (msg) =>
       handleRunnerMsg(runner, runnerFile, msg)
     );
-    runner.on("online", () => dispatchWork(runner, todoTests.pop()));
+    watchRunner(runner, runnerFile);
+    runner.on("online", () => dispatchWork(runner, nextTest(runner)));
+  }
+}
+
+// Time the test started in a runner, and stop the run if it exceeds the timeout.
+function startTest(runner) {
+  runner.startTime = performance.now();
+  if (testTimeout) {
+    runner.timeout = setTimeout(
+      () => abortRun(runner, `did not finish within ${testTimeout / 1000}s`),
+      testTimeout
+    );
   }
 }
 
-// Ask runner to run some test.
+// Replace a runner when it crashes, failing the test it was running.
+function watchRunner(runner, runnerFile) {
+  runner.on("error", (error) => {
+    if (error && error.code == "ERR_WORKER_OUT_OF_MEMORY") {
+      replaceRunner(runner, runnerFile, outOfMemoryProblem(runner));
+      return;
+    }
+    const details = error && error.stack ? error.stack : error;
+    replaceRunner(runner, runnerFile, `crashed the worker:\n\n${details}`);
+  });
+  runner.on("exit", (code) => {
+    replaceRunner(runner, runnerFile, `stopped the worker with exit code ${code}`);
+  });
+}
+
+// Problem of a worker stopped for exceeding its memory limit,
+// with the heap it used when the test started.
+// The worker cannot be measured while it runs out of memory,
+// so the rest of the limit is what the test allocated, without its actual peak.
+function outOfMemoryProblem(runner) {
+  const atStart = (runner.heapAtStart / 1024 / 1024).toFixed(1);
+  return [
+    `ran out of memory, exceeding the ${workerMaxMemory} MB limit of the worker set with --worker-max-memory.`,
+    `The heap of the worker was at ${atStart} MB when the test started, the test allocated the rest.`,
+  ].join("\n");
+}
+
+// With --isolate-modules, replace a runner by a fresh one before it runs a test of another module,
+// so that the global state of kernel code or ports does not leak between modules.
+function recycleRunner(runner, testId) {
+  runner.finished = true;
+  runner.terminate();
+  const runnerFile = runner.file;
+  const replacement = newRunner(runnerFile);
+  runners[runners.indexOf(runner)] = replacement;
+  replacement.assigned = runner.assigned;
+  replacement.on("message", (msg) =>
+    handleRunnerMsg(replacement, runnerFile, msg)
+  );
+  watchRunner(replacement, runnerFile);
+  replacement.on("online", () => dispatchWork(replacement, testId));
+}
+
+// Record a failure for the test running in a crashed runner,
+// and continue with the remaining tests in a new runner.
+// A crash while setting up the tests stops the run, since every test would crash the same way.
+function replaceRunner(runner, runnerFile, problem) {
+  if (!working || runner.finished) {
+    return;
+  }
+  const test = runner.running;
+  if (!test) {
+    abortRun(runner, problem);
+    return;
+  }
+  runner.finished = true;
+  clearTimeout(runner.timeout);
+  runner.terminate();
+  const replacement = newRunner(runnerFile);
+  runners[runners.indexOf(runner)] = replacement;
+  replacement.assigned = runner.assigned;
+  replacement.on("message", (msg) =>
+    handleRunnerMsg(replacement, runnerFile, msg)
+  );
+  watchRunner(replacement, runnerFile);
+  replacement.on("online", () => dispatchWork(replacement, nextTest(replacement)));
+  reportResult(crashedResult(test, problem, performance.now() - runner.startTime));
+}
+
+// Stop the run because of a problem in a runner, such as a hanging test.
+async function abortRun(runner, problem) {
+  if (!working || runner.finished) {
+    return;
+  }
+  runners.forEach((r) => {
+    r.finished = true;
+    clearTimeout(r.timeout);
+  });
+  const test = runner.running;
+  const name = test ? testName(test) : "The setup of the tests";
+  clearProgress();
+  sendMessage({ type: "fatal", message: `${name} ${problem}` });
+  await Promise.all(runners.map((r) => r.terminate()));
+  working = false;
+  signalFinished(1);
+  startWorkCallback();
+}
+
+// Next test to run in a runner.
+// With --granularity module, a runner is assigned all the remaining tests of a module at once,
+// and runs them before taking another module.
+function nextTest(runner) {
+  if (granularity != "module") {
+    return todoTests.pop();
+  }
+  if (!runner.assigned || runner.assigned.length == 0) {
+    const first = todoTests.pop();
+    if (first == undefined) {
+      return stealTests(runner);
+    }
+    const module = testsModules[first];
+    const sameModule = (id) => testsModules[id] == module;
+    runner.assigned = [first, ...todoTests.filter(sameModule).reverse()];
+    todoTests = todoTests.filter((id) => !sameModule(id));
+  }
+  return runner.assigned.shift();
+}
+
+// With --granularity module, an idle runner takes the second half of the tests
+// assigned to the runner with the most tests left, once there is no module left to take.
+function stealTests(runner) {
+  let victim = null;
+  for (const other of runners) {
+    const left = other.assigned ? other.assigned.length : 0;
+    if (other !== runner && left > (victim ? victim.assigned.length : 0)) {
+      victim = other;
+    }
+  }
+  if (!victim) {
+    return undefined;
+  }
+  runner.assigned = victim.assigned.splice(Math.floor(victim.assigned.length / 2));
+  return runner.assigned.shift();
+}
+
+// Ask runner to run some test, and record it to name it if it hangs or crashes the runner.
 function dispatchWork(runner, testId) {
   if (testId != undefined) {
-    runner.postMessage({ type_: "runTest", id: testId });
+    const module = testsModules[testId];
+    if (isolateModules && runner.module != undefined && runner.module != module) {
+      recycleRunner(runner, testId);
+      return;
+    }
+    runner.module = module;
+    runner.running = { id: testId, labels: testsLabels[testId] || [] };
+    runner.startTime = performance.now();
+    runner.postMessage({ type_: "dispatch", id: testId });
+  }
+}
+
+// Accumulate the coverage counters hit by one test.
+function mergeCoverage(hits) {
+  for (const id in hits) {
+    coverageHits[id] = (coverageHits[id] || 0) + hits[id];
   }
 }
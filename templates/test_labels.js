@@ -0,0 +1,48 @@
+// Top-level tests selected by the `check` function of Runner.elm, in order, with their module.
+const __elmTestTopLevel = [];
+
+// Modules whose exposed values were given to the `check` function, to tell which have no tests.
+const __elmTestCheckedModules = new Set();
+
+// Labels and module of the tests, indexed by their id, to tell which test is running
+// when it hangs or crashes, and which module it comes from.
+// It mirrors Test.Runner.fromTest, numbering the tests in order,
+// leaving out the skipped ones, and only keeping the ones under Test.only if there are some.
+function __elmTestIds() {
+  const all = [];
+  const only = [];
+  function walk(test, module, labels, inOnly) {
+    const variant = __elmTestVariant(test);
+    if (variant == "Labeled") {
+      walk(test.b, module, [...labels, test.a], inOnly);
+    } else if (variant == "Batch") {
+      for (let list = test.a; list.b; list = list.b) {
+        walk(list.a, module, labels, inOnly);
+      }
+    } else if (variant == "Only") {
+      walk(test.a, module, labels, true);
+    } else if (variant != "Skipped") {
+      (inOnly ? only : all).push({ labels: labels, module: module });
+    }
+  }
+  __elmTestTopLevel.forEach(({ module, test }) => walk(test, module, [], false));
+  return only.length > 0 ? only : all;
+}
+
+// Name of the variant of a Test value, such as "Labeled".
+// Variants are recognized by the name of their constructor, which is prefixed by "ElmTestVariant__"
+// in newer versions of elm-explorations/test. Compiling with --optimize replaces names by numbers,
+// so labels and tags could not be found and tests would silently be mixed up.
+function __elmTestVariant(test) {
+  if (typeof test.$ != "string") {
+    throw new Error(
+      "Runner.elm.js seems compiled with --optimize, but the labels and tags of tests can only be found without it."
+    );
+  }
+  return test.$.replace("ElmTestVariant__", "");
+}
+
+globalThis.__elmTestLabels = () => __elmTestIds().map((test) => test.labels);
+globalThis.__elmTestModules = () => __elmTestIds().map((test) => test.module);
+globalThis.__elmTestModulesWithoutTests = () =>
+  [...__elmTestCheckedModules].filter((module) => !__elmTestTopLevel.some((top) => top.module == module));
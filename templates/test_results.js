@@ -0,0 +1,442 @@
+// Results of all tests of the current run, in the order they are received.
+let testResults = [];
+
+// Results of the current run by their labels, outermost label first.
+let testResultsByLabels = new Map();
+
+// Modules of the tests of the current run, by their labels, outermost label first.
+let testsModulesByLabels = new Map();
+
+// Decode a test result sent by a runner.
+// The result is encoded by elm-test-runner, so we stay lenient about its shape.
+function decodeResult(msg) {
+  const result = msg.result || {};
+  const failures = Array.isArray(result.failures) ? result.failures : [];
+  const todos = Array.isArray(result.todos) ? result.todos : [];
+  let status;
+  if (["pass", "fail", "todo", "skip"].includes(result.status)) {
+    status = result.status;
+  } else if (failures.length > 0) {
+    status = "fail";
+  } else if (todos.length > 0) {
+    status = "todo";
+  } else {
+    status = "pass";
+  }
+  // Labels are sent with the innermost label first.
+  const labels = Array.isArray(result.labels) ? [...result.labels].reverse() : [];
+  return {
+    id: msg.id,
+    labels: labels,
+    status: status,
+    skipReason: status == "skip" ? skipReason(labels) : undefined,
+    duration: msg.duration,
+    // Peak of the heap during the test and its growth over the heap used when it started,
+    // in bytes, with --heap-stats.
+    heap: msg.heap,
+    // Debug.log calls of the test, captured by the runner, each ending with a newline.
+    logs: Array.isArray(msg.logs) ? msg.logs : [],
+    failures: failures,
+    todos: todos,
+  };
+}
+
+// Reason of a skipped test, given by convention at the end of one of its labels
+// after " -- ", such as `skip <| test "dates -- waiting for time zones support"`.
+function skipReason(labels) {
+  const label = [...labels].reverse().find((l) => l.includes(" -- "));
+  return label ? label.slice(label.indexOf(" -- ") + 4).trim() : "";
+}
+
+// Human readable message of a failure.
+// When the failure reason contains an expected and actual value,
+// it is presented like in the console reporter.
+// With the maxLength option, the middle of longer values is elided.
+// With the pretty option, values too long for a single line are broken across lines.
+// With the diff option, equality failures only show the differing parts of both values,
+// highlighted if the color option is also set.
+// Those are found in the full values, and only the unchanged parts are then elided.
+function failureMessage(failure, options) {
+  if (typeof failure === "string") {
+    return failure;
+  }
+  const lines = [];
+  if (failure.given) {
+    lines.push("Given " + failure.given, "");
+  }
+  const description = failure.description || failure.message || "";
+  const data = failure.reason && failure.reason.data;
+  if (data && typeof data === "object" && "expected" in data && "actual" in data) {
+    let actual = String(data.actual);
+    let expected = String(data.expected);
+    const equality = failure.reason.type == "Equality" || description == "Expect.equal";
+    const diff = options && options.diff && equality;
+    if (options && options.maxLength && !diff) {
+      actual = elideMiddle(actual, options.maxLength);
+      expected = elideMiddle(expected, options.maxLength);
+    }
+    if (options && options.pretty) {
+      actual = prettyValue(actual);
+      expected = prettyValue(expected);
+    }
+    if (diff) {
+      [actual, expected] = equalityDiff(actual, expected, options.color, options.maxLength);
+    }
+    lines.push(actual, "╷", "│ " + (data.comparison || description), "╵", expected);
+  } else if (description) {
+    lines.push(description);
+  } else {
+    lines.push(typeof data === "string" ? data : JSON.stringify(failure));
+  }
+  return lines.join("\n");
+}
+
+// Failures of a test, with failures of identical messages collapsed into one,
+// such as the same counterexample found by many fuzz runs.
+function distinctFailures(failures) {
+  const distinct = new Map();
+  failures.forEach((failure) => {
+    const message = failureMessage(failure);
+    if (distinct.has(message)) {
+      distinct.get(message).count += 1;
+    } else {
+      distinct.set(message, { failure: failure, message: message, count: 1 });
+    }
+  });
+  return [...distinct.values()];
+}
+
+// Note following a failure that occurred several times.
+function occurrencesNote(count) {
+  return count > 1 ? `\n\n(this failure occurred ${count} times)` : "";
+}
+
+// Messages of all the distinct failures of a test.
+function failuresMessage(failures) {
+  return distinctFailures(failures)
+    .map(({ message, count }) => message + occurrencesNote(count))
+    .join("\n\n");
+}
+
+// Number of unchanged characters kept around the differences of an equality diff.
+const diffContext = 30;
+
+// Actual and expected values of an equality failure, with their common prefix and suffix
+// trimmed, and the segments differing between them highlighted.
+// Strings are compared character by character, and other values word by word,
+// so that only the changed fields of records or elements of lists are highlighted.
+// Unchanged segments longer than maxLength, if given, have their middle elided.
+function equalityDiff(actual, expected, color, maxLength) {
+  const isString = (str) => str.length > 1 && str.startsWith('"') && str.endsWith('"');
+  const tokenize = (str) =>
+    isString(actual) && isString(expected) ? [...str] : str.match(/\w+|\s+|[^\w\s]/g) || [];
+  const actualTokens = tokenize(actual);
+  const expectedTokens = tokenize(expected);
+
+  // Common prefix and suffix.
+  let start = 0;
+  while (
+    start < actualTokens.length &&
+    start < expectedTokens.length &&
+    actualTokens[start] === expectedTokens[start]
+  ) {
+    start++;
+  }
+  let end = 0;
+  while (
+    end < actualTokens.length - start &&
+    end < expectedTokens.length - start &&
+    actualTokens[actualTokens.length - 1 - end] === expectedTokens[expectedTokens.length - 1 - end]
+  ) {
+    end++;
+  }
+  const prefix = actualTokens.slice(0, start).join("");
+  const suffix = actualTokens.slice(actualTokens.length - end).join("");
+  const [actualChanged, expectedChanged] = changedTokens(
+    actualTokens.slice(start, actualTokens.length - end),
+    expectedTokens.slice(start, expectedTokens.length - end)
+  );
+
+  const highlight = (code) => (str) => (color ? `\x1b[${code}m${str}\x1b[0m` : str);
+  // Trimmed parts of multiline values are cut at line boundaries.
+  const cutStart = prefix.length - diffContext;
+  const lineStart = prefix.lastIndexOf("\n", cutStart);
+  let before = prefix;
+  if (cutStart > 0) {
+    before = "…" + prefix.slice(lineStart >= 0 ? lineStart : cutStart);
+  }
+  const lineEnd = suffix.indexOf("\n", diffContext);
+  let after = suffix;
+  if (suffix.length > diffContext) {
+    after = lineEnd >= 0 ? suffix.slice(0, lineEnd) + "\n…" : suffix.slice(0, diffContext) + "…";
+  }
+  const render = (segments, paint) => {
+    const unchanged = (text) => (maxLength ? elideMiddle(text, maxLength) : text);
+    const middle = segments
+      .map(([text, changed]) => (changed ? paint(text) : unchanged(text)))
+      .join("");
+    return before + middle + after;
+  };
+  return [render(actualChanged, highlight("1;31")), render(expectedChanged, highlight("1;32"))];
+}
+
+// Segments of both lists of tokens, marked as changed if they are not
+// in their longest common subsequence.
+// Big values are entirely marked as changed to avoid a quadratic cost.
+function changedTokens(actual, expected) {
+  if (actual.length * expected.length > 250000) {
+    return [
+      [[actual.join(""), true]],
+      [[expected.join(""), true]],
+    ];
+  }
+  // lcs[i][j] is the length of the longest common subsequence of actual[i..] and expected[j..].
+  const lcs = Array.from({ length: actual.length + 1 }, () => new Array(expected.length + 1).fill(0));
+  for (let i = actual.length - 1; i >= 0; i--) {
+    for (let j = expected.length - 1; j >= 0; j--) {
+      lcs[i][j] =
+        actual[i] === expected[j] ? lcs[i + 1][j + 1] + 1 : Math.max(lcs[i + 1][j], lcs[i][j + 1]);
+    }
+  }
+  const actualSegments = [];
+  const expectedSegments = [];
+  const push = (segments, text, changed) => {
+    const last = segments[segments.length - 1];
+    if (last && last[1] === changed) {
+      last[0] += text;
+    } else {
+      segments.push([text, changed]);
+    }
+  };
+  let i = 0;
+  let j = 0;
+  while (i < actual.length || j < expected.length) {
+    if (i < actual.length && j < expected.length && actual[i] === expected[j]) {
+      push(actualSegments, actual[i++], false);
+      push(expectedSegments, expected[j++], false);
+    } else if (j >= expected.length || (i < actual.length && lcs[i + 1][j] >= lcs[i][j + 1])) {
+      push(actualSegments, actual[i++], true);
+    } else {
+      push(expectedSegments, expected[j++], true);
+    }
+  }
+  return [actualSegments, expectedSegments];
+}
+
+// Value with its middle elided if it is longer than maxLength characters.
+function elideMiddle(value, maxLength) {
+  if (value.length <= maxLength) {
+    return value;
+  }
+  const kept = Math.ceil(maxLength / 2);
+  const elided = value.length - 2 * kept;
+  return (
+    value.slice(0, kept) +
+    ` … ${elided} characters elided, increase --max-failure-output to see the full value … ` +
+    value.slice(value.length - kept)
+  );
+}
+
+// Width above which values are broken across lines in failure messages.
+const prettyWidth = 80;
+
+// Value in the Elm debug representation, such as `{ a = [1,2], b = Just "c" }`,
+// with records, lists and tuples too long for a line broken into one item per line,
+// in the style of elm-format.
+function prettyValue(value) {
+  if (value.length <= prettyWidth) {
+    return value;
+  }
+  const [parts] = valueParts(value, 0, "");
+  return renderValueParts(parts, "").replace(/ +\n/g, "\n");
+}
+
+// Parse a value into its text parts and its groups, delimited by brackets,
+// of comma separated items. Parsing stops at the given closing bracket.
+function valueParts(str, index, close) {
+  const closing = { "{": "}", "[": "]", "(": ")" };
+  const parts = [];
+  let text = "";
+  while (index < str.length) {
+    const c = str[index];
+    if (c == '"' || c == "'") {
+      // Skip string and char literals.
+      let end = index + 1;
+      while (end < str.length && str[end] != c) {
+        end += str[end] == "\\" ? 2 : 1;
+      }
+      text += str.slice(index, end + 1);
+      index = end + 1;
+    } else if (c in closing) {
+      const start = index;
+      const items = [];
+      index++;
+      while (true) {
+        const [item, next] = valueParts(str, index, closing[c]);
+        items.push(trimItem(item));
+        index = next;
+        if (str[index] != ",") {
+          break;
+        }
+        index++;
+      }
+      index++;
+      parts.push(text, { open: c, close: closing[c], items: items, raw: str.slice(start, index) });
+      text = "";
+    } else if (close && (c == close || c == ",")) {
+      break;
+    } else {
+      text += c;
+      index++;
+    }
+  }
+  parts.push(text);
+  return [parts.filter((part) => part !== ""), index];
+}
+
+function trimItem(parts) {
+  if (typeof parts[0] == "string") {
+    parts[0] = parts[0].trimStart();
+  }
+  if (typeof parts[parts.length - 1] == "string") {
+    parts[parts.length - 1] = parts[parts.length - 1].trimEnd();
+  }
+  return parts.filter((part) => part !== "");
+}
+
+function renderValueParts(parts, indent) {
+  return parts
+    .map((part, i) => {
+      if (typeof part == "string") {
+        return part;
+      }
+      const empty = part.items.length == 1 && part.items[0].length == 0;
+      if (empty || indent.length + part.raw.length <= prettyWidth) {
+        return part.raw;
+      }
+      // A group after some text, like a record field value, starts on its own line.
+      const groupIndent = i == 0 ? indent : indent + "  ";
+      const items = part.items.map(
+        (item, k) => (k == 0 ? part.open + " " : groupIndent + ", ") + renderValueParts(item, groupIndent + "  ")
+      );
+      return (i == 0 ? "" : "\n" + groupIndent) + items.join("\n") + "\n" + groupIndent + part.close;
+    })
+    .join("");
+}
+
+// Record the modules of the tests, sent by a runner with the tests count along with their labels.
+function recordTestsModules(labels, modules) {
+  testsModulesByLabels = new Map(labels.map((l, id) => [JSON.stringify(l), modules[id]]));
+}
+
+// Name of the module defining the test with these labels, outermost label first, if it is known.
+function moduleOf(labels) {
+  return testsModulesByLabels.get(JSON.stringify(labels));
+}
+
+// Record a test result sent by a runner.
+function recordResult(msg) {
+  const result = decodeResult(msg);
+  testResults.push(result);
+  testResultsByLabels.set(JSON.stringify(result.labels), result);
+  return result;
+}
+
+// Name of a test sent with a "testStarted" message by a runner, outermost label first.
+function testName(test) {
+  return test.labels.length > 0
+    ? `The test ${test.labels.join(" > ")}`
+    : `The test with id ${test.id}`;
+}
+
+// Warning for the timers a test left active, with --detect-leaks.
+function leaksWarning(test, leaks) {
+  return `Warning: ${testName(test)} left active timers (${leaks.join(", ")}), which may interfere with the next tests.\n`;
+}
+
+// Warning for the tests modules exposing values, none of which is a test.
+function modulesWithoutTestsWarning(modules) {
+  return `No tests found in these modules, since none of the values they expose is a Test:\n    ${modules.join("\n    ")}\n`;
+}
+
+// Failed result of a test that crashed its runner or threw an uncaught error,
+// encoded like the ones of elm-test-runner,
+// so that it is reported as any other failure.
+function crashedResult(test, problem, duration) {
+  const description = `${testName(test)} ${problem}`;
+  return {
+    type_: "testResult",
+    id: test.id,
+    duration: duration,
+    logs: [],
+    result: {
+      status: "fail",
+      labels: [...test.labels].reverse(),
+      duration: duration,
+      logs: [],
+      todos: [],
+      failures: [
+        {
+          given: null,
+          description: description,
+          reason: { type: "Custom", data: description },
+        },
+      ],
+      coverageReports: [],
+    },
+  };
+}
+
+// Progress of the run, such as "423/1982 · 3 failed · 12s".
+function progressLine(testsCount, startTime) {
+  const failed = testResults.filter((r) => r.status == "fail").length;
+  const seconds = Math.round((Date.now() - startTime) / 1000);
+  return `${testResults.length}/${testsCount} · ${failed} failed · ${seconds}s`;
+}
+
+// Summary of the run, read by elm-test-rs after the supervisor exits.
+function runSummary(exitCode) {
+  return JSON.stringify({
+    exitCode: exitCode,
+    seed: flags.initialSeed,
+    fuzzRuns: flags.fuzzRuns,
+    environment: environment,
+    tests: testResults.map(({ labels, status, duration, heap }) => ({
+      labels,
+      module: moduleOf(labels),
+      status,
+      duration,
+      heap,
+    })),
+  });
+}
+
+// Tests to dispatch, as a stack of ids with the next one last.
+// The slowest tests of the recorded runs come first, so that they do not end up
+// running at the end on a few workers while the other ones are idle.
+// Tests without recorded duration, such as new ones, come before all of them,
+// and tests keep their order otherwise.
+// With --isolate-modules, the tests of a module stay together, and modules are sorted
+// by the total duration of their tests, since a worker is replaced when it changes module.
+function dispatchOrder(count, labels, modules, durations, isolateModules) {
+  const ids = Array(count).fill(0).map((_, id) => id);
+  const duration = ids.map((id) => {
+    const name = (labels[id] || []).join(" > ");
+    return name in durations ? durations[name] : Infinity;
+  });
+  const moduleDuration = new Map();
+  const moduleFirst = new Map();
+  ids.forEach((id) => {
+    const module = modules[id];
+    moduleDuration.set(module, (moduleDuration.get(module) || 0) + duration[id]);
+    if (!moduleFirst.has(module)) {
+      moduleFirst.set(module, id);
+    }
+  });
+  const byModule = (a, b) =>
+    moduleDuration.get(modules[b]) - moduleDuration.get(modules[a]) ||
+    moduleFirst.get(modules[a]) - moduleFirst.get(modules[b]);
+  return ids
+    .sort((a, b) => (isolateModules && byModule(a, b)) || duration[b] - duration[a] || a - b)
+    .reverse();
+}
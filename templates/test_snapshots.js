@@ -0,0 +1,66 @@
+// Snapshots of tests/__snapshots__/ by name, embedded by elm-test-rs.
+const __elmTestSnapshots = {{ snapshots }};
+const __elmTestUpdateSnapshots = {{ updateSnapshots }};
+
+// Snapshots to write, sent by the runner with the result of the test.
+globalThis.__elmTestSnapshotWrites = {};
+
+// Compare a string with its snapshot, returning null if they are the same,
+// or the failure message with the lines that differ otherwise.
+// Missing snapshots, or all of them with --update-snapshots, are recorded to be written.
+function __elmTestCheckSnapshot(name, actual) {
+  const expected = __elmTestSnapshots[name];
+  if (__elmTestUpdateSnapshots || expected === undefined) {
+    __elmTestSnapshots[name] = actual;
+    globalThis.__elmTestSnapshotWrites[name] = actual;
+    return null;
+  } else if (expected === actual) {
+    return null;
+  }
+  return [
+    `The snapshot tests/__snapshots__/${name} does not match, with - for the snapshot and + for the actual string:`,
+    __elmTestLinesDiff(expected, actual),
+    "Run the tests with --update-snapshots if the change is expected.",
+  ].join("\n\n");
+}
+
+// Lines of a diff between two strings, keeping two unchanged lines around the changes.
+function __elmTestLinesDiff(expected, actual) {
+  const a = expected.split("\n");
+  const b = actual.split("\n");
+  let lines = [];
+  if (a.length * b.length > 1000000) {
+    // Too big for a diff, all lines are shown.
+    lines = [...a.map((line) => "- " + line), ...b.map((line) => "+ " + line)];
+  } else {
+    // Lengths of the longest common subsequences of lines of the ends of a and b
+    const lcs = Array.from({ length: a.length + 1 }, () => new Array(b.length + 1).fill(0));
+    for (let i = a.length - 1; i >= 0; i--) {
+      for (let j = b.length - 1; j >= 0; j--) {
+        lcs[i][j] = a[i] === b[j] ? lcs[i + 1][j + 1] + 1 : Math.max(lcs[i + 1][j], lcs[i][j + 1]);
+      }
+    }
+    let i = 0;
+    let j = 0;
+    while (i < a.length || j < b.length) {
+      if (i < a.length && j < b.length && a[i] === b[j]) {
+        lines.push("  " + a[i++]);
+        j++;
+      } else if (i < a.length && (j == b.length || lcs[i + 1][j] >= lcs[i][j + 1])) {
+        lines.push("- " + a[i++]);
+      } else {
+        lines.push("+ " + b[j++]);
+      }
+    }
+  }
+  const changed = (line) => !line.startsWith("  ");
+  const kept = [];
+  lines.forEach((line, k) => {
+    if (lines.slice(Math.max(0, k - 2), k + 3).some(changed)) {
+      kept.push(line);
+    } else if (kept[kept.length - 1] != "  ...") {
+      kept.push("  ...");
+    }
+  });
+  return kept.join("\n");
+}
@@ -0,0 +1,38 @@
+// Tags given by convention in labels, such as `test "[slow] big sort"`.
+// Tests are only kept if they or one of their groups have one of the included tags,
+// when there are some, and removed if they or one of their groups have an excluded tag.
+const __elmTestTags = { included: {{ includedTags }}, excluded: {{ excludedTags }} };
+
+// Remove the tests not selected by their tags from a Test value.
+// Variants of the Test type are recognized by the name of their constructor, see __elmTestVariant.
+function __elmTestFilterTags(test, tagged) {
+  const variant = __elmTestVariant(test);
+  const tag = test.$;
+  const emptyBatch = () => ({ ...test, $: tag.replace(variant, "Batch"), a: { $: "[]" } });
+  if (variant == "Labeled") {
+    const tags = [...test.a.matchAll(/\[([^\]\s]+)\]/g)].map((match) => match[1]);
+    if (tags.some((t) => __elmTestTags.excluded.includes(t))) {
+      return emptyBatch();
+    }
+    const isTagged = tagged || tags.some((t) => __elmTestTags.included.includes(t));
+    return { ...test, b: __elmTestFilterTags(test.b, isTagged) };
+  } else if (variant == "Batch") {
+    return { ...test, a: __elmTestMapList(test.a, (t) => __elmTestFilterTags(t, tagged)) };
+  } else if (variant == "Only" || variant == "Skipped") {
+    return { ...test, a: __elmTestFilterTags(test.a, tagged) };
+  }
+  return tagged || __elmTestTags.included.length == 0 ? test : emptyBatch();
+}
+
+// Map the elements of an Elm list, keeping its structure.
+function __elmTestMapList(list, f) {
+  const nodes = [];
+  let result = list;
+  for (; result.b; result = result.b) {
+    nodes.push(result);
+  }
+  for (let i = nodes.length - 1; i >= 0; i--) {
+    result = { ...nodes[i], a: f(nodes[i].a), b: result };
+  }
+  return result;
+}
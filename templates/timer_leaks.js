@@ -0,0 +1,49 @@
+// With --detect-leaks, timers created by a test and still active when it ends are reported,
+// since they keep running during the next tests of the worker and may interfere with them.
+// The global timer functions are wrapped to record the active timers created during a test.
+globalThis.__elmTestLeaks = (function () {
+  const { setTimeout, setInterval, clearTimeout, clearInterval } = globalThis;
+  const active = new Map();
+  let recording = false;
+  globalThis.setTimeout = function (callback, ...rest) {
+    let handle;
+    const wrapped =
+      typeof callback == "function"
+        ? function (...args) {
+            active.delete(handle);
+            return callback.apply(this, args);
+          }
+        : callback;
+    handle = setTimeout(wrapped, ...rest);
+    if (recording) active.set(handle, "setTimeout");
+    return handle;
+  };
+  globalThis.setInterval = function (...args) {
+    const handle = setInterval(...args);
+    if (recording) active.set(handle, "setInterval");
+    return handle;
+  };
+  globalThis.clearTimeout = function (handle) {
+    active.delete(handle);
+    return clearTimeout(handle);
+  };
+  globalThis.clearInterval = function (handle) {
+    active.delete(handle);
+    return clearInterval(handle);
+  };
+  return {
+    // Start recording the timers of a test.
+    start() {
+      active.clear();
+      recording = true;
+    },
+    // Timers of the test still active, counted by kind, such as ["2 setInterval"].
+    take() {
+      recording = false;
+      const counts = {};
+      active.forEach((kind) => (counts[kind] = (counts[kind] || 0) + 1));
+      active.clear();
+      return Object.keys(counts).map((kind) => `${counts[kind]} ${kind}`);
+    },
+  };
+})();